@@ -2,6 +2,7 @@ use bevy::prelude::*;
 use bevy::window::WindowMode;
 
 use bevy::render::camera::Camera;
+use bevy_tmx::parallax::ParallaxCamera;
 use bevy_tmx::TmxPlugin;
 
 fn main() {
@@ -25,10 +26,13 @@ fn main() {
 
 fn spawn_scene(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands.spawn_scene(asset_server.load("sticker/sandbox.tmx"));
-    commands.spawn().insert_bundle(OrthographicCameraBundle {
-        transform: Transform::from_xyz(600.0, -600.0, 50.0),
-        ..OrthographicCameraBundle::new_2d()
-    });
+    commands
+        .spawn()
+        .insert_bundle(OrthographicCameraBundle {
+            transform: Transform::from_xyz(600.0, -600.0, 50.0),
+            ..OrthographicCameraBundle::new_2d()
+        })
+        .insert(ParallaxCamera);
 }
 
 fn circle_camera(time: Res<Time>, mut camera: Query<(&mut Transform, &Camera)>) {
@@ -0,0 +1,144 @@
+use bevy_asset::{Assets, Handle};
+use bevy_core::Time;
+use bevy_ecs::{
+    reflect::ReflectComponent,
+    system::{Query, Res},
+};
+use bevy_reflect::{Reflect, TypeUuid};
+use bevy_render::mesh::Mesh;
+use bevy_sprite::ColorMaterial;
+
+/// The frames of an animated tile, shared by all entities that display the same animated tile.
+#[derive(Debug, Default, Clone, TypeUuid)]
+#[uuid = "c2bb9d1e-6f1b-4c55-a1a4-4c8d1f3e7a21"]
+pub struct TileAnimation {
+    /// The frames of the animation, in order of appearance.
+    pub frames: Vec<AnimationFrame>,
+}
+
+/// A single frame of a `TileAnimation`.
+#[derive(Debug, Default, Clone)]
+pub struct AnimationFrame {
+    /// The mesh to display during this frame.
+    pub mesh: Handle<Mesh>,
+    /// The material to display during this frame.
+    pub material: Handle<ColorMaterial>,
+    /// Duration of the frame in seconds.
+    pub duration: f32,
+}
+
+/// Component for tiles that are animated.
+/// Animated tiles can't be baked into the mesh of their layer, so every animated tile is spawned as a separate entity.
+/// Keep this in mind when using large amounts of animated tiles, since each of them is drawn separately.
+#[derive(Debug, Default, Clone, TypeUuid, Reflect)]
+#[reflect(Component)]
+#[uuid = "5a0d3b52-9b1e-4d0c-8f0e-2a43f5d6c7b8"]
+pub struct AnimatedTile {
+    /// The animation to play.
+    pub animation: Handle<TileAnimation>,
+    /// Index of the frame that is currently displayed.
+    pub frame: usize,
+    /// Time in seconds since the start of the current animation cycle.
+    pub elapsed: f32,
+}
+
+/// System that advances `AnimatedTile`s and swaps their mesh and material when the frame changes.
+pub fn tile_animation_system(
+    time: Res<Time>,
+    animations: Res<Assets<TileAnimation>>,
+    mut tiles: Query<(
+        &mut AnimatedTile,
+        &mut Handle<Mesh>,
+        &mut Handle<ColorMaterial>,
+    )>,
+) {
+    for (mut tile, mut mesh, mut material) in tiles.iter_mut() {
+        let animation = if let Some(animation) = animations.get(&tile.animation) {
+            animation
+        } else {
+            continue;
+        };
+
+        if tile.advance(animation, time.delta_seconds()) {
+            *mesh = animation.frames[tile.frame].mesh.clone();
+            *material = animation.frames[tile.frame].material.clone();
+        }
+    }
+}
+
+impl AnimatedTile {
+    /// Advance the animation by `seconds`, returning whether the frame changed.
+    pub fn advance(&mut self, animation: &TileAnimation, seconds: f32) -> bool {
+        let frames = &animation.frames;
+        let total: f32 = frames.iter().map(|f| f.duration).sum();
+        if total <= 0.0 {
+            return false;
+        }
+
+        self.elapsed = (self.elapsed + seconds) % total;
+
+        let mut remaining = self.elapsed;
+        let frame = frames
+            .iter()
+            .position(|f| {
+                if remaining < f.duration {
+                    true
+                } else {
+                    remaining -= f.duration;
+                    false
+                }
+            })
+            .unwrap_or(0);
+
+        let changed = frame != self.frame;
+        self.frame = frame;
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{app, spawn};
+    use crate::TmxPlugin;
+
+    #[test]
+    fn animated_tiles_cycle_through_their_frames() {
+        let mut app = app(TmxPlugin::default());
+        spawn(&mut app, "water.tmx");
+
+        // only the animated tile is spawned as a separate entity
+        let mut query = app
+            .world
+            .query::<(&AnimatedTile, &Handle<Mesh>, &Handle<ColorMaterial>)>();
+        let tiles: Vec<_> = query
+            .iter(&app.world)
+            .map(|(tile, mesh, material)| (tile.clone(), mesh.clone(), material.clone()))
+            .collect();
+        assert_eq!(tiles.len(), 1);
+        let (mut tile, mesh, material) = tiles.into_iter().next().unwrap();
+
+        let animations = app.world.get_resource::<Assets<TileAnimation>>().unwrap();
+        let animation = animations.get(&tile.animation).unwrap();
+        let durations: Vec<f32> = animation.frames.iter().map(|f| f.duration).collect();
+        assert_eq!(durations, vec![0.125, 0.25, 0.5]);
+        // every frame shows a different tile
+        assert_ne!(animation.frames[0].mesh, animation.frames[1].mesh);
+        assert_ne!(animation.frames[1].mesh, animation.frames[2].mesh);
+        // the system keeps the entity on the frame it's at
+        assert_eq!(mesh, animation.frames[tile.frame].mesh);
+        assert_eq!(material, animation.frames[tile.frame].material);
+
+        tile.elapsed = 0.0;
+        tile.frame = 0;
+        let mut advance = |seconds| {
+            let changed = tile.advance(animation, seconds);
+            (changed, tile.frame)
+        };
+        assert_eq!(advance(0.0625), (false, 0));
+        assert_eq!(advance(0.125), (true, 1));
+        assert_eq!(advance(0.25), (true, 2));
+        // after the last frame the animation starts over
+        assert_eq!(advance(0.5), (true, 0));
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "zip")]
+use std::{io::Cursor, sync::Mutex};
+
+use anyhow::*;
+
+/// A zip archive that a map is loaded from, kept in memory. Files are only decompressed when they are read.
+/// The archive acts as a directory at its own path, so the file `map.tmx` in `levels/pack.zip`
+///  is found at `levels/pack.zip/map.tmx`. Paths that lead out of the archive are loaded as usual.
+pub(crate) struct Archive {
+    path: PathBuf,
+    /// The index in the archive of every file, by its path within the archive.
+    files: HashMap<PathBuf, usize>,
+    #[cfg(feature = "zip")]
+    zip: Mutex<zip::ZipArchive<Cursor<Vec<u8>>>>,
+}
+
+impl Archive {
+    /// Open the zip archive at `path` with the contents `bytes`.
+    #[cfg(feature = "zip")]
+    pub fn from_zip(path: PathBuf, bytes: Vec<u8>) -> Result<Self> {
+        let mut zip = zip::ZipArchive::new(Cursor::new(bytes))
+            .with_context(|| format!("invalid zip archive `{}`", path.display()))?;
+        let mut files = HashMap::new();
+        for i in 0..zip.len() {
+            let file = zip.by_index_raw(i)?;
+            if file.is_dir() {
+                continue;
+            }
+            let name = file
+                .enclosed_name()
+                .ok_or_else(|| anyhow!("invalid file name `{}` in archive", file.name()))?
+                .to_path_buf();
+            files.insert(name, i);
+        }
+
+        Ok(Archive {
+            path,
+            files,
+            zip: Mutex::new(zip),
+        })
+    }
+
+    /// Whether the resolved file `path` points into the archive.
+    pub fn contains(&self, path: &Path) -> bool {
+        path.starts_with(&self.path)
+    }
+
+    /// Read the file at the resolved `path` from the archive.
+    pub fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        let index = path
+            .strip_prefix(&self.path)
+            .ok()
+            .and_then(|name| self.files.get(name))
+            .ok_or_else(|| anyhow!("file `{}` not found in archive", path.display()))?;
+        self.read_index(*index)
+            .with_context(|| format!("failed to read `{}` from archive", path.display()))
+    }
+
+    #[cfg(feature = "zip")]
+    fn read_index(&self, index: usize) -> Result<Vec<u8>> {
+        use std::io::Read;
+
+        // the size in the header of the file is not trusted, so the contents are not preallocated
+        let mut contents = Vec::new();
+        self.zip
+            .lock()
+            .unwrap()
+            .by_index(index)?
+            .read_to_end(&mut contents)?;
+        Ok(contents)
+    }
+
+    #[cfg(not(feature = "zip"))]
+    fn read_index(&self, _index: usize) -> Result<Vec<u8>> {
+        bail!("reading archives requires the `zip` feature")
+    }
+
+    /// The resolved path of the map in the archive. Archives that contain more than one map are ambiguous.
+    #[cfg(feature = "zip")]
+    pub fn map(&self) -> Result<PathBuf> {
+        let mut maps = self.files.keys().filter(|path| {
+            matches!(
+                path.extension().and_then(|extension| extension.to_str()),
+                Some("tmx") | Some("tmj")
+            )
+        });
+        match (maps.next(), maps.next()) {
+            (Some(map), None) => Ok(self.path.join(map)),
+            (None, _) => bail!("no map found in archive `{}`", self.path.display()),
+            (Some(_), Some(_)) => bail!(
+                "archive `{}` contains more than one map",
+                self.path.display()
+            ),
+        }
+    }
+}
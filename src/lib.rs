@@ -12,30 +12,76 @@
 //! - Object layers with support for custom object processing
 //! - Image layers with support for custom image layer processing
 //! - Parallax rendering
-//!  
-//! # Todo
-//! - Infinite map support
-//! - All render orders other than `RightDown`
+//! - Repeating image layers
+//! - Animated tiles
+//! - Infinite maps
+//! - Maps exported in the json format (.tmj, or .json with `TmxPlugin::json_extension`)
+//! - Worlds of multiple maps (.world)
+//! - Writing loaded maps back to .tmx
+//!
+//! # Cargo features
+//! - `render` (default): the `TmxPlugin` and everything needed to load and draw maps as bevy scenes,
+//!   like the scene builder, parallax and decoding images into textures.
+//! - `plugin`: the previous name of `render`, which enables it.
+//! - `image`: decoding of embedded images and reading image sizes from image files. Enabled by `render`.
+//! - `zstd`: support for zstd compressed tile layer data.
+//! - `zip`: loading maps from zip archives that contain the map together with its tilesets, templates and images.
+//! - `webp`, `bmp`, `tiff`: decoding of images in these formats. PNG, JPEG and GIF images are always supported.
+//!
+//! Without default features, only the `tmx` module is available, which parses maps with nothing but `bevy_math` from bevy.
 //!
 //! # Overview
 //! Using bevy_tmx is supposed to be really simple, just add the `TmxPlugin` to your `App` and load a scene.
 //! If you need to add custom functionality to the entities loaded from the `.tmx` file, you can customize the `TmxLoader` to do so during load time.
+//!
+//! # Object properties
+//! Objects with a float or int property named `z` are spawned with that z coordinate instead of the depth
+//!  that follows from the draw order of their layer. The value is used as is, so it's not multiplied with the z scale
+//!  given to `TmxPlugin::scale`. Like all z coordinates of the map, it's relative to the map root, and such objects aren't sorted by y.
 
 #![deny(missing_docs)]
 
+/// Report a problem that doesn't prevent the map from loading. Warnings are logged with `bevy_log`,
+///  without the `render` feature they go to the `log` crate, so any logger set up by the application shows them.
+macro_rules! warn {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "render")]
+        bevy_log::warn!($($arg)*);
+        #[cfg(not(feature = "render"))]
+        log::warn!($($arg)*);
+    }};
+}
+
+/// Components and system for animated tiles
+#[cfg(feature = "render")]
+pub mod animation;
+mod archive;
 /// Component and system for parallax rendering
-#[cfg(feature = "plugin")]
+#[cfg(feature = "render")]
 pub mod parallax;
-#[cfg(feature = "plugin")]
+#[cfg(feature = "render")]
 mod plugin;
-#[cfg(feature = "plugin")]
+/// Component and system for repeating image layers
+#[cfg(feature = "render")]
+pub mod repeat;
+#[cfg(feature = "render")]
 mod scene;
+#[cfg(test)]
+mod test_util;
 /// Representation of the .tmx file format
 pub mod tmx;
+/// Component and system for depth sorting by y coordinate
+#[cfg(feature = "render")]
+pub mod ysort;
 
-#[cfg(not(feature = "plugin"))]
+/// Component and system for spawning the maps of a world
+#[cfg(feature = "render")]
+pub mod world;
+
+#[cfg(not(feature = "render"))]
 mod loader {
-    use super::tmx::Map;
+    use super::archive::Archive;
+    use super::tmx::{Map, Tileset};
     use anyhow::*;
     use std::path::{Component, Path, PathBuf};
     use std::sync::Arc;
@@ -43,6 +89,7 @@ mod loader {
     #[derive(Clone)]
     pub(crate) struct TmxLoadContext<'a> {
         relative: Arc<Path>,
+        archive: Option<Arc<Archive>>,
         lifetime: &'a (),
     }
 
@@ -51,9 +98,40 @@ mod loader {
             &'p self,
             path: impl AsRef<Path> + Send + 'p,
         ) -> Result<Vec<u8>> {
-            Ok(std::fs::read(self.file_path(path))?)
+            let file_path = self.file_path(path);
+            if let Some(archive) = self.archive.as_ref().filter(|a| a.contains(&file_path)) {
+                return archive.read(&file_path);
+            }
+            Ok(std::fs::read(file_path)?)
+        }
+
+        pub fn add_dependency(&self, _path: impl AsRef<Path>) {}
+
+        pub fn is_archived(&self, path: impl AsRef<Path>) -> bool {
+            let path = self.file_path(path);
+            matches!(&self.archive, Some(archive) if archive.contains(&path))
         }
 
+        #[cfg(feature = "zip")]
+        pub fn archive(self, archive: Arc<Archive>, map: &Path) -> Self {
+            Self {
+                relative: Arc::from(map.parent().unwrap_or(map)),
+                archive: Some(archive),
+                ..self
+            }
+        }
+
+        pub async fn cached_tileset(
+            &self,
+            _path: &Path,
+            _hash: u64,
+            _first_gid: u32,
+        ) -> Option<Tileset> {
+            None
+        }
+
+        pub async fn cache_tileset(&self, _path: PathBuf, _hash: u64, _tileset: &Tileset) {}
+
         pub fn file_path(&self, path: impl AsRef<Path>) -> PathBuf {
             let mut joined = PathBuf::new();
             for c in self.relative.join(path.as_ref()).components() {
@@ -70,6 +148,22 @@ mod loader {
             joined
         }
 
+        pub fn filesystem(base_dir: &Path) -> TmxLoadContext<'static> {
+            TmxLoadContext {
+                relative: Arc::from(base_dir),
+                archive: None,
+                lifetime: &(),
+            }
+        }
+
+        pub fn root(&self) -> Self {
+            Self {
+                relative: Arc::from(Path::new("")),
+                archive: self.archive.clone(),
+                lifetime: self.lifetime,
+            }
+        }
+
         pub fn file_directory(&self, path: impl AsRef<Path>) -> Self {
             Self {
                 relative: if let Some(parent) = path.as_ref().parent() {
@@ -77,34 +171,66 @@ mod loader {
                 } else {
                     self.relative.clone()
                 },
+                archive: self.archive.clone(),
                 lifetime: self.lifetime,
             }
         }
     }
 
     /// Load tmx::Map from a file.
+    /// With the `zip` feature, the file can also be a zip archive with a single map and the files it uses.
     pub async fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Map> {
         let path = path.as_ref();
-        let context = ();
-        let context = if let Some(parent) = path.parent() {
-            TmxLoadContext {
-                relative: Arc::from(parent.to_path_buf()),
-                lifetime: &context,
-            }
-        } else {
-            TmxLoadContext {
-                relative: Path::new(".").to_path_buf().into(),
-                lifetime: &context,
-            }
-        };
+        let context = TmxLoadContext::filesystem(path.parent().unwrap_or_else(|| Path::new(".")));
 
-        let reader = xml::EventReader::new(std::fs::File::open(path)?);
+        #[cfg(feature = "zip")]
+        if path.extension().and_then(|e| e.to_str()) == Some("zip") {
+            let archive = Arc::new(Archive::from_zip(path.to_path_buf(), std::fs::read(path)?)?);
+            let map = archive.map()?;
+            let bytes = archive.read(&map)?;
+            let context = context.archive(archive.clone(), &map);
+            return if crate::tmx::is_json_file(&map) {
+                Map::load_from_json(context, &bytes).await
+            } else {
+                Map::load_from_xml_reader(context, xml::EventReader::new(bytes.as_slice())).await
+            };
+        }
 
-        Ok(Map::load_from_xml_reader(context, reader).await?)
+        if crate::tmx::is_json_file(path) {
+            Ok(Map::load_from_json(context, std::fs::read(path)?.as_slice()).await?)
+        } else {
+            let reader = xml::EventReader::new(std::fs::File::open(path)?);
+            Ok(Map::load_from_xml_reader(context, reader).await?)
+        }
     }
 }
 
-#[cfg(not(feature = "plugin"))]
+#[cfg(not(feature = "render"))]
 pub use loader::*;
-#[cfg(feature = "plugin")]
+#[cfg(feature = "render")]
 pub use plugin::*;
+
+#[cfg(all(test, not(feature = "render")))]
+mod tests {
+    use super::*;
+    use crate::test_util::fixtures;
+    use crate::tmx::Layer;
+
+    #[test]
+    fn maps_are_parsed_without_the_render_feature() {
+        let map = futures_lite::future::block_on(load_from_file(fixtures().join("equivalent.tmx")))
+            .unwrap();
+
+        let names: Vec<&str> = map.layers.iter().map(Layer::name).collect();
+        assert_eq!(names, vec!["ground", "objects", "decoration"]);
+        assert!(map.layer_by_name("flowers").is_some());
+        assert_eq!(map.properties["music"].as_str(), Some("forest.ogg"));
+        assert_eq!(map.properties["level"].as_int(), Some(3));
+
+        let door = map.object_by_name("door").unwrap();
+        assert_eq!(door.properties["locked"].as_bool(), Some(true));
+        assert_eq!(door.properties["key"].as_str(), Some("gold"));
+        // the size of external tileset images is known without decoding them
+        assert_eq!(map.get_tileset(1).unwrap().tile_count(), 4);
+    }
+}
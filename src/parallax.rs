@@ -1,7 +1,7 @@
 use bevy_ecs::{reflect::ReflectComponent, system::Query};
-use bevy_math::{vec3, Vec2};
+use bevy_math::{vec3, Rect, Vec2};
 use bevy_reflect::{Reflect, TypeUuid};
-use bevy_render::camera::Camera;
+use bevy_render::camera::{Camera, OrthographicProjection};
 use bevy_transform::components::{GlobalTransform, Transform};
 
 /// Component for sprites that should render according to a parallax relative to the camera.
@@ -17,22 +17,73 @@ pub struct Parallax {
     ///  while factors above 1.0 will make them appear closer.
     /// You can think of the camera as being on factor 1.0.
     pub factor: Vec2,
+    /// The camera position at which the entity is rendered at its source transform.
+    pub origin: Vec2,
     /// The source transform to use when performing parallax transformation.
     pub transform: Transform,
+    /// The area covered by the entity at its source transform, like the pixel rect of the map.
+    /// When set, the translation is clamped so that the area keeps covering the camera viewport,
+    ///  which stops backgrounds from sliding past the edge of the map.
+    #[reflect(ignore)]
+    pub bounds: Option<Rect<f32>>,
 }
 
+/// Marker component for the camera that drives the parallax of `Parallax` entities.
+/// When multiple cameras exist, for example a world camera and a UI camera, tag the gameplay camera with this
+///  marker so the parallax is computed relative to it:
+/// ```ignore
+/// commands
+///     .spawn_bundle(OrthographicCameraBundle::new_2d())
+///     .insert(ParallaxCamera);
+/// ```
+/// If no camera is tagged, the first camera found is used.
+#[derive(Debug, Default, Clone, Copy, TypeUuid, Reflect)]
+#[reflect(Component)]
+#[uuid = "5c2a8e1f-9b7d-4e36-a0c4-d18f6b3e2a97"]
+pub struct ParallaxCamera;
+
 /// System that updates the `Transform` component of `Parallax` entities.
+/// Zooming by scaling the camera or its `OrthographicProjection` is treated like moving the camera towards or away
+///  from the map, see `zoomed_factor`, and changes the size of the viewport that `Parallax::bounds` has to cover.
 pub fn parallax_transform_system(
-    cameras: Query<(&GlobalTransform, &Camera)>,
+    cameras: Query<(
+        &GlobalTransform,
+        &Camera,
+        Option<&OrthographicProjection>,
+        Option<&ParallaxCamera>,
+    )>,
     mut parallax: Query<(&mut Transform, &Parallax)>,
 ) {
-    if let Some((camera_transform, _camera)) = cameras.iter().next() {
+    let camera = cameras
+        .iter()
+        .find(|(_, _, _, marker)| marker.is_some())
+        .or_else(|| cameras.iter().next());
+
+    if let Some((camera_transform, _camera, projection, _marker)) = camera {
         let translation = camera_transform.translation;
+        let zoom = camera_transform.scale.truncate().abs()
+            * projection.map_or(1.0, |projection| projection.scale.abs());
+        let viewport = projection.map_or(Vec2::ZERO, |projection| {
+            Vec2::new(
+                projection.right - projection.left,
+                projection.top - projection.bottom,
+            ) * zoom
+        });
 
         for (mut transform, parallax) in parallax.iter_mut() {
-            transform.translation = parallax.transform.translation
-                + translation * vec3(1.0, 1.0, 0.0)
-                - translation * parallax.factor.extend(0.0);
+            let relative = translation - parallax.origin.extend(0.0);
+            transform.translation = parallax.transform.translation + relative * vec3(1.0, 1.0, 0.0)
+                - relative * zoomed_factor(parallax.factor, zoom).extend(0.0);
+            if let Some(bounds) = parallax.bounds.as_ref() {
+                let offset = clamp_offset(
+                    (transform.translation - parallax.transform.translation).truncate(),
+                    bounds,
+                    translation.truncate(),
+                    viewport.abs(),
+                );
+                transform.translation.x = parallax.transform.translation.x + offset.x;
+                transform.translation.y = parallax.transform.translation.y + offset.y;
+            }
             transform.rotation = parallax.transform.rotation;
             transform.scale = parallax.transform.scale;
         }
@@ -42,6 +93,251 @@ pub fn parallax_transform_system(
 impl Parallax {
     /// Construct a new `Parallax`.
     pub fn new(factor: Vec2, transform: Transform) -> Self {
-        Self { factor, transform }
+        Self {
+            factor,
+            origin: Vec2::ZERO,
+            transform,
+            bounds: None,
+        }
+    }
+
+    /// Set the area covered by the entity at its source transform, to keep it covering the camera viewport.
+    pub fn with_bounds(mut self, bounds: Rect<f32>) -> Self {
+        self.bounds = Some(bounds);
+        self
+    }
+
+    /// Set the camera position at which the entity is rendered at its source transform.
+    pub fn with_origin(mut self, origin: Vec2) -> Self {
+        self.origin = origin;
+        self
+    }
+}
+
+/// The parallax factor of a layer seen through a camera zoomed by `zoom`, where a zoom above 1.0 shows more of the map.
+/// The camera is taken to sit at factor 1.0 and zooming out moves it away from the map, so the distance to the layer
+///  grows relative to the distance to the map and the factor of the layer approaches 1.0, while zooming in brings the
+///  camera closer to the map and spreads the factors of the layers apart.
+/// Layers at factor 1.0 stay fixed to the map and layers at factor 0.0 stay fixed to the camera at any zoom.
+fn zoomed_factor(factor: Vec2, zoom: Vec2) -> Vec2 {
+    let zoomed = |factor: f32, zoom: f32| {
+        // zooming in far enough moves the camera past layers in front of the map
+        zoom * factor / (1.0 - factor + zoom * factor).max(f32::EPSILON)
+    };
+    Vec2::new(zoomed(factor.x, zoom.x), zoomed(factor.y, zoom.y))
+}
+
+/// Clamp the parallax `offset` of an entity covering `bounds`, so that the moved bounds contain the viewport of
+///  `size` around `camera`. When the viewport is larger than the bounds, they are centered on the camera instead.
+fn clamp_offset(offset: Vec2, bounds: &Rect<f32>, camera: Vec2, size: Vec2) -> Vec2 {
+    // the bounds may be flipped by a negative scale
+    let min = Vec2::new(bounds.left.min(bounds.right), bounds.top.min(bounds.bottom));
+    let max = Vec2::new(bounds.left.max(bounds.right), bounds.top.max(bounds.bottom));
+    let low = camera + size * 0.5 - max;
+    let high = camera - size * 0.5 - min;
+    let clamp = |offset: f32, low: f32, high: f32| {
+        if low <= high {
+            offset.clamp(low, high)
+        } else {
+            (low + high) * 0.5
+        }
+    };
+    Vec2::new(
+        clamp(offset.x, low.x, high.x),
+        clamp(offset.y, low.y, high.y),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::{
+        schedule::{Stage, SystemStage},
+        system::IntoSystem,
+        world::World,
+    };
+    use bevy_math::{Vec2, Vec3};
+
+    use super::*;
+    use crate::test_util::{app, spawn};
+    use crate::TmxPlugin;
+
+    /// The offset that the parallax adds to the source transforms of the entities in the app.
+    fn parallax_offsets(world: &mut World) -> Vec<Vec3> {
+        let mut query = world.query::<(&Transform, &Parallax)>();
+        query
+            .iter(world)
+            .map(|(transform, parallax)| transform.translation - parallax.transform.translation)
+            .collect()
+    }
+
+    #[test]
+    fn parallax_is_relative_to_the_parallax_origin() {
+        let mut app = app(TmxPlugin::default());
+        spawn(&mut app, "parallax.tmx");
+        app.world.spawn().insert_bundle((
+            GlobalTransform::from_xyz(140.0, -90.0, 0.0),
+            Camera::default(),
+        ));
+        app.update();
+
+        // tiled moves the layer by (camera - origin) * (1 - factor), with the y axis pointing down
+        let expected = Vec2::new(140.0 - 100.0, 90.0 - 50.0) * (Vec2::ONE - Vec2::new(0.5, 0.25));
+        assert_eq!(
+            parallax_offsets(&mut app.world),
+            vec![Vec3::new(expected.x, -expected.y, 0.0)]
+        );
+
+        // centered on the origin, the layer is at its regular position
+        let mut cameras = app.world.query::<(&mut GlobalTransform, &Camera)>();
+        for (mut transform, _) in cameras.iter_mut(&mut app.world) {
+            transform.translation = Vec3::new(100.0, -50.0, 0.0);
+        }
+        app.update();
+        assert_eq!(parallax_offsets(&mut app.world), vec![Vec3::ZERO]);
+    }
+
+    /// Run the parallax system once on `world`.
+    fn run_parallax(world: &mut World) {
+        let mut stage = SystemStage::parallel().with_system(parallax_transform_system.system());
+        stage.run(world);
+    }
+
+    #[test]
+    fn the_marked_camera_drives_the_parallax() {
+        for marked_first in [false, true] {
+            let mut world = World::new();
+            let spawn_camera = |world: &mut World, marked: bool| {
+                let (x, y) = if marked { (0.0, 40.0) } else { (100.0, 0.0) };
+                let mut camera = world.spawn();
+                camera.insert_bundle((GlobalTransform::from_xyz(x, y, 0.0), Camera::default()));
+                if marked {
+                    camera.insert(ParallaxCamera);
+                }
+            };
+            spawn_camera(&mut world, marked_first);
+            spawn_camera(&mut world, !marked_first);
+            world.spawn().insert_bundle((
+                Transform::default(),
+                Parallax::new(Vec2::new(0.5, 0.5), Transform::default()),
+            ));
+            run_parallax(&mut world);
+
+            assert_eq!(
+                parallax_offsets(&mut world),
+                vec![Vec3::new(0.0, 20.0, 0.0)]
+            );
+        }
+    }
+
+    /// Spawn a camera at `translation` with a 100x100 viewport, zoomed by `zoom`, and a background of 400x200
+    ///  with a parallax factor of a half.
+    fn bounded_parallax_world(translation: Vec3, zoom: f32) -> World {
+        let mut world = World::new();
+        world.spawn().insert_bundle((
+            GlobalTransform {
+                translation,
+                scale: Vec3::splat(zoom),
+                ..Default::default()
+            },
+            Camera::default(),
+            OrthographicProjection {
+                left: -50.0,
+                right: 50.0,
+                bottom: -50.0,
+                top: 50.0,
+                ..Default::default()
+            },
+        ));
+        world.spawn().insert_bundle((
+            Transform::default(),
+            Parallax::new(Vec2::new(0.5, 0.5), Transform::default()).with_bounds(Rect {
+                left: 0.0,
+                right: 400.0,
+                bottom: 0.0,
+                top: 200.0,
+            }),
+        ));
+        world
+    }
+
+    #[test]
+    fn bounded_parallax_keeps_covering_the_viewport() {
+        // far to the right, half the camera translation would leave the viewport past the right edge of the background
+        let mut world = bounded_parallax_world(Vec3::new(1000.0, 100.0, 0.0), 1.0);
+        run_parallax(&mut world);
+        // so the background is moved until its right edge lines up with the right edge of the viewport
+        assert_eq!(
+            parallax_offsets(&mut world),
+            vec![Vec3::new(1050.0 - 400.0, 50.0, 0.0)]
+        );
+
+        // within the bounds, the parallax isn't clamped
+        let mut world = bounded_parallax_world(Vec3::new(300.0, 100.0, 0.0), 1.0);
+        run_parallax(&mut world);
+        assert_eq!(
+            parallax_offsets(&mut world),
+            vec![Vec3::new(150.0, 50.0, 0.0)]
+        );
+    }
+
+    #[test]
+    fn the_parallax_offset_compensates_for_the_zoom_of_the_camera() {
+        let offset = |factor: Vec2, zoom: f32, projection_scale: f32| {
+            let mut world = World::new();
+            world.spawn().insert_bundle((
+                GlobalTransform {
+                    translation: Vec3::new(140.0, -90.0, 0.0),
+                    scale: Vec3::splat(zoom),
+                    ..Default::default()
+                },
+                Camera::default(),
+                OrthographicProjection {
+                    scale: projection_scale,
+                    ..Default::default()
+                },
+            ));
+            world.spawn().insert_bundle((
+                Transform::default(),
+                Parallax::new(factor, Transform::default()),
+            ));
+            run_parallax(&mut world);
+            parallax_offsets(&mut world)[0]
+        };
+        let assert_offset = |offset: Vec3, expected: Vec3| {
+            assert!(
+                (offset - expected).abs().max_element() < 1e-4,
+                "{:?} != {:?}",
+                offset,
+                expected
+            );
+        };
+        let factor = Vec2::new(0.5, 0.25);
+
+        assert_offset(offset(factor, 1.0, 1.0), Vec3::new(70.0, -67.5, 0.0));
+        // zoomed out three times, by the camera and its projection, the layer is relatively closer to the map
+        assert_offset(offset(factor, 1.5, 2.0), Vec3::new(35.0, -45.0, 0.0));
+        // zoomed in three times, the layer is relatively further away from the map
+        assert_offset(offset(factor, 1.0 / 3.0, 1.0), Vec3::new(105.0, -81.0, 0.0));
+
+        // the map stays in place and layers at factor 0 stay in view at any zoom
+        for &(zoom, projection_scale) in &[(1.5, 2.0), (1.0 / 3.0, 1.0)] {
+            assert_offset(offset(Vec2::ONE, zoom, projection_scale), Vec3::ZERO);
+            assert_offset(
+                offset(Vec2::ZERO, zoom, projection_scale),
+                Vec3::new(140.0, -90.0, 0.0),
+            );
+        }
+    }
+
+    #[test]
+    fn zooming_out_widens_the_viewport_that_bounded_parallax_covers() {
+        // zoomed out twice, the viewport is 200x200, so the background has to move further than the zoomed parallax
+        //  offset of a third of the camera translation to cover it
+        let mut world = bounded_parallax_world(Vec3::new(1000.0, 100.0, 0.0), 2.0);
+        run_parallax(&mut world);
+        assert_eq!(
+            parallax_offsets(&mut world),
+            vec![Vec3::new(1100.0 - 400.0, 0.0, 0.0)]
+        );
     }
 }
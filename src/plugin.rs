@@ -1,45 +1,128 @@
+use std::collections::HashMap;
 use std::path::{Component, Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use anyhow::*;
+use bevy_app::EventReader;
 use bevy_app::{AppBuilder, Plugin};
-use bevy_asset::{AddAsset, AssetLoader, BoxedFuture, LoadContext, LoadedAsset};
+use bevy_asset::{
+    AddAsset, AssetEvent, AssetLoader, AssetPath, BoxedFuture, Handle, HandleId, LoadContext,
+    LoadedAsset,
+};
 use bevy_ecs::{
-    system::IntoSystem,
+    schedule::ParallelSystemDescriptorCoercion,
+    system::{IntoSystem, Res},
     world::{EntityMut, World},
 };
 use bevy_math::*;
+use bevy_render::texture::Texture as BevyTexture;
+use bevy_scene::Scene;
+use bevy_tasks::{ComputeTaskPool, TaskPool};
+use bevy_transform::components::{GlobalTransform, Transform};
 
-use crate::parallax::{parallax_transform_system, Parallax};
+use crate::animation::{tile_animation_system, AnimatedTile, TileAnimation};
+use crate::archive::Archive;
+use crate::parallax::{parallax_transform_system, Parallax, ParallaxCamera};
+use crate::repeat::{repeat_image_system, RepeatImage};
+pub use crate::scene::{
+    despawn_map, HiddenObject, LayerMetadata, MapHandle, MapProperties, MapRoot, ObjectProperties,
+    TileColliders, TileMap, TileMapLayer, TilePos, TileShapes,
+};
 use crate::scene::{
-    proto_sprite_upgrade_system, ImageVisitor, MapVisitor, ObjectVisitor, ProtoSprite, SceneBuilder,
+    layer_visibility_system, proto_atlas_sprite_upgrade_system, proto_sprite_upgrade_system,
+    ImageVisitor, LayerVisitor, MapVisitor, ObjectVisitor, ProtoAtlasSprite, ProtoSprite,
+    SceneBuilder, SceneSettings, TileVisitor,
 };
-use crate::tmx::{Map, Object};
+use crate::tmx::{self, Layer, Map, Object, ObjectTemplate, Texture, Tile, Tileset};
+use crate::world::{
+    map_scene_system, map_scene_transform_system, world_streaming_system, MapScene, StreamedMap,
+    WorldStreaming, WorldStreamingEvent,
+};
+use crate::ysort::{ysort_system, YSort};
 
 /// Plugin that adds support for .tmx asset loading. Loading behaviour can be customized on creation.
 pub struct TmxPlugin {
     object_visitor: Option<Arc<ObjectVisitor>>,
+    tile_visitor: Option<Arc<TileVisitor>>,
+    layer_visitor: Option<Arc<LayerVisitor>>,
     image_visitor: Option<Arc<ImageVisitor>>,
     map_visitor: Option<Arc<MapVisitor>>,
-    scale: Vec3,
+    settings: SceneSettings,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 struct TmxSceneLoader {
     object_visitor: Option<Arc<ObjectVisitor>>,
+    tile_visitor: Option<Arc<TileVisitor>>,
+    layer_visitor: Option<Arc<LayerVisitor>>,
     image_visitor: Option<Arc<ImageVisitor>>,
     map_visitor: Option<Arc<MapVisitor>>,
-    scale: Vec3,
+    settings: SceneSettings,
+    tilesets: TilesetCache,
+    textures: TextureCache,
+    task_pool: TaskPool,
+}
+
+/// External tilesets that were parsed by any map, keyed by their resolved path.
+/// The hash of the file contents is stored along with the tileset, so modified files are parsed again.
+type TilesetCache = Arc<Mutex<HashMap<PathBuf, (u64, Arc<Tileset>)>>>;
+
+/// Textures that were loaded by any map or tileset, keyed by the label of the image, which contains its resolved path.
+/// Other assets that use the same image share the texture that is a labeled asset of the first asset that loaded it.
+/// Textures are only shared once the texture_cache_system sees that they were created, so a failed load is never shared.
+/// Entries are removed by the texture_cache_system once the texture is unloaded.
+#[derive(Default, Clone)]
+pub(crate) struct TextureCache(Arc<Mutex<TextureCacheEntries>>);
+
+#[derive(Default)]
+struct TextureCacheEntries {
+    loaded: HashMap<String, AssetPath<'static>>,
+    pending: HashMap<HandleId, (String, AssetPath<'static>)>,
 }
 
+/// Loader for maps in the json format, producing the same scenes as the .tmx loader.
+struct TmxJsonLoader(TmxSceneLoader);
+
+/// Loader for maps in the json format that are saved with the .json extension.
+/// It's only added with `TmxPlugin::json_extension`, since it takes over all .json assets of the app.
+struct TmxJsonExtensionLoader(TmxJsonLoader);
+
+/// Loader for standalone tileset files, which are loaded as `Tileset` assets.
+/// The images of the tileset are available as labeled assets, see `Texture::handle`.
+struct TmxTilesetLoader(TmxSceneLoader);
+
+/// Loader for world files, which are loaded as scenes that spawn the scenes of their maps.
+/// The `tmx::World` itself is available as the labeled asset "world".
+struct TmxWorldLoader(TmxSceneLoader);
+
+/// Loader for standalone template files, which are loaded as `ObjectTemplate` assets.
+struct TmxTemplateLoader(TmxSceneLoader);
+
+/// Loader for zip archives with a single map, which is loaded as a scene just like the map itself would be.
+/// Tilesets, templates and images of the map are read from the archive.
+#[cfg(feature = "zip")]
+struct TmxZipLoader(TmxSceneLoader);
+
 #[derive(Clone)]
 pub(crate) struct TmxLoadContext<'a> {
     relative: Arc<Path>,
-    context: &'a LoadContext<'a>,
+    context: Option<&'a LoadContext<'a>>,
+    dependencies: Arc<Mutex<Vec<PathBuf>>>,
+    tilesets: TilesetCache,
+    archive: Option<Arc<Archive>>,
 }
 
 impl TmxPlugin {
     /// Adds some custom loading functionality for objects in tmx assets
+    ///
+    /// A common use is to add physics colliders to the objects of a map, using `Object::collider`:
+    /// ```ignore
+    /// TmxPlugin::default().visit_objects(|object, entity| {
+    ///     if let Collider::Rect { center, half_extents } = object.collider().scaled(Vec2::new(1.0, -1.0)) {
+    ///         entity.insert(MyPhysicsCollider::cuboid(center, half_extents));
+    ///     }
+    /// })
+    /// ```
     pub fn visit_objects<F: 'static + for<'w> Fn(&Object, &mut EntityMut<'w>) + Send + Sync>(
         mut self,
         f: F,
@@ -48,6 +131,32 @@ impl TmxPlugin {
         self
     }
 
+    /// Adds some custom loading functionality for tiles in tmx assets.
+    /// The closure receives the tile, its global tile id including flip flags and its coordinate within the layer.
+    /// Only tiles that are spawned as a separate entity are visited, which are animated tiles and,
+    ///  if `per_tile_entities` is enabled, all other tiles as well.
+    pub fn visit_tiles<
+        F: 'static + for<'w> Fn(&Tile, u32, IVec2, &mut EntityMut<'w>) + Send + Sync,
+    >(
+        mut self,
+        f: F,
+    ) -> Self {
+        self.tile_visitor = Some(Arc::new(f));
+        self
+    }
+
+    /// Adds some custom loading functionality for layers in tmx assets.
+    /// Every layer is spawned as an entity that has the entities of its contents as children,
+    ///  the closure is called once for each of those layer entities. Group layers are spawned as well,
+    ///  with the entities of the layers within them as children, and are visited before those layers.
+    pub fn visit_layers<F: 'static + for<'w> Fn(&Layer, &mut EntityMut<'w>) + Send + Sync>(
+        mut self,
+        f: F,
+    ) -> Self {
+        self.layer_visitor = Some(Arc::new(f));
+        self
+    }
+
     /// Adds some custom loading functionality for image layers in tmx assets
     pub fn visit_images<F: 'static + for<'w> Fn(&mut EntityMut<'w>) + Send + Sync>(
         mut self,
@@ -68,14 +177,89 @@ impl TmxPlugin {
 
     /// Sets the scale to apply to the coordinate system of loaded .tmx assets. Defaults to (1, -1), since bevy's y axis points up where tiled's y axis points down.
     pub fn scale(mut self, scale: Vec2) -> Self {
-        self.scale.x = scale.x;
-        self.scale.y = scale.y;
+        self.settings.scale.x = scale.x;
+        self.settings.scale.y = scale.y;
+        self
+    }
+
+    /// Whether to flip the y axis of loaded .tmx assets, so that it points up like bevy's y axis. Defaults to true.
+    /// Passing false keeps tiled's y-down coordinate system, so that positions in the scene match the pixel coordinates
+    ///  in the map editor. The map is then rendered upside down by a default camera; to view it the right way up,
+    ///  flip the camera instead, for example by setting the y scale of its `Transform` to -1.
+    pub fn flip_y(mut self, flip_y: bool) -> Self {
+        self.settings.scale.y = if flip_y {
+            -self.settings.scale.y.abs()
+        } else {
+            self.settings.scale.y.abs()
+        };
+        self
+    }
+
+    /// Whether to spawn a quad tinted with the map's background color behind all layers. Defaults to false.
+    /// The quad covers the pixel bounds of the map's tiles, and the layers are moved forward by one depth step to make room for it.
+    pub fn background(mut self, background: bool) -> Self {
+        self.settings.background = background;
+        self
+    }
+
+    /// Sets the amount of texels to shrink the UVs of tiles with on each side, to prevent neighbouring tiles
+    ///  in the tileset image from bleeding into the rendered tile. Defaults to 0.5.
+    pub fn tile_uv_inset(mut self, texels: f32) -> Self {
+        self.settings.tile_uv_inset = texels;
+        self
+    }
+
+    /// Whether to spawn every tile as a separate sprite entity with a `TilePos` component, instead of
+    ///  batching all tiles in a layer into a single mesh per tileset image. Defaults to false.
+    /// This is useful for tiles that need to be modified individually, like destructible tiles, but
+    ///  spawning thousands of entities is considerably slower to load and render than the batched meshes.
+    pub fn per_tile_entities(mut self, per_tile_entities: bool) -> Self {
+        self.settings.per_tile_entities = per_tile_entities;
+        self
+    }
+
+    /// Whether to insert a `YSort` component on tile objects in layers with the topdown draw order, so that their depth
+    ///  keeps following their y coordinate when they move. Defaults to true.
+    pub fn y_sort(mut self, y_sort: bool) -> Self {
+        self.settings.y_sort = y_sort;
+        self
+    }
+
+    /// Whether to spawn a translucent quad for rectangle and ellipse objects, matching their size and rotation. Defaults to false.
+    /// These objects are usually triggers or zones that are invisible in the game, so drawing them helps when building levels.
+    /// The quads have the object color of their layer, as shown in the map editor, and their entities get a `Sprite` with a size of one.
+    pub fn draw_object_rects(mut self, draw_object_rects: bool) -> Self {
+        self.settings.draw_object_rects = draw_object_rects;
+        self
+    }
+
+    /// Whether to keep layers with a parallax factor from sliding past the edge of the map. Defaults to false.
+    /// The `Parallax` of their entities gets the pixel bounds of the map, so that their translation is clamped
+    ///  to keep the layer covering the camera viewport, like a side-scroller background that stops at the end of the level.
+    pub fn clamp_parallax(mut self, clamp_parallax: bool) -> Self {
+        self.settings.clamp_parallax = clamp_parallax;
+        self
+    }
+
+    /// Whether to load files with the .json extension as maps in the json format. Defaults to false, so only .tmj files are.
+    /// The loader for .json files replaces any other loader for that extension, so only enable this when the app
+    ///  doesn't load other .json assets.
+    pub fn json_extension(mut self, json_extension: bool) -> Self {
+        self.settings.json_extension = json_extension;
+        self
+    }
+
+    /// Load the maps of worlds only while the camera is within `streaming.load_radius` of them. Defaults to loading all maps.
+    /// The entities of the maps get a `StreamedMap` component instead of a `MapScene`, and a `WorldStreamingEvent`
+    ///  is sent whenever a map is loaded or unloaded. The camera is picked the same way as for parallax.
+    pub fn world_streaming(mut self, streaming: WorldStreaming) -> Self {
+        self.settings.world_streaming = Some(streaming);
         self
     }
 
     /// Sets the depth added after each layer. Defaults to 1.
     pub fn depth_scale(mut self, depth_scale: f32) -> Self {
-        self.scale.z = depth_scale;
+        self.settings.scale.z = depth_scale;
         self
     }
 }
@@ -83,19 +267,292 @@ impl TmxPlugin {
 impl Plugin for TmxPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.register_type::<ProtoSprite>();
+        app.register_type::<ProtoAtlasSprite>();
+        app.register_type::<MapRoot>();
+        app.register_type::<LayerMetadata>();
+        app.register_type::<HiddenObject>();
+        app.register_type::<MapHandle>();
+        app.register_type::<ObjectProperties>();
+        app.register_type::<MapProperties>();
+        app.register_type::<TileMap>();
+        app.register_type::<TilePos>();
         app.register_type::<Parallax>();
+        app.register_type::<ParallaxCamera>();
+        app.register_type::<RepeatImage>();
+        app.register_type::<YSort>();
+        app.register_type::<MapScene>();
+        app.register_type::<StreamedMap>();
+        app.register_type::<AnimatedTile>();
+        app.register_type::<TileColliders>();
         app.add_asset::<Map>();
+        app.add_asset::<Tileset>();
+        app.add_asset::<ObjectTemplate>();
+        app.add_asset::<tmx::World>();
+        app.add_event::<WorldStreamingEvent>();
+        app.add_asset::<TileAnimation>();
+        app.add_asset::<TileShapes>();
 
         let asset_loader = TmxSceneLoader {
             object_visitor: self.object_visitor.clone(),
+            tile_visitor: self.tile_visitor.clone(),
+            layer_visitor: self.layer_visitor.clone(),
             image_visitor: self.image_visitor.clone(),
             map_visitor: self.map_visitor.clone(),
-            scale: self.scale,
+            settings: self.settings,
+            tilesets: TilesetCache::default(),
+            textures: TextureCache::default(),
+            task_pool: app
+                .world()
+                .get_resource::<ComputeTaskPool>()
+                .map(|pool| pool.0.clone())
+                .unwrap_or_default(),
         };
 
+        app.insert_resource(asset_loader.textures.clone());
+        app.add_asset_loader(TmxJsonLoader(asset_loader.clone()));
+        if self.settings.json_extension {
+            app.add_asset_loader(TmxJsonExtensionLoader(TmxJsonLoader(asset_loader.clone())));
+        }
+        app.add_asset_loader(TmxTilesetLoader(asset_loader.clone()));
+        app.add_asset_loader(TmxTemplateLoader(asset_loader.clone()));
+        app.add_asset_loader(TmxWorldLoader(asset_loader.clone()));
+        #[cfg(feature = "zip")]
+        app.add_asset_loader(TmxZipLoader(asset_loader.clone()));
         app.add_asset_loader(asset_loader);
         app.add_system(proto_sprite_upgrade_system.system());
-        app.add_system(parallax_transform_system.system());
+        app.add_system(proto_atlas_sprite_upgrade_system.system());
+        app.add_system(layer_visibility_system.system());
+        app.add_system(parallax_transform_system.system().label("tmx_parallax"));
+        app.add_system(repeat_image_system.system().after("tmx_parallax"));
+        app.add_system(ysort_system.system().after("tmx_parallax"));
+        app.add_system(tile_animation_system.system());
+        app.add_system(map_scene_system.system());
+        app.add_system(map_scene_transform_system.system());
+        if let Some(streaming) = self.settings.world_streaming {
+            app.insert_resource(streaming);
+            app.add_system(world_streaming_system.system());
+        }
+        app.add_system(texture_cache_system.system());
+    }
+}
+
+impl TextureCache {
+    /// Look up the texture of `image`, if it was loaded by an asset other than the one at `path`.
+    pub fn get(&self, image: &Texture, path: &Path) -> Option<AssetPath<'static>> {
+        self.0
+            .lock()
+            .unwrap()
+            .loaded
+            .get(image.label())
+            .filter(|cached| cached.path() != path)
+            .cloned()
+    }
+
+    /// Load the texture of `image`, or share the texture of another asset that loaded the same image.
+    pub async fn load(
+        &self,
+        image: &Texture,
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Handle<BevyTexture>> {
+        if let Some(cached) = self.get(image, load_context.path()) {
+            return Ok(image.load_from(load_context.get_handle(cached)).await);
+        }
+
+        let handle = image.load(load_context).await?;
+        let path = AssetPath::new(
+            load_context.path().to_path_buf(),
+            Some(image.label().to_string()),
+        );
+        self.0
+            .lock()
+            .unwrap()
+            .pending
+            .insert(handle.id, (image.label().to_string(), path));
+        Ok(handle)
+    }
+
+    /// Share the texture with the `id` with other assets, now that it was loaded.
+    fn created(&self, id: HandleId) {
+        let mut entries = self.0.lock().unwrap();
+        if let Some((label, path)) = entries.pending.remove(&id) {
+            entries.loaded.insert(label, path);
+        }
+    }
+
+    /// Forget the texture with the `id`, so it's loaded again when needed.
+    fn removed(&self, id: HandleId) {
+        let mut entries = self.0.lock().unwrap();
+        entries.pending.remove(&id);
+        entries
+            .loaded
+            .retain(|_, path| HandleId::from(path.get_id()) != id);
+    }
+}
+
+/// System that shares textures in the `TextureCache` once they are loaded,
+/// and forgets them once they are unloaded, so they are loaded again when needed.
+fn texture_cache_system(
+    cache: Res<TextureCache>,
+    mut events: EventReader<AssetEvent<BevyTexture>>,
+) {
+    for event in events.iter() {
+        match event {
+            AssetEvent::Created { handle } => cache.created(handle.id),
+            AssetEvent::Removed { handle } => cache.removed(handle.id),
+            AssetEvent::Modified { .. } => (),
+        }
+    }
+}
+
+impl TmxSceneLoader {
+    fn load_context<'a>(&self, load_context: &'a LoadContext<'a>) -> TmxLoadContext<'a> {
+        TmxLoadContext {
+            relative: Arc::from(
+                load_context
+                    .path()
+                    .parent()
+                    .unwrap_or_else(|| Path::new("."))
+                    .to_path_buf(),
+            ),
+            context: Some(load_context),
+            dependencies: Arc::default(),
+            tilesets: self.tilesets.clone(),
+            archive: None,
+        }
+    }
+
+    async fn load_scene(
+        &self,
+        bytes: &[u8],
+        load_context: &mut LoadContext<'_>,
+        json: bool,
+        archive: Option<(Arc<Archive>, &Path)>,
+    ) -> Result<()> {
+        let env = match archive {
+            Some((archive, map)) => self.load_context(load_context).archive(archive, map),
+            None => self.load_context(load_context),
+        };
+        let dependencies = env.dependencies.clone();
+
+        let map = if json {
+            Map::load_from_json(env, bytes).await?
+        } else {
+            Map::load_from_xml_reader(env, xml::EventReader::new(bytes)).await?
+        };
+        let path = load_context.path().to_path_buf();
+        let images: Vec<&Texture> = map
+            .images()
+            .into_iter()
+            .filter(|image| self.textures.get(image, &path).is_none())
+            .collect();
+        Texture::decode_all(images, load_context, &self.task_pool).await?;
+
+        let builder = SceneBuilder::new(
+            load_context,
+            &map,
+            self.object_visitor.as_deref(),
+            self.tile_visitor.as_deref(),
+            self.layer_visitor.as_deref(),
+            self.image_visitor.as_deref(),
+            self.map_visitor.as_deref(),
+            self.settings,
+            self.textures.clone(),
+        );
+        let scene = builder.build().await?;
+
+        load_context.set_labeled_asset("map", LoadedAsset::new(map));
+        // external tilesets and templates that the map was loaded from, so changes to them can be tracked.
+        // images are no dependencies, because the asset server would decode them a second time with its own loader.
+        let dependencies = dependencies
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|path| AssetPath::new(path.clone(), None))
+            .collect();
+        load_context.set_default_asset(LoadedAsset::new(scene).with_dependencies(dependencies));
+        Ok(())
+    }
+
+    async fn load_tileset(&self, load_context: &mut LoadContext<'_>) -> Result<()> {
+        let path = load_context.path().to_path_buf();
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| anyhow!("invalid tileset path"))?;
+        let env = self.load_context(load_context);
+
+        let tileset = Tileset::load_standalone(env, file_name).await?;
+        let images: Vec<&Texture> = tileset
+            .images()
+            .filter(|image| self.textures.get(image, &path).is_none())
+            .collect();
+        Texture::decode_all(images, load_context, &self.task_pool).await?;
+        for image in tileset.images() {
+            self.textures.load(image, load_context).await?;
+        }
+
+        load_context.set_default_asset(LoadedAsset::new(tileset));
+        Ok(())
+    }
+
+    async fn load_world(&self, bytes: &[u8], load_context: &mut LoadContext<'_>) -> Result<()> {
+        let world = tmx::World::from_bytes(bytes)?;
+        let env = self.load_context(load_context);
+
+        let mut scene_world = World::default();
+        let mut dependencies = Vec::new();
+        for map in world.maps.iter() {
+            let path = env.file_path(&map.file_name);
+            let translation = Vec3::new(
+                map.position.x as f32 * self.settings.scale.x,
+                map.position.y as f32 * self.settings.scale.y,
+                0.0,
+            );
+            let mut entity = scene_world.spawn();
+            entity.insert_bundle((
+                Transform::from_translation(translation),
+                GlobalTransform::from_translation(translation),
+            ));
+            if self.settings.world_streaming.is_some() {
+                // streamed maps are loaded by the world_streaming_system, so they are no dependencies of the world
+                let size = map.size.as_f32() * self.settings.scale.xy();
+                entity.insert(StreamedMap::new(format!("{}", path.display()), size));
+            } else {
+                let path = AssetPath::new(path, None);
+                entity.insert(MapScene(load_context.get_handle(path.clone())));
+                dependencies.push(path);
+            }
+        }
+
+        load_context.set_labeled_asset("world", LoadedAsset::new(world));
+        load_context.set_default_asset(
+            LoadedAsset::new(Scene::new(scene_world)).with_dependencies(dependencies),
+        );
+        Ok(())
+    }
+
+    async fn load_template(&self, load_context: &mut LoadContext<'_>) -> Result<()> {
+        let file_name = load_context
+            .path()
+            .file_name()
+            .ok_or_else(|| anyhow!("invalid template path"))?
+            .to_os_string();
+        let template =
+            ObjectTemplate::load_standalone(self.load_context(load_context), file_name).await?;
+        load_context.set_default_asset(LoadedAsset::new(template));
+        Ok(())
+    }
+
+    #[cfg(feature = "zip")]
+    async fn load_zip(&self, bytes: &[u8], load_context: &mut LoadContext<'_>) -> Result<()> {
+        let archive = Arc::new(Archive::from_zip(
+            load_context.path().to_path_buf(),
+            bytes.to_vec(),
+        )?);
+        let map = archive.map()?;
+        let bytes = archive.read(&map)?;
+        let json = tmx::is_json_file(&map);
+        self.load_scene(&bytes, load_context, json, Some((archive.clone(), &map)))
+            .await
     }
 }
 
@@ -105,37 +562,96 @@ impl AssetLoader for TmxSceneLoader {
         bytes: &'a [u8],
         load_context: &'a mut LoadContext<'b>,
     ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
-        Box::pin(async move {
-            let env = TmxLoadContext {
-                relative: Arc::from(
-                    load_context
-                        .path()
-                        .parent()
-                        .unwrap_or_else(|| Path::new("."))
-                        .to_path_buf(),
-                ),
-                context: load_context,
-            };
+        Box::pin(async move { self.load_scene(bytes, load_context, false, None).await })
+    }
 
-            let map = Map::load_from_xml_reader(env, xml::EventReader::new(bytes)).await?;
-            let builder = SceneBuilder::new(
-                load_context,
-                &map,
-                self.object_visitor.as_deref(),
-                self.image_visitor.as_deref(),
-                self.map_visitor.as_deref(),
-                self.scale,
-            );
-            let scene = builder.build().await?;
+    fn extensions(&self) -> &[&str] {
+        &["tmx"]
+    }
+}
 
-            load_context.set_labeled_asset("map", LoadedAsset::new(map));
-            load_context.set_default_asset(LoadedAsset::new(scene));
-            Ok(())
-        })
+impl AssetLoader for TmxJsonLoader {
+    fn load<'a, 'b>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext<'b>,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move { self.0.load_scene(bytes, load_context, true, None).await })
     }
 
     fn extensions(&self) -> &[&str] {
-        &["tmx"]
+        &["tmj"]
+    }
+}
+
+impl AssetLoader for TmxJsonExtensionLoader {
+    fn load<'a, 'b>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext<'b>,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        self.0.load(bytes, load_context)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["json"]
+    }
+}
+
+impl AssetLoader for TmxTilesetLoader {
+    fn load<'a, 'b>(
+        &'a self,
+        _bytes: &'a [u8],
+        load_context: &'a mut LoadContext<'b>,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move { self.0.load_tileset(load_context).await })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tsx", "tsj"]
+    }
+}
+
+impl AssetLoader for TmxWorldLoader {
+    fn load<'a, 'b>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext<'b>,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move { self.0.load_world(bytes, load_context).await })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["world"]
+    }
+}
+
+impl AssetLoader for TmxTemplateLoader {
+    fn load<'a, 'b>(
+        &'a self,
+        _bytes: &'a [u8],
+        load_context: &'a mut LoadContext<'b>,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move { self.0.load_template(load_context).await })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tx", "tj"]
+    }
+}
+
+#[cfg(feature = "zip")]
+impl AssetLoader for TmxZipLoader {
+    fn load<'a, 'b>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext<'b>,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move { self.0.load_zip(bytes, load_context).await })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["zip"]
     }
 }
 
@@ -143,16 +659,89 @@ impl Default for TmxPlugin {
     fn default() -> Self {
         TmxPlugin {
             object_visitor: None,
+            tile_visitor: None,
+            layer_visitor: None,
             image_visitor: None,
             map_visitor: None,
-            scale: Vec3::new(1.0, -1.0, 1.0),
+            settings: SceneSettings {
+                scale: Vec3::new(1.0, -1.0, 1.0),
+                background: false,
+                tile_uv_inset: 0.5,
+                per_tile_entities: false,
+                y_sort: true,
+                draw_object_rects: false,
+                clamp_parallax: false,
+                json_extension: false,
+                world_streaming: None,
+            },
         }
     }
 }
 
 impl<'a> TmxLoadContext<'a> {
     pub async fn load_file<'p>(&'p self, path: impl AsRef<Path> + Send + 'p) -> Result<Vec<u8>> {
-        Ok(self.context.read_asset_bytes(self.file_path(path)).await?)
+        let file_path = self.file_path(path.as_ref());
+        if let Some(archive) = self.archive.as_ref().filter(|a| a.contains(&file_path)) {
+            return archive.read(&file_path);
+        }
+        match self.context {
+            Some(context) => Ok(context.read_asset_bytes(self.file_path(path)).await?),
+            None => Ok(std::fs::read(self.file_path(path))?),
+        }
+    }
+
+    /// A context that reads files directly from the filesystem relative to `base_dir`, for maps loaded without an `AssetServer`.
+    pub fn filesystem(base_dir: &Path) -> TmxLoadContext<'static> {
+        TmxLoadContext {
+            relative: Arc::from(base_dir),
+            context: None,
+            dependencies: Arc::default(),
+            tilesets: TilesetCache::default(),
+            archive: None,
+        }
+    }
+
+    /// A context that reads the files in `archive` from memory, relative to the directory of the `map` in it.
+    pub fn archive(self, archive: Arc<Archive>, map: &Path) -> Self {
+        Self {
+            relative: Arc::from(map.parent().unwrap_or(map)),
+            archive: Some(archive),
+            ..self
+        }
+    }
+
+    /// Whether the file at `path` is read from an archive.
+    pub fn is_archived(&self, path: impl AsRef<Path>) -> bool {
+        let path = self.file_path(path);
+        matches!(&self.archive, Some(archive) if archive.contains(&path))
+    }
+
+    /// Record that the asset being loaded depends on the tileset or template file at `path`.
+    /// Files in archives are no dependencies, since they can't be loaded by the asset server.
+    pub fn add_dependency(&self, path: impl AsRef<Path>) {
+        if self.is_archived(path.as_ref()) {
+            return;
+        }
+        let path = self.file_path(path);
+        let mut dependencies = self.dependencies.lock().unwrap();
+        if !dependencies.contains(&path) {
+            dependencies.push(path);
+        }
+    }
+
+    /// Retrieve a copy of the external tileset at the resolved `path` if it was parsed before and the file didn't change.
+    pub async fn cached_tileset(&self, path: &Path, hash: u64, first_gid: u32) -> Option<Tileset> {
+        let cached = match self.tilesets.lock().unwrap().get(path) {
+            Some((cached_hash, tileset)) if *cached_hash == hash => tileset.clone(),
+            _ => return None,
+        };
+        Some(cached.detached(first_gid).await)
+    }
+
+    /// Store a parsed external tileset, so other maps don't need to parse it again.
+    pub async fn cache_tileset(&self, path: PathBuf, hash: u64, tileset: &Tileset) {
+        let tileset = Arc::new(tileset.detached(tileset.first_gid).await);
+        self.tilesets.lock().unwrap().insert(path, (hash, tileset));
     }
 
     pub fn file_path(&self, path: impl AsRef<Path>) -> PathBuf {
@@ -171,6 +760,17 @@ impl<'a> TmxLoadContext<'a> {
         joined
     }
 
+    /// A context that resolves paths relative to the asset directory, instead of the directory of the current file.
+    pub fn root(&self) -> Self {
+        Self {
+            relative: Arc::from(Path::new("")),
+            context: self.context,
+            dependencies: self.dependencies.clone(),
+            tilesets: self.tilesets.clone(),
+            archive: self.archive.clone(),
+        }
+    }
+
     pub fn file_directory(&self, path: impl AsRef<Path>) -> Self {
         Self {
             relative: if let Some(parent) = path.as_ref().parent() {
@@ -179,6 +779,152 @@ impl<'a> TmxLoadContext<'a> {
                 self.relative.clone()
             },
             context: self.context,
+            dependencies: self.dependencies.clone(),
+            tilesets: self.tilesets.clone(),
+            archive: self.archive.clone(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use bevy_asset::Assets;
+    use bevy_sprite::ColorMaterial;
+    use futures_lite::future::block_on;
+
+    use super::*;
+    use crate::test_util::{app, fixtures, load, spawn};
+
+    #[test]
+    fn external_tilesets_and_templates_are_dependencies() {
+        let fixtures = fixtures();
+        let env = TmxLoadContext::filesystem(&fixtures);
+        let dependencies = env.dependencies.clone();
+        let bytes = std::fs::read(fixtures.join("external.tmx")).unwrap();
+        let map = block_on(Map::load_from_xml_reader(
+            env,
+            xml::EventReader::new(bytes.as_slice()),
+        ))
+        .unwrap();
+        assert_eq!(map.tilesets.len(), 1);
+
+        // the tileset is referenced by both the map and the template, but recorded once
+        assert_eq!(
+            *dependencies.lock().unwrap(),
+            vec![fixtures.join("tiles.tsx"), fixtures.join("crate.tx")]
+        );
+    }
+
+    #[test]
+    fn external_tilesets_are_parsed_once() {
+        let fixtures = fixtures();
+        let tilesets = TilesetCache::default();
+        let load = |tilesets: &TilesetCache| {
+            let env = TmxLoadContext {
+                tilesets: tilesets.clone(),
+                ..TmxLoadContext::filesystem(&fixtures)
+            };
+            let bytes = std::fs::read(fixtures.join("external.tmx")).unwrap();
+            block_on(Map::load_from_xml_reader(
+                env,
+                xml::EventReader::new(bytes.as_slice()),
+            ))
+            .unwrap()
+        };
+
+        let first = load(&tilesets);
+        assert_eq!(first.tilesets[0].columns, 2);
+        // the map and the template use the same tileset file, which is parsed once
+        assert_eq!(tilesets.lock().unwrap().len(), 1);
+
+        // mark the cached tileset, so the next map shows whether it parsed the file again
+        for (_, tileset) in tilesets.lock().unwrap().values_mut() {
+            let mut marked = Tileset::clone(tileset);
+            marked.columns = 99;
+            *tileset = Arc::new(marked);
+        }
+        let second = load(&tilesets);
+        assert_eq!(second.tilesets[0].columns, 99);
+        assert_eq!(second.tilesets[0].first_gid, 1);
+    }
+
+    #[test]
+    fn standalone_tilesets_are_loaded_as_assets() {
+        let mut app = app(TmxPlugin::default());
+        let handle = load::<Tileset>(&mut app, "tiles.tsx");
+
+        let tilesets = app.world.get_resource::<Assets<Tileset>>().unwrap();
+        let tileset = tilesets.get(&handle).expect("tileset wasn't loaded");
+        assert_eq!(tileset.tile_count(), 4);
+        let image = tileset.image.as_ref().unwrap();
+        assert_eq!((image.width(), image.height()), (32, 32));
+        assert_eq!(image.path(), Some(Path::new("tiles.png")));
+
+        // the image is a labeled asset of the tileset
+        let textures = app.world.get_resource::<Assets<BevyTexture>>().unwrap();
+        assert!(textures.get(image.handle().unwrap()).is_some());
+    }
+
+    #[test]
+    fn standalone_templates_are_loaded_as_assets() {
+        let mut app = app(TmxPlugin::default());
+        let handle = load::<ObjectTemplate>(&mut app, "crate.tx");
+
+        let templates = app.world.get_resource::<Assets<ObjectTemplate>>().unwrap();
+        let template = templates.get(&handle).expect("template wasn't loaded");
+        assert_eq!(template.object.name, "crate");
+        assert_eq!(
+            (template.object.width, template.object.height),
+            (16.0, 16.0)
+        );
+        // the tile is local to the referenced tileset
+        assert_eq!(template.object.kind, tmx::ObjectKind::Tile(1));
+        assert_eq!(template.tileset.as_deref(), Some("tiles.tsx"));
+    }
+
+    #[test]
+    fn maps_that_share_an_image_share_its_texture() {
+        let mut app = app(TmxPlugin::default());
+        spawn(&mut app, "sparse.tmx");
+        spawn(&mut app, "objects.tmx");
+
+        let mut query = app.world.query::<&Handle<ColorMaterial>>();
+        let materials = app.world.get_resource::<Assets<ColorMaterial>>().unwrap();
+        let textures: HashSet<HandleId> = query
+            .iter(&app.world)
+            .filter_map(|material| materials.get(material)?.texture.as_ref())
+            .map(|texture| texture.id)
+            .collect();
+        // both maps use tiles.png, which is decoded once by the map that was loaded first
+        assert_eq!(textures.len(), 1);
+        let loaded = app.world.get_resource::<Assets<BevyTexture>>().unwrap();
+        assert_eq!(loaded.len(), 1);
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn maps_are_loaded_from_zip_archives() {
+        let mut app = app(TmxPlugin::default());
+        // the tileset and its image only exist within the archive
+        spawn(&mut app, "level.zip");
+
+        let mut roots = app.world.query::<&TileMap>();
+        let tiles = roots.iter(&app.world).next().unwrap();
+        assert_eq!(tiles.layer("ground").unwrap().data, vec![1, 2, 3, 4]);
+
+        let mut query = app.world.query::<&Handle<ColorMaterial>>();
+        let materials = app.world.get_resource::<Assets<ColorMaterial>>().unwrap();
+        let textures = app.world.get_resource::<Assets<BevyTexture>>().unwrap();
+        let sizes: Vec<(u32, u32)> = query
+            .iter(&app.world)
+            .filter_map(|material| materials.get(material)?.texture.as_ref())
+            .map(|texture| {
+                let size = textures.get(texture).unwrap().size;
+                (size.width, size.height)
+            })
+            .collect();
+        assert_eq!(sizes, vec![(32, 32)]);
+    }
+}
@@ -0,0 +1,141 @@
+use bevy_asset::{Assets, Handle};
+use bevy_ecs::{
+    reflect::ReflectComponent,
+    system::{Query, ResMut},
+};
+use bevy_math::{UVec2, Vec2, Vec3};
+use bevy_reflect::{Reflect, TypeUuid};
+use bevy_render::{
+    camera::{Camera, OrthographicProjection},
+    mesh::{Indices, Mesh},
+    pipeline::PrimitiveTopology,
+};
+use bevy_transform::components::{GlobalTransform, Parent, Transform};
+
+use crate::parallax::ParallaxCamera;
+
+/// Component for image layers that repeat their image along one or both axes.
+/// The repeat_image_system keeps enough copies of the image around the camera to fill its viewport,
+///  and moves the entity in steps of whole images so that the copies follow the camera.
+/// The system runs after the parallax_transform_system, so repeating layers can have a `Parallax` as well.
+#[derive(Debug, Default, Clone, TypeUuid, Reflect)]
+#[reflect(Component)]
+#[uuid = "3f8b6c2d-71e4-4a90-b5d3-9c0e2a6f4d18"]
+pub struct RepeatImage {
+    /// Whether the image is repeated along the x axis.
+    pub repeat_x: bool,
+    /// Whether the image is repeated along the y axis.
+    pub repeat_y: bool,
+    /// The size of a single copy of the image, which should match the size of the `Sprite`.
+    pub size: Vec2,
+    /// The number of copies of the image in the current mesh, per axis.
+    pub copies: UVec2,
+}
+
+/// System that updates the mesh and `Transform` of `RepeatImage` entities to cover the camera viewport.
+pub fn repeat_image_system(
+    cameras: Query<(
+        &GlobalTransform,
+        &Camera,
+        Option<&OrthographicProjection>,
+        Option<&ParallaxCamera>,
+    )>,
+    parents: Query<&GlobalTransform>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut images: Query<(
+        &mut RepeatImage,
+        &mut Transform,
+        &mut Handle<Mesh>,
+        Option<&Parent>,
+    )>,
+) {
+    let camera = cameras
+        .iter()
+        .find(|(_, _, _, marker)| marker.is_some())
+        .or_else(|| cameras.iter().next());
+
+    let (camera_transform, projection) = match camera {
+        Some((transform, _, Some(projection), _)) => (transform, projection),
+        _ => return,
+    };
+    let viewport = Vec2::new(
+        projection.right - projection.left,
+        projection.top - projection.bottom,
+    ) * projection.scale
+        * camera_transform.scale.truncate();
+
+    for (mut repeat, mut transform, mut mesh, parent) in images.iter_mut() {
+        let parent = parent
+            .and_then(|parent| parents.get(parent.0).ok())
+            .copied()
+            .unwrap_or_default();
+        let world = parent.mul_transform(*transform);
+        let step = (repeat.size * world.scale.truncate()).abs();
+        if step.x <= 0.0 || step.y <= 0.0 {
+            continue;
+        }
+
+        // Enough copies to cover the viewport when the camera is up to half an image away from the center.
+        let needed = (viewport.abs() / step).ceil() + Vec2::ONE;
+        let copies = UVec2::new(
+            if repeat.repeat_x { needed.x as u32 } else { 1 },
+            if repeat.repeat_y { needed.y as u32 } else { 1 },
+        );
+        if copies != repeat.copies {
+            repeat.copies = copies;
+            *mesh = meshes.add(repeat_mesh(copies));
+        }
+
+        let shift = ((camera_transform.translation - world.translation).truncate() / step).round();
+        let shift = Vec2::new(
+            if repeat.repeat_x { shift.x } else { 0.0 },
+            if repeat.repeat_y { shift.y } else { 0.0 },
+        );
+        if shift != Vec2::ZERO {
+            let local = shift * (repeat.size * transform.scale.truncate()).abs();
+            transform.translation += Vec3::from((local, 0.0));
+        }
+    }
+}
+
+/// Build a mesh with a grid of unit quads centered on the origin, one for every copy of the image.
+fn repeat_mesh(copies: UVec2) -> Mesh {
+    let count = (copies.x * copies.y) as usize;
+    let mut vertices = Vec::with_capacity(count * 4);
+    let mut uvs = Vec::with_capacity(count * 4);
+    let mut indices = Vec::with_capacity(count * 6);
+
+    let half = copies.as_f32() * 0.5;
+    for y in 0..copies.y {
+        for x in 0..copies.x {
+            let left = x as f32 - half.x;
+            let top = half.y - y as f32;
+            let index = vertices.len() as u32;
+            vertices.extend_from_slice(&[
+                [left, top, 0.0],
+                [left + 1.0, top, 0.0],
+                [left, top - 1.0, 0.0],
+                [left + 1.0, top - 1.0, 0.0],
+            ]);
+            uvs.extend_from_slice(&[[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]]);
+            indices.extend_from_slice(&[
+                index,
+                index + 1,
+                index + 2,
+                index + 2,
+                index + 1,
+                index + 3,
+            ]);
+        }
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.set_attribute(
+        Mesh::ATTRIBUTE_NORMAL,
+        vec![[0.0, 0.0, 1.0]; vertices.len()],
+    );
+    mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, vertices);
+    mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
@@ -1,12 +1,17 @@
+// the `Bundle` derive of bevy 0.5 forgets the fields of a bundle after moving them out
+#![allow(clippy::forget_non_drop)]
+
 use std::collections::hash_map::Entry;
 use std::collections::{HashMap, VecDeque};
 use std::iter::FromIterator;
 
 use anyhow::*;
-use bevy_asset::{Handle, LoadContext, LoadedAsset};
+use bevy_asset::{AssetPath, Handle, LoadContext, LoadedAsset};
+use bevy_core::Name;
 use bevy_ecs::{
     bundle::Bundle,
     entity::Entity,
+    query::Changed,
     reflect::ReflectComponent,
     system::{Commands, Query},
     world::{EntityMut, World},
@@ -22,27 +27,73 @@ use bevy_render::{
     texture::Texture,
 };
 use bevy_scene::Scene;
-use bevy_sprite::{ColorMaterial, Sprite, QUAD_HANDLE, SPRITE_PIPELINE_HANDLE};
-use bevy_transform::components::{GlobalTransform, Transform};
+use bevy_sprite::{
+    ColorMaterial, Rect, Sprite, TextureAtlas, TextureAtlasSprite, QUAD_HANDLE,
+    SPRITE_PIPELINE_HANDLE, SPRITE_SHEET_PIPELINE_HANDLE,
+};
+use bevy_transform::{
+    components::{Children, GlobalTransform, Parent, Transform},
+    hierarchy::{BuildWorldChildren, DespawnRecursiveExt},
+};
 
+use crate::animation::{AnimatedTile, AnimationFrame, TileAnimation};
 use crate::parallax::Parallax;
-use crate::tmx::{Layer, Map, Object, Texture as TmxTexture, TexturePtr, Tile};
+use crate::plugin::TextureCache;
+use crate::repeat::RepeatImage;
+use crate::tmx::{
+    Collider, Layer, Map, Object, ObjectKind, Properties, Property, Texture as TmxTexture,
+    TexturePtr, Tile,
+};
+use crate::world::WorldStreaming;
+use crate::ysort::YSort;
 
 pub type ObjectVisitor = dyn for<'w> Fn(&Object, &mut EntityMut<'w>) + Send + Sync;
+pub type TileVisitor = dyn for<'w> Fn(&Tile, u32, IVec2, &mut EntityMut<'w>) + Send + Sync;
+pub type LayerVisitor = dyn for<'w> Fn(&Layer, &mut EntityMut<'w>) + Send + Sync;
 pub type ImageVisitor = dyn for<'w> Fn(&mut EntityMut<'w>) + Send + Sync;
 pub type MapVisitor = dyn for<'w> Fn(&Map, &mut World) + Send + Sync;
 
+/// Settings that control how a `Map` is converted to a `Scene`.
+#[derive(Clone, Copy, Default)]
+pub struct SceneSettings {
+    pub scale: Vec3,
+    pub background: bool,
+    pub tile_uv_inset: f32,
+    pub per_tile_entities: bool,
+    pub y_sort: bool,
+    pub draw_object_rects: bool,
+    pub clamp_parallax: bool,
+    pub json_extension: bool,
+    pub world_streaming: Option<WorldStreaming>,
+}
+
 pub struct SceneBuilder<'a, 'b> {
     world: World,
     context: &'a mut LoadContext<'b>,
     map: &'a Map,
     texture_handles: HashMap<TexturePtr, Handle<Texture>>,
+    textures: TextureCache,
     material_handles: HashMap<(Handle<Texture>, [u8; 4]), Handle<ColorMaterial>>,
-    object_sprites: HashMap<u32, ProtoSpriteBundle>,
+    color_materials: HashMap<[u8; 4], Handle<ColorMaterial>>,
+    object_sprites: HashMap<(u32, [u32; 2], [u32; 4]), ProtoSpriteBundle>,
+    tile_meshes: HashMap<u32, Handle<Mesh>>,
+    tile_animations: HashMap<(u32, [u8; 4]), (Handle<TileAnimation>, AnimationFrame)>,
+    texture_atlases: HashMap<TexturePtr, Handle<TextureAtlas>>,
+    atlas_indices: HashMap<u32, u32>,
+    tile_shapes: HashMap<u32, Option<Handle<TileShapes>>>,
     label_counter: usize,
     offset_z: f32,
     scale: Vec3,
+    /// The origin and bounds of the `Parallax` of entities in layers with a parallax factor.
+    parallax: Parallax,
+    background: bool,
+    tile_uv_inset: f32,
+    per_tile_entities: bool,
+    y_sort: bool,
+    draw_object_rects: bool,
     visit_object: Option<&'a ObjectVisitor>,
+    visit_tile: Option<&'a TileVisitor>,
+    visit_layer: Option<&'a LayerVisitor>,
     visit_image: Option<&'a ImageVisitor>,
     visit_map: Option<&'a MapVisitor>,
 }
@@ -52,6 +103,214 @@ pub struct SceneBuilder<'a, 'b> {
 #[uuid = "39eb4ed0-d44e-4ed5-8676-2e0c148f96c4"]
 pub struct ProtoSprite(Vec2);
 
+#[derive(Debug, Default, Clone, TypeUuid, Reflect)]
+#[reflect(Component)]
+#[uuid = "8d5f07a4-3c0b-4f7e-9a6d-52e1b8c4f0d3"]
+pub struct ProtoAtlasSprite {
+    color: Color,
+    index: u32,
+    flip_x: bool,
+    flip_y: bool,
+}
+
+/// Marker component for the root entity of a loaded map. All layers of the map are spawned as its children,
+///  so the whole map can be moved by changing its `Transform`, or removed with `despawn_map`.
+#[derive(Debug, Default, Clone, Copy, TypeUuid, Reflect)]
+#[reflect(Component)]
+#[uuid = "b7e2d4a9-1f6c-4e83-9c05-d3a8f2b16e47"]
+pub struct MapRoot;
+
+/// Despawn the map with the root entity `root`, together with all of its layers, tiles and objects.
+/// To replace the current level with a new one, despawn all loaded maps before spawning the next scene:
+/// ```ignore
+/// fn unload_maps(mut commands: Commands, roots: Query<Entity, With<MapRoot>>) {
+///     for root in roots.iter() {
+///         despawn_map(&mut commands, root);
+///     }
+/// }
+/// ```
+/// The meshes, materials and textures of the map are labeled assets of the scene, so they are only unloaded
+///  once the `Handle<Scene>` that the map was spawned from is dropped as well.
+pub fn despawn_map(commands: &mut Commands, root: Entity) {
+    commands.entity(root).despawn_recursive();
+}
+
+/// Component on the root entity of a loaded map, with the handle of the `Map` that the scene was built from.
+/// The map stays loaded for as long as the scene is, so it can be used to query the map at runtime:
+/// ```ignore
+/// fn system(maps: Res<Assets<Map>>, roots: Query<&MapHandle>) {
+///     for handle in roots.iter() {
+///         if let Some(map) = maps.get(&handle.0) {
+///             let spawns = map.objects_by_type("spawn");
+///         }
+///     }
+/// }
+/// ```
+#[derive(Debug, Default, Clone, TypeUuid, Reflect)]
+#[reflect(Component)]
+#[uuid = "2c9e5a17-d84b-4f6e-a3c1-e07b9f5d2684"]
+pub struct MapHandle(pub Handle<Map>);
+
+/// Component on the entity of every layer, describing the layer that its children were spawned from.
+/// Group layers have neutral parallax, opacity and visibility, since those are applied to their grouped layers.
+/// Changing `visible` shows or hides the contents of the layer, and for groups the contents of all grouped layers.
+#[derive(Debug, Default, Clone, TypeUuid, Reflect)]
+#[reflect(Component)]
+#[uuid = "a41f7c3e-8d26-4b95-b0e7-2c5d9f183a6b"]
+pub struct LayerMetadata {
+    /// The name of the layer, as set in the map editor.
+    pub name: String,
+    /// Index of the layer, in the order that layers are drawn. Groups are counted as well.
+    pub index: usize,
+    /// Parallax factor of the layer.
+    pub parallax: Vec2,
+    /// Opacity of the layer.
+    pub opacity: f32,
+    /// Whether the layer is visible.
+    pub visible: bool,
+}
+
+/// Marker component for objects that are hidden in the map editor, which stay hidden when their layer is shown.
+#[derive(Debug, Default, Clone, Copy, TypeUuid, Reflect)]
+#[reflect(Component)]
+#[uuid = "5f0b9c2e-7a14-4d3b-8e61-c29d4a7f0b35"]
+pub struct HiddenObject;
+
+/// Component with the custom properties of an object, inserted on every spawned object entity that has any.
+/// The typed lookups of the `Properties` trait are available on it as well.
+#[derive(Debug, Default, Clone, TypeUuid, Reflect)]
+#[reflect_value(Component)]
+#[uuid = "71d3b0e8-4a5c-4f92-8e6d-c1f2a9b7e305"]
+pub struct ObjectProperties(pub HashMap<String, Property>);
+
+impl Properties for ObjectProperties {
+    fn property(&self, name: &str) -> Option<&Property> {
+        self.0.get(name)
+    }
+}
+
+/// Component with the custom properties of the map, inserted on the entity with the `MapRoot` component.
+/// This is a component rather than a resource, because resources of a scene aren't spawned along with its entities.
+#[derive(Debug, Default, Clone, TypeUuid, Reflect)]
+#[reflect_value(Component)]
+#[uuid = "c5a8e2f4-07b9-4d61-92e3-5f1d8b6a4c70"]
+pub struct MapProperties(pub HashMap<String, Property>);
+
+impl Properties for MapProperties {
+    fn property(&self, name: &str) -> Option<&Property> {
+        self.0.get(name)
+    }
+}
+
+/// Component with the tile grids of all tile layers of the map, inserted on the entity with the `MapRoot` component.
+/// This allows gameplay systems to look up tiles without rendering them or holding on to the `Map` asset.
+/// Like `MapProperties`, this is a component because resources of a scene aren't spawned.
+#[derive(Debug, Default, Clone, TypeUuid, Reflect)]
+#[reflect_value(Component)]
+#[uuid = "d6b1f8a3-4c72-4e09-a5d3-8e2f7c0b1964"]
+pub struct TileMap {
+    /// The tile layers of the map in drawing order, including the tile layers within groups.
+    pub layers: Vec<TileMapLayer>,
+}
+
+/// The tile grid of a single tile layer in a `TileMap`.
+#[derive(Debug, Clone)]
+pub struct TileMapLayer {
+    /// The name of the layer, as set in the map editor.
+    pub name: String,
+    /// Position of the first tile in `data`, measured in tiles. Only infinite maps have layers that don't start at zero.
+    pub position: IVec2,
+    /// The amount of tiles in the x and y axis.
+    pub size: UVec2,
+    /// Global tile ids of the tiles in the layer with the flip flags cleared, row by row.
+    /// The tile at (x, y) relative to `position` is at `data[y * size.x + x]`, and empty cells are zero.
+    pub data: Vec<u32>,
+}
+
+impl TileMap {
+    fn new(map: &Map) -> Self {
+        fn collect(layers: &[Layer], result: &mut Vec<TileMapLayer>) {
+            for layer in layers {
+                match layer {
+                    Layer::TileLayer {
+                        name,
+                        position,
+                        size,
+                        data,
+                        ..
+                    } => result.push(TileMapLayer {
+                        name: name.clone(),
+                        position: *position,
+                        size: *size,
+                        data: data.iter().map(|&gid| Map::clear_flip_flags(gid)).collect(),
+                    }),
+                    Layer::Group { layers, .. } => collect(layers, result),
+                    _ => (),
+                }
+            }
+        }
+
+        let mut layers = Vec::new();
+        collect(&map.layers, &mut layers);
+        TileMap { layers }
+    }
+
+    /// Find a tile layer by its name. If multiple layers share the same name, the first one is returned.
+    pub fn layer(&self, name: &str) -> Option<&TileMapLayer> {
+        self.layers.iter().find(|layer| layer.name == name)
+    }
+}
+
+impl TileMapLayer {
+    /// The global tile id at tile coordinate (`x`, `y`), like `Layer::tile_at` but without flip flags.
+    pub fn tile_at(&self, x: i32, y: i32) -> Option<u32> {
+        let x = x - self.position.x;
+        let y = y - self.position.y;
+        if x < 0 || y < 0 || x >= self.size.x as i32 || y >= self.size.y as i32 {
+            return None;
+        }
+        self.data
+            .get((y as u32 * self.size.x + x as u32) as usize)
+            .copied()
+            .filter(|&gid| gid != 0)
+    }
+}
+
+/// Component for tiles that are spawned as individual entities, see `TmxPlugin::per_tile_entities`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, TypeUuid, Reflect)]
+#[reflect(Component)]
+#[uuid = "e4a1c9b7-62f3-4d18-b5a0-7c93d2e6f145"]
+pub struct TilePos {
+    /// Horizontal tile coordinate within the layer.
+    pub x: i32,
+    /// Vertical tile coordinate within the layer.
+    pub y: i32,
+    /// Index of the layer the tile belongs to, in the order that layers are drawn.
+    pub layer: usize,
+}
+
+/// The collision shapes of a tile, shared by all entities that display the same tile.
+#[derive(Debug, Default, Clone, TypeUuid)]
+#[uuid = "9f3e2b61-0c4d-4a7e-8d15-b2c6e8a0f374"]
+pub struct TileShapes {
+    /// The colliders of the tile, with flips applied and scaled to world units.
+    /// The colliders are relative to the top left corner of the tile.
+    pub colliders: Vec<Collider>,
+}
+
+/// Component for tile entities whose tile has collision shapes defined in its tileset.
+/// Tile entities are spawned for animated tiles, or for all tiles when `TmxPlugin::per_tile_entities` is enabled.
+/// For tiles in batched layer meshes, the shapes can be accessed with `Tile::colliders` instead.
+#[derive(Debug, Default, Clone, TypeUuid, Reflect)]
+#[reflect(Component)]
+#[uuid = "3b8d7f0a-5e21-4c96-a4f3-61d0c9e2b857"]
+pub struct TileColliders {
+    /// The collision shapes of the tile.
+    pub shapes: Handle<TileShapes>,
+    /// Position of the top left corner of the tile in world units, ignoring parallax.
+    pub origin: Vec2,
+}
+
 #[derive(Bundle, Clone)]
 struct ProtoSpriteBundle {
     pub sprite: ProtoSprite,
@@ -65,63 +324,194 @@ struct ProtoSpriteBundle {
     pub global_transform: GlobalTransform,
 }
 
+#[derive(Bundle, Clone)]
+struct ProtoAtlasSpriteBundle {
+    pub sprite: ProtoAtlasSprite,
+    pub texture_atlas: Handle<TextureAtlas>,
+    pub mesh: Handle<Mesh>,
+    pub main_pass: MainPass,
+    pub draw: Draw,
+    pub visible: Visible,
+    pub render_pipelines: RenderPipelines,
+    pub transform: Transform,
+    pub global_transform: GlobalTransform,
+}
+
 impl<'a, 'b> SceneBuilder<'a, 'b> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         load_context: &'a mut LoadContext<'b>,
         map: &'a Map,
         visit_object: Option<&'a ObjectVisitor>,
+        visit_tile: Option<&'a TileVisitor>,
+        visit_layer: Option<&'a LayerVisitor>,
         visit_image: Option<&'a ImageVisitor>,
         visit_map: Option<&'a MapVisitor>,
-        scale: Vec3,
+        settings: SceneSettings,
+        textures: TextureCache,
     ) -> Self {
         Self {
             world: World::default(),
             context: load_context,
             map,
             texture_handles: HashMap::default(),
+            textures,
             material_handles: HashMap::default(),
+            color_materials: HashMap::default(),
             object_sprites: HashMap::default(),
+            tile_meshes: HashMap::default(),
+            tile_animations: HashMap::default(),
+            texture_atlases: HashMap::default(),
+            atlas_indices: HashMap::default(),
+            tile_shapes: HashMap::default(),
             label_counter: 0,
             offset_z: 0.0,
             visit_object,
+            visit_tile,
+            visit_layer,
             visit_image,
             visit_map,
-            scale,
+            scale: settings.scale,
+            parallax: Parallax {
+                origin: map.parallax_origin * settings.scale.xy(),
+                bounds: if settings.clamp_parallax {
+                    let bounds = map.pixel_bounds();
+                    Some(bevy_math::Rect {
+                        left: bounds.left * settings.scale.x,
+                        right: bounds.right * settings.scale.x,
+                        top: bounds.top * settings.scale.y,
+                        bottom: bounds.bottom * settings.scale.y,
+                    })
+                } else {
+                    None
+                },
+                ..Parallax::default()
+            },
+            background: settings.background,
+            tile_uv_inset: settings.tile_uv_inset,
+            per_tile_entities: settings.per_tile_entities,
+            y_sort: settings.y_sort,
+            draw_object_rects: settings.draw_object_rects,
         }
     }
 
     pub async fn build(mut self) -> Result<Scene> {
-        let mut layer_queue = VecDeque::from_iter(self.map.layers.iter());
-        while let Some(layer) = layer_queue.pop_front() {
+        let mut layer_entities = Vec::new();
+        if self.background {
+            layer_entities.push(self.spawn_background());
+            self.offset_z += self.scale.z;
+        }
+
+        let mut layer_index = 0;
+        // layers are queued along with the group entity they should be parented to, if any
+        let mut layer_queue =
+            VecDeque::from_iter(self.map.layers.iter().map(|layer| (layer, None)));
+        while let Some((layer, parent)) = layer_queue.pop_front() {
+            let mut children = Vec::new();
             match layer {
                 Layer::TileLayer {
                     position,
                     size,
                     color,
-                    visible: _,
+                    opacity,
+                    visible,
                     offset,
                     parallax,
                     data,
+                    ..
                 } => {
+                    let color = &with_opacity(color, *opacity);
                     let mut images_to_meshes =
                         HashMap::<TexturePtr, (Handle<ColorMaterial>, Vec<_>)>::new();
 
-                    for (i, &gid) in data.iter().enumerate() {
-                        if let Some(&Tile {
-                            image: Some(ref image),
-                            top_left,
-                            bottom_right,
-                            width: tile_width,
-                            height: tile_height,
-                            ..
-                        }) = self.map.get_tile(gid)
+                    let render_order = self.map.tile_type.render_order();
+                    for (n, i) in render_order.tile_indices(size.x, size.y).enumerate() {
+                        let gid = data.get(i).copied().unwrap_or(0);
+                        if let Some(
+                            tile @ &Tile {
+                                image: Some(ref image),
+                                top_left,
+                                bottom_right,
+                                width: tile_width,
+                                height: tile_height,
+                                ref animation,
+                                ..
+                            },
+                        ) = self.map.get_tile(gid)
                         {
-                            let (x, y) = self.map.tile_type.coord_to_pos(
-                                size.y as i32,
+                            let coord = IVec2::new(
                                 (i as i32 % size.x as i32) + position.x,
                                 (i as i32 / size.x as i32) + position.y,
                             );
-                            let tile = (x, y, tile_width, tile_height, top_left, bottom_right);
+                            let (x, y) =
+                                self.map
+                                    .tile_type
+                                    .coord_to_pos(size.y as i32, coord.x, coord.y);
+                            let (x, y) = match self.map.get_tileset(gid) {
+                                Some(tileset) => {
+                                    (x + tileset.tile_offset.x, y + tileset.tile_offset.y)
+                                }
+                                None => (x, y),
+                            };
+                            let tile_pos = TilePos {
+                                x: coord.x,
+                                y: coord.y,
+                                layer: layer_index,
+                            };
+                            // tiles drawn later are moved slightly forward within the layer, so tiles
+                            //  that overlap their neighbours, like tall isometric tiles, sort by render order
+                            let depth = (n as f32 / data.len() as f32) * self.scale.z;
+                            if !animation.is_empty() {
+                                let position = IVec2::new(x, y) + *offset;
+                                let z = self.offset_z + depth;
+                                let entity = self
+                                    .spawn_animated_tile(gid, position, z, color, parallax)
+                                    .await?;
+                                if let Some(entity) = entity {
+                                    children.push(entity);
+                                    let colliders = self.tile_colliders(gid, tile, position);
+                                    let mut entity = self.world.entity_mut(entity);
+                                    if let Some(colliders) = colliders {
+                                        entity.insert(colliders);
+                                    }
+                                    if self.per_tile_entities {
+                                        entity.insert(tile_pos);
+                                    }
+                                    if let Some(mut visibility) = entity.get_mut::<Visible>() {
+                                        visibility.is_visible = *visible;
+                                    }
+                                    if let Some(handler) = self.visit_tile.as_ref() {
+                                        (*handler)(tile, gid, coord, &mut entity);
+                                    }
+                                }
+                                continue;
+                            }
+                            if self.per_tile_entities {
+                                let position = IVec2::new(x, y) + *offset;
+                                let z = self.offset_z + depth;
+                                let entity = self
+                                    .spawn_tile_sprite(
+                                        gid, tile, image, position, z, color, parallax,
+                                    )
+                                    .await?;
+                                children.push(entity);
+                                let colliders = self.tile_colliders(gid, tile, position);
+                                let mut entity = self.world.entity_mut(entity);
+                                entity.insert(tile_pos);
+                                if let Some(colliders) = colliders {
+                                    entity.insert(colliders);
+                                }
+                                if let Some(mut visibility) = entity.get_mut::<Visible>() {
+                                    visibility.is_visible = *visible;
+                                }
+                                if let Some(handler) = self.visit_tile.as_ref() {
+                                    (*handler)(tile, gid, coord, &mut entity);
+                                }
+                                continue;
+                            }
+                            let inset = self.uv_inset(image);
+                            let uvs = flipped_uvs(top_left + inset, bottom_right - inset, gid);
+                            let tile = (x, y, depth, tile_width, tile_height, uvs);
                             match images_to_meshes.entry(TexturePtr::from(image)) {
                                 Entry::Occupied(mut value) => value.get_mut().1.push(tile),
                                 vacant => {
@@ -139,31 +529,36 @@ impl<'a, 'b> SceneBuilder<'a, 'b> {
                         let mut uvs = Vec::with_capacity(tiles.len() * 4);
                         let mut indices = Vec::with_capacity(tiles.len() * 6);
 
-                        for (x, y, w, h, top_left, bottom_right) in tiles {
-                            let i = vertices.len() as u16;
+                        for (x, y, z, w, h, tile_uvs) in tiles {
+                            let i = vertices.len() as u32;
                             indices.extend_from_slice(&[i, i + 1, i + 2, i + 2, i + 1, i + 3]);
 
-                            vertices.push([x as f32, y as f32, 0.0]);
-                            vertices.push([(x + w) as f32, y as f32, 0.0]);
-                            vertices.push([x as f32, (y + h) as f32, 0.0]);
-                            vertices.push([(x + w) as f32, (y + h) as f32, 0.0]);
+                            vertices.push([x as f32, y as f32, z]);
+                            vertices.push([(x + w) as f32, y as f32, z]);
+                            vertices.push([x as f32, (y + h) as f32, z]);
+                            vertices.push([(x + w) as f32, (y + h) as f32, z]);
 
                             normals.push([0.0, 0.0, 1.0]);
                             normals.push([0.0, 0.0, 1.0]);
                             normals.push([0.0, 0.0, 1.0]);
                             normals.push([0.0, 0.0, 1.0]);
 
-                            uvs.push([top_left.x, top_left.y]);
-                            uvs.push([bottom_right.x, top_left.y]);
-                            uvs.push([top_left.x, bottom_right.y]);
-                            uvs.push([bottom_right.x, bottom_right.y]);
+                            uvs.extend_from_slice(&tile_uvs);
                         }
 
+                        let vertex_count = vertices.len();
                         let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
                         mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, vertices);
                         mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
                         mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
-                        mesh.set_indices(Some(Indices::U16(indices)));
+                        // Only fall back to 32 bit indices if the vertices can't be addressed with 16 bits.
+                        if vertex_count > u16::MAX as usize + 1 {
+                            mesh.set_indices(Some(Indices::U32(indices)));
+                        } else {
+                            mesh.set_indices(Some(Indices::U16(
+                                indices.into_iter().map(|i| i as u16).collect(),
+                            )));
+                        }
                         self.label_counter += 1;
                         let mesh = self.context.set_labeled_asset(
                             format!("mesh#{}", self.label_counter).as_str(),
@@ -171,6 +566,7 @@ impl<'a, 'b> SceneBuilder<'a, 'b> {
                         );
 
                         let mut entity = self.world.spawn();
+                        children.push(entity.id());
                         let transform = Transform::from_xyz(
                             offset.x as f32 * self.scale.x,
                             offset.y as f32 * self.scale.y,
@@ -181,10 +577,18 @@ impl<'a, 'b> SceneBuilder<'a, 'b> {
                             mesh,
                             material,
                             transform,
+                            visible: Visible {
+                                is_transparent: true,
+                                is_visible: *visible,
+                            },
                             ..ProtoSpriteBundle::default()
                         });
                         if parallax != &Vec2::new(1.0, 1.0) {
-                            entity.insert(Parallax::new(*parallax, transform));
+                            entity.insert(Parallax {
+                                factor: *parallax,
+                                transform,
+                                ..self.parallax.clone()
+                            });
                         }
                     }
                 }
@@ -195,23 +599,80 @@ impl<'a, 'b> SceneBuilder<'a, 'b> {
                     parallax,
                     visible,
                     color,
+                    opacity,
+                    draworder_index,
+                    object_color,
                     ..
                 } => {
-                    for (i, object) in objects.iter().enumerate() {
-                        let object_sprite = if let Some(gid) = object.tile {
-                            self.object_sprite(gid, color).await?
+                    let color = &with_opacity(color, *opacity);
+                    // With topdown draw order objects further down the map are drawn in front,
+                    //  objects at the same height keep their order of appearance.
+                    let mut order = Vec::from_iter(0..objects.len());
+                    if !draworder_index {
+                        order.sort_by(|&a, &b| {
+                            objects[a]
+                                .y
+                                .partial_cmp(&objects[b].y)
+                                .unwrap_or(std::cmp::Ordering::Equal)
+                        });
+                    }
+                    let mut depths = vec![0; objects.len()];
+                    for (depth, &i) in order.iter().enumerate() {
+                        depths[i] = depth;
+                    }
+
+                    for (object, depth) in objects.iter().zip(depths) {
+                        let object_sprite = if let Some(gid) = object.tile() {
+                            let size = Vec2::new(object.width, object.height);
+                            self.object_sprite(gid, size, color).await?
+                        } else {
+                            None
+                        };
+                        let object_rect = if self.draw_object_rects
+                            && matches!(object.kind, ObjectKind::Rectangle | ObjectKind::Ellipse)
+                        {
+                            Some(self.object_rect(object, &(*color * *object_color)))
                         } else {
                             None
                         };
 
                         let mut entity = self.world.spawn();
+                        children.push(entity.id());
 
                         let mut transform = Transform::from_xyz(
                             (offset.x as f32 + object.x) * self.scale.x,
                             (offset.y as f32 + object.y) * self.scale.y,
-                            self.offset_z as f32 + (i as f32 / objects.len() as f32) * self.scale.z,
+                            self.offset_z + (depth as f32 / objects.len() as f32) * self.scale.z,
                         );
-                        transform.rotation = Quat::from_rotation_z(-object.rotation.to_radians());
+                        // tiled rotates clockwise, which is a negative angle when the y axis is flipped
+                        let handedness = (self.scale.x * self.scale.y).signum();
+                        transform.rotation =
+                            Quat::from_rotation_z(handedness * object.rotation.to_radians());
+
+                        // A `z` property places the object at that depth in front of the map root, as is,
+                        //  so it isn't multiplied with the z scale and it isn't sorted by y either.
+                        let z = object.properties.get("z").and_then(Property::as_float);
+                        if let Some(z) = z {
+                            transform.translation.z = z as f32;
+                        }
+
+                        let y_sort = if self.y_sort
+                            && !draworder_index
+                            && object_sprite.is_some()
+                            && z.is_none()
+                        {
+                            let bounds = self.map.pixel_bounds();
+                            let sort = YSort::new(
+                                self.offset_z,
+                                self.scale.z,
+                                (offset.y as f32 + bounds.top) * self.scale.y,
+                                (bounds.bottom - bounds.top) * self.scale.y,
+                            );
+                            transform.translation.z = sort.z(transform.translation.y);
+                            Some(sort)
+                        } else {
+                            None
+                        };
 
                         if let Some(object_sprite) = object_sprite {
                             entity.insert_bundle(ProtoSpriteBundle {
@@ -225,12 +686,41 @@ impl<'a, 'b> SceneBuilder<'a, 'b> {
                                 },
                                 ..object_sprite
                             });
+                        } else if let Some(object_rect) = object_rect {
+                            entity.insert_bundle(ProtoSpriteBundle {
+                                transform,
+                                visible: Visible {
+                                    is_transparent: true,
+                                    is_visible: *visible && object.visible,
+                                },
+                                ..object_rect
+                            });
                         } else {
                             entity.insert_bundle((transform, GlobalTransform::default()));
                         }
 
                         if parallax != &Vec2::new(1.0, 1.0) {
-                            entity.insert(Parallax::new(*parallax, transform));
+                            entity.insert(Parallax {
+                                factor: *parallax,
+                                transform,
+                                ..self.parallax.clone()
+                            });
+                        }
+
+                        if let Some(y_sort) = y_sort {
+                            entity.insert(y_sort);
+                        }
+
+                        if !object.name.is_empty() {
+                            entity.insert(Name::new(object.name.clone()));
+                        }
+
+                        if !object.visible {
+                            entity.insert(HiddenObject);
+                        }
+
+                        if !object.properties.is_empty() {
+                            entity.insert(ObjectProperties(object.properties.clone()));
                         }
 
                         if let Some(handler) = self.visit_object.as_ref() {
@@ -241,11 +731,16 @@ impl<'a, 'b> SceneBuilder<'a, 'b> {
 
                 Layer::ImageLayer {
                     color,
-                    visible: _,
+                    opacity,
+                    visible,
                     offset,
                     parallax,
                     image,
+                    repeat_x,
+                    repeat_y,
+                    ..
                 } => {
+                    let color = &with_opacity(color, *opacity);
                     let texture = self.texture_handle(image).await?;
                     let material = self.texture_material_handle(texture, color);
                     let transform = Transform::from_xyz(
@@ -254,45 +749,446 @@ impl<'a, 'b> SceneBuilder<'a, 'b> {
                         self.offset_z,
                     );
 
+                    let size =
+                        Vec2::new(image.width() as f32, image.height() as f32) * self.scale.xy();
+
                     let mut entity = self.world.spawn();
+                    children.push(entity.id());
                     entity.insert_bundle(ProtoSpriteBundle {
-                        sprite: ProtoSprite(
-                            Vec2::new(image.width() as f32, image.height() as f32)
-                                * self.scale.xy(),
-                        ),
+                        sprite: ProtoSprite(size),
                         material,
                         transform,
+                        visible: Visible {
+                            is_transparent: true,
+                            is_visible: *visible,
+                        },
                         ..ProtoSpriteBundle::default()
                     });
                     if parallax != &Vec2::new(1.0, 1.0) {
-                        entity.insert(Parallax::new(*parallax, transform));
+                        entity.insert(Parallax {
+                            factor: *parallax,
+                            transform,
+                            ..self.parallax.clone()
+                        });
+                    }
+                    if *repeat_x || *repeat_y {
+                        entity.insert(RepeatImage {
+                            repeat_x: *repeat_x,
+                            repeat_y: *repeat_y,
+                            size,
+                            ..RepeatImage::default()
+                        });
                     }
                     if let Some(handler) = self.visit_image.as_ref() {
                         (*handler)(&mut entity);
                     }
                 }
 
-                Layer::Group { layers } => {
-                    for layer in layers.iter().rev() {
-                        layer_queue.push_front(layer);
-                    }
+                Layer::Group { .. } => (),
+            }
+
+            let entity = self.spawn_layer(layer, layer_index, &children);
+            if let Layer::Group { layers, .. } = layer {
+                for layer in layers.iter().rev() {
+                    layer_queue.push_front((layer, Some(entity)));
                 }
             }
+            match parent {
+                Some(parent) => {
+                    self.world.entity_mut(parent).push_children(&[entity]);
+                }
+                None => layer_entities.push(entity),
+            }
 
             self.offset_z += self.scale.z;
+            layer_index += 1;
+        }
+
+        let mut root = self.world.spawn();
+        root.insert_bundle((MapRoot, Transform::default(), GlobalTransform::default()))
+            .push_children(&layer_entities);
+        // the map itself is added to the load context as the "map" labeled asset by the loader
+        let map_path = AssetPath::new_ref(self.context.path(), Some("map"));
+        root.insert(MapHandle(self.context.get_handle(map_path)));
+        if !self.map.properties.is_empty() {
+            root.insert(MapProperties(self.map.properties.clone()));
         }
+        root.insert(TileMap::new(self.map));
 
         if let Some(visit_map) = self.visit_map {
-            (*visit_map)(&self.map, &mut self.world);
+            (*visit_map)(self.map, &mut self.world);
         }
 
         Ok(Scene::new(self.world))
     }
 
+    /// Spawn the entity for a layer, with the entities spawned for the contents of the layer as its children.
+    /// Groups are offset by their own transform, so that it applies to all of the grouped layers.
+    fn spawn_layer(&mut self, layer: &Layer, index: usize, children: &[Entity]) -> Entity {
+        let transform = match layer {
+            Layer::Group { offset, .. } => Transform::from_xyz(
+                offset.x as f32 * self.scale.x,
+                offset.y as f32 * self.scale.y,
+                0.0,
+            ),
+            _ => Transform::default(),
+        };
+        let (parallax, opacity, visible) = match layer {
+            Layer::TileLayer {
+                parallax,
+                opacity,
+                visible,
+                ..
+            }
+            | Layer::ObjectLayer {
+                parallax,
+                opacity,
+                visible,
+                ..
+            }
+            | Layer::ImageLayer {
+                parallax,
+                opacity,
+                visible,
+                ..
+            } => (*parallax, *opacity, *visible),
+            Layer::Group { .. } => (Vec2::ONE, 1.0, true),
+        };
+        let metadata = LayerMetadata {
+            name: layer.name().to_string(),
+            index,
+            parallax,
+            opacity,
+            visible,
+        };
+
+        let mut entity = self.world.spawn();
+        entity
+            .insert_bundle((transform, GlobalTransform::default(), metadata))
+            .push_children(children);
+        if !layer.name().is_empty() {
+            entity.insert(Name::new(layer.name().to_string()));
+        }
+        if let Some(handler) = self.visit_layer.as_ref() {
+            (*handler)(layer, &mut entity);
+        }
+        entity.id()
+    }
+
+    fn spawn_background(&mut self) -> Entity {
+        let [a, r, g, b] = self.map.background;
+        self.label_counter += 1;
+        let material = self.context.set_labeled_asset(
+            format!("material#{}", self.label_counter).as_str(),
+            LoadedAsset::new(ColorMaterial::color(Color::rgba_u8(r, g, b, a))),
+        );
+
+        let bounds = self.map.pixel_bounds();
+        let min = Vec2::new(bounds.left, bounds.top);
+        let max = Vec2::new(bounds.right, bounds.bottom);
+        let center = (min + max) * 0.5;
+        self.world
+            .spawn()
+            .insert_bundle(ProtoSpriteBundle {
+                sprite: ProtoSprite((max - min) * self.scale.xy()),
+                material,
+                transform: Transform::from_translation(
+                    (center * self.scale.xy()).extend(self.offset_z),
+                ),
+                ..ProtoSpriteBundle::default()
+            })
+            .id()
+    }
+
+    async fn spawn_animated_tile(
+        &mut self,
+        gid: u32,
+        position: IVec2,
+        z: f32,
+        color: &Vec4,
+        parallax: &Vec2,
+    ) -> Result<Option<Entity>> {
+        let (animation, first_frame) = match self.tile_animation(gid, color).await? {
+            Some(animation) => animation,
+            None => return Ok(None),
+        };
+
+        let transform = Transform::from_xyz(
+            position.x as f32 * self.scale.x,
+            position.y as f32 * self.scale.y,
+            z,
+        );
+
+        let mut entity = self.world.spawn();
+        entity.insert_bundle(ProtoSpriteBundle {
+            sprite: ProtoSprite(self.scale.xy()),
+            mesh: first_frame.mesh,
+            material: first_frame.material,
+            transform,
+            ..ProtoSpriteBundle::default()
+        });
+        entity.insert(AnimatedTile {
+            animation,
+            ..AnimatedTile::default()
+        });
+        if parallax != &Vec2::new(1.0, 1.0) {
+            entity.insert(Parallax {
+                factor: *parallax,
+                transform,
+                ..self.parallax.clone()
+            });
+        }
+
+        Ok(Some(entity.id()))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn spawn_tile_sprite(
+        &mut self,
+        gid: u32,
+        tile: &Tile,
+        image: &TmxTexture,
+        position: IVec2,
+        z: f32,
+        color: &Vec4,
+        parallax: &Vec2,
+    ) -> Result<Entity> {
+        let texture_atlas = self.texture_atlas(gid, image).await?;
+        let index = self
+            .atlas_indices
+            .get(&Map::clear_flip_flags(gid))
+            .copied()
+            .unwrap_or(0);
+
+        // Texture atlas sprites can only be flipped horizontally and vertically,
+        //  so diagonal flips are expressed as a 90 degree rotation combined with a flip.
+        let mut flip_x = gid & Map::FLIPPED_HORIZONTALLY != 0;
+        let flip_y = gid & Map::FLIPPED_VERTICALLY != 0;
+        let mut rotation = Quat::IDENTITY;
+        if gid & Map::FLIPPED_DIAGONALLY != 0 {
+            flip_x = !flip_x;
+            let angle = if flip_x != flip_y {
+                std::f32::consts::FRAC_PI_2
+            } else {
+                -std::f32::consts::FRAC_PI_2
+            };
+            rotation = Quat::from_rotation_z(angle);
+        }
+
+        let center = position.as_f32() + Vec2::new(tile.width as f32, tile.height as f32) * 0.5;
+        let transform = Transform {
+            translation: (center * self.scale.xy()).extend(z),
+            rotation,
+            scale: Vec3::new(self.scale.x, -self.scale.y, 1.0),
+        };
+
+        let mut entity = self.world.spawn();
+        entity.insert_bundle(ProtoAtlasSpriteBundle {
+            sprite: ProtoAtlasSprite {
+                color: Color::from(*color),
+                index,
+                flip_x,
+                flip_y,
+            },
+            texture_atlas,
+            transform,
+            ..ProtoAtlasSpriteBundle::default()
+        });
+        if parallax != &Vec2::new(1.0, 1.0) {
+            entity.insert(Parallax {
+                factor: *parallax,
+                transform,
+                ..self.parallax.clone()
+            });
+        }
+
+        Ok(entity.id())
+    }
+
+    /// Get the `TileColliders` for a tile at `position`, or `None` if the tile has no collision shapes.
+    fn tile_colliders(&mut self, gid: u32, tile: &Tile, position: IVec2) -> Option<TileColliders> {
+        let scale = self.scale.xy();
+        let shapes = match self.tile_shapes.entry(gid) {
+            Entry::Occupied(value) => value.get().clone(),
+            vacant => {
+                let size = Vec2::new(tile.width as f32, tile.height as f32);
+                let colliders: Vec<Collider> = tile
+                    .colliders()
+                    .map(|collider| flipped_collider(&collider, size, gid).scaled(scale))
+                    .collect();
+                let shapes = if colliders.is_empty() {
+                    None
+                } else {
+                    self.label_counter += 1;
+                    Some(self.context.set_labeled_asset(
+                        format!("shapes#{}", self.label_counter).as_str(),
+                        LoadedAsset::new(TileShapes { colliders }),
+                    ))
+                };
+                vacant.or_insert(shapes).clone()
+            }
+        }?;
+
+        Some(TileColliders {
+            shapes,
+            origin: position.as_f32() * scale,
+        })
+    }
+
+    /// Get the texture atlas for `image`, creating it from the tileset of `gid` if it doesn't exist yet.
+    async fn texture_atlas(
+        &mut self,
+        gid: u32,
+        image: &TmxTexture,
+    ) -> Result<Handle<TextureAtlas>> {
+        if let Some(texture_atlas) = self.texture_atlases.get(&TexturePtr::from(image)) {
+            return Ok(texture_atlas.clone());
+        }
+
+        let texture = self.texture_handle(image).await?;
+        let size = Vec2::new(image.width() as f32, image.height() as f32);
+        let mut texture_atlas = TextureAtlas::new_empty(texture, size);
+        if let Some(tileset) = self.map.get_tileset(gid) {
+            for (id, tile) in tileset.tiles.iter().enumerate() {
+                if let Some(Tile {
+                    image: Some(tile_image),
+                    top_left,
+                    bottom_right,
+                    width,
+                    height,
+                    ..
+                }) = tile
+                {
+                    if TexturePtr::from(tile_image) == TexturePtr::from(image) {
+                        // images that don't know their own size contain exactly one tile
+                        let size = if size.x > 0.0 && size.y > 0.0 {
+                            size
+                        } else {
+                            Vec2::new(*width as f32, *height as f32)
+                        };
+                        self.atlas_indices.insert(
+                            tileset.first_gid + id as u32,
+                            texture_atlas.textures.len() as u32,
+                        );
+                        texture_atlas.add_texture(Rect {
+                            min: *top_left * size,
+                            max: *bottom_right * size,
+                        });
+                    }
+                }
+            }
+        }
+
+        self.label_counter += 1;
+        let texture_atlas = self.context.set_labeled_asset(
+            format!("atlas#{}", self.label_counter).as_str(),
+            LoadedAsset::new(texture_atlas),
+        );
+        self.texture_atlases
+            .insert(TexturePtr::from(image), texture_atlas.clone());
+
+        Ok(texture_atlas)
+    }
+
+    async fn tile_animation(
+        &mut self,
+        gid: u32,
+        color: &Vec4,
+    ) -> Result<Option<(Handle<TileAnimation>, AnimationFrame)>> {
+        let key = (gid, color_u8(color));
+        if let Some((animation, first_frame)) = self.tile_animations.get(&key) {
+            return Ok(Some((animation.clone(), first_frame.clone())));
+        }
+
+        let (tileset, tile) = match (self.map.get_tileset(gid), self.map.get_tile(gid)) {
+            (Some(tileset), Some(tile)) => (tileset, tile),
+            _ => return Ok(None),
+        };
+
+        // frames refer to tiles within the same tileset, and are flipped the same way as the animated tile.
+        let flip_flags = gid ^ Map::clear_flip_flags(gid);
+        let mut frames = Vec::with_capacity(tile.animation.len());
+        for frame in tile.animation.iter() {
+            let frame_gid = (tileset.first_gid + frame.tile) | flip_flags;
+            let frame_tile = match self.map.get_tile(frame_gid) {
+                Some(frame_tile) => frame_tile,
+                None => continue,
+            };
+            let image = match frame_tile.image.as_ref() {
+                Some(image) => image,
+                None => continue,
+            };
+
+            let texture = self.texture_handle(image).await?;
+            frames.push(AnimationFrame {
+                mesh: self.tile_mesh(frame_gid, frame_tile),
+                material: self.texture_material_handle(texture, color),
+                duration: frame.duration as f32 / 1000.0,
+            });
+        }
+
+        let first_frame = match frames.first() {
+            Some(frame) => frame.clone(),
+            None => return Ok(None),
+        };
+
+        self.label_counter += 1;
+        let animation = self.context.set_labeled_asset(
+            format!("animation#{}", self.label_counter).as_str(),
+            LoadedAsset::new(TileAnimation { frames }),
+        );
+        self.tile_animations
+            .insert(key, (animation.clone(), first_frame.clone()));
+
+        Ok(Some((animation, first_frame)))
+    }
+
+    fn tile_mesh(&mut self, gid: u32, tile: &Tile) -> Handle<Mesh> {
+        if let Some(mesh) = self.tile_meshes.get(&gid) {
+            return mesh.clone();
+        }
+
+        let (w, h) = (tile.width as f32, tile.height as f32);
+        let inset = tile
+            .image
+            .as_ref()
+            .map_or(Vec2::ZERO, |image| self.uv_inset(image));
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            vec![[0.0, 0.0, 0.0], [w, 0.0, 0.0], [0.0, h, 0.0], [w, h, 0.0]],
+        );
+        mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0.0, 0.0, 1.0]; 4]);
+        mesh.set_attribute(
+            Mesh::ATTRIBUTE_UV_0,
+            flipped_uvs(tile.top_left + inset, tile.bottom_right - inset, gid).to_vec(),
+        );
+        mesh.set_indices(Some(Indices::U16(vec![0, 1, 2, 2, 1, 3])));
+        self.label_counter += 1;
+        let mesh = self.context.set_labeled_asset(
+            format!("tile#{}", self.label_counter).as_str(),
+            LoadedAsset::new(mesh),
+        );
+
+        self.tile_meshes.insert(gid, mesh.clone());
+        mesh
+    }
+
+    /// The UV offset that corresponds to `tile_uv_inset` texels in `image`.
+    fn uv_inset(&self, image: &TmxTexture) -> Vec2 {
+        if image.width() > 0 && image.height() > 0 {
+            Vec2::splat(self.tile_uv_inset) / Vec2::new(image.width() as f32, image.height() as f32)
+        } else {
+            Vec2::ZERO
+        }
+    }
+
     async fn texture_handle(&mut self, image: &TmxTexture) -> Result<Handle<Texture>> {
         let handle: Handle<Texture> = match self.texture_handles.entry(TexturePtr::from(image)) {
             Entry::Occupied(value) => value.get().clone(),
-            vacant => vacant.or_insert(image.load(self.context).await?).clone(),
+            vacant => vacant
+                .or_insert(self.textures.load(image, self.context).await?)
+                .clone(),
         };
         Ok(handle)
     }
@@ -306,15 +1202,8 @@ impl<'a, 'b> SceneBuilder<'a, 'b> {
         let label_counter = &mut self.label_counter;
         let context = &mut *self.context;
 
-        let color_u8 = [
-            (color.x * 255.0) as u8,
-            (color.y * 255.0) as u8,
-            (color.z * 255.0) as u8,
-            (color.w * 255.0) as u8,
-        ];
-
         material_handles
-            .entry((texture.clone(), color_u8))
+            .entry((texture.clone(), color_u8(color)))
             .or_insert_with(|| {
                 *label_counter += 1;
                 context.set_labeled_asset(
@@ -328,15 +1217,64 @@ impl<'a, 'b> SceneBuilder<'a, 'b> {
             .clone()
     }
 
-    async fn object_sprite(&mut self, gid: u32, color: &Vec4) -> Result<Option<ProtoSpriteBundle>> {
-        if self.object_sprites.contains_key(&gid) {
-            Ok(self.object_sprites.get(&gid).cloned())
+    /// A translucent quad in the shape of a rectangle or ellipse object, to make it visible.
+    fn object_rect(&mut self, object: &Object, color: &Vec4) -> ProtoSpriteBundle {
+        let color = *color * Vec4::new(1.0, 1.0, 1.0, 0.5);
+        let color_materials = &mut self.color_materials;
+        let label_counter = &mut self.label_counter;
+        let context = &mut *self.context;
+        let material = color_materials
+            .entry(color_u8(&color))
+            .or_insert_with(|| {
+                *label_counter += 1;
+                context.set_labeled_asset(
+                    format!("material#{}", *label_counter).as_str(),
+                    LoadedAsset::new(ColorMaterial::color(Color::from(color))),
+                )
+            })
+            .clone();
+
+        self.label_counter += 1;
+        let mesh = self.context.set_labeled_asset(
+            format!("object#{}", self.label_counter).as_str(),
+            LoadedAsset::new(object.to_mesh(self.scale.xy())),
+        );
+
+        ProtoSpriteBundle {
+            sprite: ProtoSprite(Vec2::ONE),
+            mesh,
+            material,
+            ..ProtoSpriteBundle::default()
+        }
+    }
+
+    async fn object_sprite(
+        &mut self,
+        gid: u32,
+        size: Vec2,
+        color: &Vec4,
+    ) -> Result<Option<ProtoSpriteBundle>> {
+        let tile = if let Some(tile) = self.map.get_tile(gid) {
+            tile
+        } else {
+            return Ok(None);
+        };
+        // the part of the object that is covered by the image, objects with the same fit and color share a sprite
+        let fit = match self.map.get_tileset(gid) {
+            Some(tileset) => tileset
+                .fill_mode
+                .fit(Vec2::new(tile.width as f32, tile.height as f32), size),
+            None => Vec2::ONE,
+        };
+        let key = (
+            gid,
+            [fit.x.to_bits(), fit.y.to_bits()],
+            <[f32; 4]>::from(*color).map(f32::to_bits),
+        );
+
+        if self.object_sprites.contains_key(&key) {
+            Ok(self.object_sprites.get(&key).cloned())
         } else {
-            let tile = if let Some(tile) = self.map.get_tile(gid) {
-                tile
-            } else {
-                return Ok(None);
-            };
             let image = if let Some(image) = tile.image.as_ref() {
                 image
             } else {
@@ -345,25 +1283,37 @@ impl<'a, 'b> SceneBuilder<'a, 'b> {
 
             let texture = self.texture_handle(image).await?;
             let material = self.texture_material_handle(texture, color);
+            let (top_left, bottom_right) = (
+                tile.top_left + self.uv_inset(image),
+                tile.bottom_right - self.uv_inset(image),
+            );
+            // Tiled anchors tile objects at the alignment point of their tileset and rotates them around it,
+            //  so the quad is placed with that point at the entity's origin. Once the sprite
+            //  size (which has the flipped y scale) is applied, rotating the entity pivots around that point.
+            let anchor = match self.map.get_tileset(gid) {
+                Some(tileset) => tileset.object_alignment.anchor(&self.map.tile_type),
+                None => Vec2::new(0.0, 1.0),
+            };
+            // letterboxed images are centered within the bounds of the object
+            let (left, top) = (
+                (1.0 - fit.x) * 0.5 - anchor.x,
+                (1.0 - fit.y) * 0.5 - anchor.y,
+            );
+            let (right, bottom) = (left + fit.x, top + fit.y);
             let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
             mesh.set_attribute(
                 Mesh::ATTRIBUTE_POSITION,
                 vec![
-                    [0.0, -1.0, 0.0],
-                    [1.0, -1.0, 0.0],
-                    [0.0, 0.0, 0.0],
-                    [1.0, 0.0, 0.0],
+                    [left, top, 0.0],
+                    [right, top, 0.0],
+                    [left, bottom, 0.0],
+                    [right, bottom, 0.0],
                 ],
             );
             mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0.0, 0.0, 1.0]; 4]);
             mesh.set_attribute(
                 Mesh::ATTRIBUTE_UV_0,
-                vec![
-                    [tile.top_left.x, tile.top_left.y],
-                    [tile.bottom_right.x, tile.top_left.y],
-                    [tile.top_left.x, tile.bottom_right.y],
-                    [tile.bottom_right.x, tile.bottom_right.y],
-                ],
+                flipped_uvs(top_left, bottom_right, gid).to_vec(),
             );
             mesh.set_indices(Some(Indices::U16(vec![0, 1, 2, 2, 1, 3])));
             self.label_counter += 1;
@@ -374,7 +1324,7 @@ impl<'a, 'b> SceneBuilder<'a, 'b> {
 
             Ok(Some(
                 self.object_sprites
-                    .entry(gid)
+                    .entry(key)
                     .or_insert(ProtoSpriteBundle {
                         sprite: ProtoSprite(self.scale.xy()),
                         mesh,
@@ -408,6 +1358,173 @@ impl Default for ProtoSpriteBundle {
     }
 }
 
+/// The tint color of a layer combined with its opacity, to multiply the contents of the layer with.
+fn with_opacity(color: &Vec4, opacity: f32) -> Vec4 {
+    *color * Vec4::new(1.0, 1.0, 1.0, opacity)
+}
+
+fn color_u8(color: &Vec4) -> [u8; 4] {
+    [
+        (color.x * 255.0) as u8,
+        (color.y * 255.0) as u8,
+        (color.z * 255.0) as u8,
+        (color.w * 255.0) as u8,
+    ]
+}
+
+/// Compute the UVs for the four corners of a tile quad (top left, top right, bottom left, bottom right),
+///  taking the flip flags stored in the gid into account.
+fn flipped_uvs(top_left: Vec2, bottom_right: Vec2, gid: u32) -> [[f32; 2]; 4] {
+    let flip_h = gid & Map::FLIPPED_HORIZONTALLY != 0;
+    let flip_v = gid & Map::FLIPPED_VERTICALLY != 0;
+    let flip_d = gid & Map::FLIPPED_DIAGONALLY != 0;
+
+    let corner = |mut u: f32, mut v: f32| {
+        if flip_v {
+            v = 1.0 - v;
+        }
+        if flip_h {
+            u = 1.0 - u;
+        }
+        if flip_d {
+            std::mem::swap(&mut u, &mut v);
+        }
+        let uv = top_left + (bottom_right - top_left) * vec2(u, v);
+        [uv.x, uv.y]
+    };
+
+    [
+        corner(0.0, 0.0),
+        corner(1.0, 0.0),
+        corner(0.0, 1.0),
+        corner(1.0, 1.0),
+    ]
+}
+
+/// Apply the flip flags stored in the gid to a collider within a tile of `size` pixels.
+/// Just like the tile image, the collider is first flipped diagonally, then horizontally and then vertically.
+fn flipped_collider(collider: &Collider, size: Vec2, gid: u32) -> Collider {
+    let flip_h = gid & Map::FLIPPED_HORIZONTALLY != 0;
+    let flip_v = gid & Map::FLIPPED_VERTICALLY != 0;
+    let flip_d = gid & Map::FLIPPED_DIAGONALLY != 0;
+
+    let point = |p: Vec2| {
+        let mut p = p / size;
+        if flip_d {
+            p = p.yx();
+        }
+        if flip_h {
+            p.x = 1.0 - p.x;
+        }
+        if flip_v {
+            p.y = 1.0 - p.y;
+        }
+        p * size
+    };
+    let extents = |e: Vec2| if flip_d { (e / size).yx() * size } else { e };
+
+    match collider {
+        Collider::Rect {
+            center,
+            half_extents,
+        } => Collider::Rect {
+            center: point(*center),
+            half_extents: extents(*half_extents),
+        },
+        Collider::Ellipse { center, radii } => Collider::Ellipse {
+            center: point(*center),
+            radii: extents(*radii),
+        },
+        Collider::Polygon(points) => Collider::Polygon(points.iter().map(|&p| point(p)).collect()),
+        Collider::Polyline(points) => {
+            Collider::Polyline(points.iter().map(|&p| point(p)).collect())
+        }
+        Collider::Point(p) => Collider::Point(point(*p)),
+    }
+}
+
+impl Default for ProtoAtlasSpriteBundle {
+    fn default() -> Self {
+        ProtoAtlasSpriteBundle {
+            mesh: QUAD_HANDLE.typed(),
+            render_pipelines: RenderPipelines::from_pipelines(vec![RenderPipeline::new(
+                SPRITE_SHEET_PIPELINE_HANDLE.typed(),
+            )]),
+            visible: Visible {
+                is_transparent: true,
+                ..Default::default()
+            },
+            main_pass: MainPass,
+            draw: Default::default(),
+            sprite: Default::default(),
+            texture_atlas: Default::default(),
+            transform: Default::default(),
+            global_transform: Default::default(),
+        }
+    }
+}
+
+/// The components that the layer_visibility_system walks the hierarchy of a map with.
+type LayerHierarchy<'a> = (
+    Option<&'a LayerMetadata>,
+    Option<&'a Children>,
+    Option<&'a Parent>,
+    Option<&'a HiddenObject>,
+);
+
+/// System that updates the `Visible` component of the contents of layers when the `LayerMetadata` of a layer changes.
+/// Contents are visible when all the layers they are in are visible, unless they are a `HiddenObject`.
+pub fn layer_visibility_system(
+    changed: Query<Entity, Changed<LayerMetadata>>,
+    hierarchy: Query<LayerHierarchy>,
+    mut contents: Query<&mut Visible>,
+) {
+    fn apply(
+        entity: Entity,
+        visible: bool,
+        hierarchy: &Query<LayerHierarchy>,
+        contents: &mut Query<&mut Visible>,
+    ) {
+        let (layer, children, _, hidden) = match hierarchy.get(entity) {
+            Ok(components) => components,
+            Err(_) => return,
+        };
+        let visible = match layer {
+            Some(layer) => visible && layer.visible,
+            None => visible && hidden.is_none(),
+        };
+        if layer.is_none() {
+            if let Ok(mut contents) = contents.get_mut(entity) {
+                if contents.is_visible != visible {
+                    contents.is_visible = visible;
+                }
+            }
+        }
+        for &child in children.into_iter().flat_map(|children| children.iter()) {
+            apply(child, visible, hierarchy, contents);
+        }
+    }
+
+    for entity in changed.iter() {
+        // the visibility of the layers that this layer is in
+        let mut visible = true;
+        let mut parent = hierarchy
+            .get(entity)
+            .ok()
+            .and_then(|(_, _, parent, _)| parent);
+        while let Some(Ok((layer, _, grandparent, _))) =
+            parent.map(|parent| hierarchy.get(parent.0))
+        {
+            match layer {
+                Some(layer) => visible &= layer.visible,
+                None => break,
+            }
+            parent = grandparent;
+        }
+        apply(entity, visible, &hierarchy, &mut contents);
+    }
+}
+
 pub fn proto_sprite_upgrade_system(mut commands: Commands, sprites: Query<(Entity, &ProtoSprite)>) {
     for (e, s) in sprites.iter() {
         commands
@@ -416,3 +1533,1080 @@ pub fn proto_sprite_upgrade_system(mut commands: Commands, sprites: Query<(Entit
             .remove::<ProtoSprite>();
     }
 }
+
+pub fn proto_atlas_sprite_upgrade_system(
+    mut commands: Commands,
+    sprites: Query<(Entity, &ProtoAtlasSprite)>,
+) {
+    for (e, s) in sprites.iter() {
+        commands
+            .entity(e)
+            .insert(TextureAtlasSprite {
+                color: s.color,
+                index: s.index,
+                flip_x: s.flip_x,
+                flip_y: s.flip_y,
+            })
+            .remove::<ProtoAtlasSprite>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use bevy_app::App;
+    use bevy_asset::Assets;
+    use bevy_ecs::query::{With, Without};
+    use bevy_ecs::system::CommandQueue;
+    use bevy_render::mesh::VertexAttributeValues;
+
+    use super::*;
+    use crate::test_util::{app, fixtures, parse, spawn};
+    use crate::TmxPlugin;
+
+    /// The UVs of the tile meshes in the app, by their x position.
+    fn mesh_uvs(app: &mut App) -> Vec<([f32; 3], [f32; 2])> {
+        let mut query = app.world.query::<&Handle<Mesh>>();
+        let meshes = app.world.get_resource::<Assets<Mesh>>().unwrap();
+        let mut result = Vec::new();
+        for handle in query.iter(&app.world) {
+            let mesh = meshes.get(handle).unwrap();
+            let positions = match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+                Some(VertexAttributeValues::Float3(positions)) => positions.clone(),
+                _ => panic!("mesh without positions"),
+            };
+            let uvs = match mesh.attribute(Mesh::ATTRIBUTE_UV_0) {
+                Some(VertexAttributeValues::Float2(uvs)) => uvs.clone(),
+                _ => panic!("mesh without uvs"),
+            };
+            result.extend(positions.into_iter().zip(uvs));
+        }
+        result
+    }
+
+    #[test]
+    fn flipped_uvs_mirror_and_rotate_the_tile() {
+        let uvs = |gid| flipped_uvs(Vec2::ZERO, Vec2::ONE, gid);
+        assert_eq!(uvs(1), [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]]);
+        assert_eq!(
+            uvs(1 | Map::FLIPPED_HORIZONTALLY),
+            [[1.0, 0.0], [0.0, 0.0], [1.0, 1.0], [0.0, 1.0]]
+        );
+        assert_eq!(
+            uvs(1 | Map::FLIPPED_VERTICALLY),
+            [[0.0, 1.0], [1.0, 1.0], [0.0, 0.0], [1.0, 0.0]]
+        );
+        assert_eq!(
+            uvs(1 | Map::FLIPPED_HORIZONTALLY | Map::FLIPPED_VERTICALLY),
+            [[1.0, 1.0], [0.0, 1.0], [1.0, 0.0], [0.0, 0.0]]
+        );
+        assert_eq!(
+            uvs(1 | Map::FLIPPED_DIAGONALLY),
+            [[0.0, 0.0], [0.0, 1.0], [1.0, 0.0], [1.0, 1.0]]
+        );
+        // a diagonal and horizontal flip rotates the tile 90 degrees clockwise
+        assert_eq!(
+            uvs(1 | Map::FLIPPED_DIAGONALLY | Map::FLIPPED_HORIZONTALLY),
+            [[0.0, 1.0], [0.0, 0.0], [1.0, 1.0], [1.0, 0.0]]
+        );
+    }
+
+    #[test]
+    fn flipped_tiles_have_mirrored_uvs_in_the_layer_mesh() {
+        let mut app = app(TmxPlugin::default().tile_uv_inset(0.0));
+        spawn(&mut app, "flips.tmx");
+
+        // the four corners of each tile quad, ordered from left to right
+        let mut tiles: Vec<Vec<_>> = mesh_uvs(&mut app).chunks(4).map(<[_]>::to_vec).collect();
+        tiles.sort_by(|a, b| a[0].0[0].partial_cmp(&b[0].0[0]).unwrap());
+        let uvs: Vec<Vec<[f32; 2]>> = tiles
+            .into_iter()
+            .map(|tile| tile.into_iter().map(|(_, uv)| uv).collect())
+            .collect();
+        let (l, r, t, b) = (0.0, 0.5, 0.0, 0.5);
+        assert_eq!(
+            uvs,
+            vec![
+                vec![[l, t], [r, t], [l, b], [r, b]],
+                vec![[r, t], [l, t], [r, b], [l, b]],
+                vec![[l, b], [r, b], [l, t], [r, t]],
+                vec![[r, b], [l, b], [r, t], [l, t]],
+            ]
+        );
+    }
+
+    #[test]
+    fn tiles_are_layered_in_render_order() {
+        let mut app = app(TmxPlugin::default().per_tile_entities(true));
+        spawn(&mut app, "left_up.tmx");
+
+        let mut query = app.world.query::<(&TilePos, &Transform)>();
+        let mut tiles: Vec<(f32, IVec2)> = query
+            .iter(&app.world)
+            .map(|(pos, transform)| (transform.translation.z, IVec2::new(pos.x, pos.y)))
+            .collect();
+        tiles.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        let order: Vec<IVec2> = tiles.into_iter().map(|(_, pos)| pos).collect();
+        assert_eq!(
+            order,
+            vec![
+                IVec2::new(2, 1),
+                IVec2::new(1, 1),
+                IVec2::new(0, 1),
+                IVec2::new(2, 0),
+                IVec2::new(1, 0),
+                IVec2::new(0, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn chunks_of_infinite_maps_are_placed_at_their_coordinates() {
+        let mut app = app(TmxPlugin::default().per_tile_entities(true));
+        spawn(&mut app, "infinite.tmx");
+
+        let mut query = app.world.query::<(&TilePos, &GlobalTransform)>();
+        let mut tiles: Vec<(i32, i32, Vec2)> = query
+            .iter(&app.world)
+            .map(|(pos, transform)| (pos.x, pos.y, transform.translation.truncate()))
+            .collect();
+        tiles.sort_by_key(|&(x, y, _)| (y, x));
+        // tiles are centered on their cell, with the y axis flipped by the default scale
+        assert_eq!(
+            tiles,
+            vec![
+                (-2, 0, Vec2::new(-24.0, -8.0)),
+                (-1, 1, Vec2::new(-8.0, -24.0)),
+                (5, 2, Vec2::new(88.0, -40.0)),
+                (4, 3, Vec2::new(72.0, -56.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn tile_uvs_are_inset_by_half_a_texel() {
+        // the first tile covers the top left 16x16 pixels of the 32x32 image
+        for &(inset, min, max) in &[(None, 0.5, 15.5), (Some(2.0), 2.0, 14.0)] {
+            let plugin = match inset {
+                Some(inset) => TmxPlugin::default().tile_uv_inset(inset),
+                None => TmxPlugin::default(),
+            };
+            let mut app = app(plugin);
+            spawn(&mut app, "flips.tmx");
+
+            let uvs = mesh_uvs(&mut app);
+            assert_eq!(uvs.len(), 16);
+            for (_, [u, v]) in uvs {
+                assert!(u == min / 32.0 || u == max / 32.0, "u = {}", u);
+                assert!(v == min / 32.0 || v == max / 32.0, "v = {}", v);
+            }
+        }
+    }
+
+    #[test]
+    fn per_tile_entities_are_spawned_for_every_tile() {
+        let mut app = app(TmxPlugin::default().per_tile_entities(true));
+        spawn(&mut app, "left_up.tmx");
+
+        let mut query = app
+            .world
+            .query::<(&TilePos, &Transform, &TextureAtlasSprite)>();
+        let mut tiles: Vec<(i32, i32, Vec2, u32)> = query
+            .iter(&app.world)
+            .map(|(pos, transform, sprite)| {
+                (pos.x, pos.y, transform.translation.truncate(), sprite.index)
+            })
+            .collect();
+        tiles.sort_by_key(|&(x, y, _, _)| (y, x));
+        assert_eq!(
+            tiles,
+            vec![
+                (0, 0, Vec2::new(8.0, -8.0), 0),
+                (1, 0, Vec2::new(24.0, -8.0), 1),
+                (2, 0, Vec2::new(40.0, -8.0), 2),
+                (0, 1, Vec2::new(8.0, -24.0), 3),
+                (1, 1, Vec2::new(24.0, -24.0), 0),
+                (2, 1, Vec2::new(40.0, -24.0), 1),
+            ]
+        );
+    }
+
+    /// The visibility of the entities spawned for each layer, by the name of the layer.
+    fn layer_visibility(app: &mut App) -> HashMap<String, Vec<bool>> {
+        let mut query = app.world.query::<(&LayerMetadata, &Children)>();
+        let layers: Vec<(String, Vec<Entity>)> = query
+            .iter(&app.world)
+            .map(|(layer, children)| (layer.name.clone(), children.iter().copied().collect()))
+            .collect();
+        layers
+            .into_iter()
+            .map(|(name, children)| {
+                let visible = children
+                    .into_iter()
+                    .filter_map(|child| app.world.get::<Visible>(child))
+                    .map(|visible| visible.is_visible)
+                    .collect();
+                (name, visible)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn hidden_layers_are_spawned_invisible() {
+        let mut app = app(TmxPlugin::default());
+        spawn(&mut app, "hidden.tmx");
+
+        let visibility = layer_visibility(&mut app);
+        assert_eq!(visibility["shown"], vec![true]);
+        assert_eq!(visibility["hidden"], vec![false]);
+        assert_eq!(visibility["hidden image"], vec![false]);
+    }
+
+    /// The names and transforms of the entities in the object layer named `layer`, ordered by x.
+    fn objects(app: &mut App, layer: &str) -> Vec<(Option<String>, Transform)> {
+        let mut layers = app.world.query::<(&LayerMetadata, &Children)>();
+        let children: Vec<Entity> = layers
+            .iter(&app.world)
+            .find(|(metadata, _)| metadata.name == layer)
+            .map(|(_, children)| children.iter().copied().collect())
+            .unwrap();
+        let mut objects: Vec<_> = children
+            .into_iter()
+            .map(|entity| {
+                let entity = app.world.entity(entity);
+                (
+                    entity.get::<Name>().map(|name| name.as_str().to_string()),
+                    *entity.get::<Transform>().unwrap(),
+                )
+            })
+            .collect();
+        objects.sort_by(|a, b| a.1.translation.x.partial_cmp(&b.1.translation.x).unwrap());
+        objects
+    }
+
+    #[test]
+    fn named_objects_have_a_name_component() {
+        let mut app = app(TmxPlugin::default());
+        spawn(&mut app, "objects.tmx");
+
+        let names: Vec<Option<String>> = objects(&mut app, "objects")
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                Some("Chest".to_string()),
+                Some("PlayerStart".to_string()),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn tiles_are_visited_once_per_non_empty_tile() {
+        let visits = Arc::new(Mutex::new(Vec::new()));
+        let recorded = visits.clone();
+        let mut app = app(TmxPlugin::default()
+            .per_tile_entities(true)
+            .visit_tiles(move |_, gid, coord, _| recorded.lock().unwrap().push((gid, coord))));
+        spawn(&mut app, "sparse.tmx");
+
+        let mut visits = visits.lock().unwrap().clone();
+        visits.sort_by_key(|&(gid, coord)| (coord.y, coord.x, gid));
+        assert_eq!(
+            visits,
+            vec![
+                (1, IVec2::new(0, 0)),
+                (2, IVec2::new(2, 0)),
+                (3, IVec2::new(1, 1)),
+                (1, IVec2::new(2, 1)),
+                (4, IVec2::new(3, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn layers_are_visited_once_including_groups() {
+        let visits = Arc::new(Mutex::new(Vec::new()));
+        let recorded = visits.clone();
+        let mut app = app(TmxPlugin::default()
+            .visit_layers(move |layer, _| recorded.lock().unwrap().push(layer.name().to_string())));
+        spawn(&mut app, "sparse.tmx");
+
+        let mut visits = visits.lock().unwrap().clone();
+        visits.sort();
+        assert_eq!(visits, vec!["decoration", "flowers", "ground"]);
+    }
+
+    #[test]
+    fn topdown_objects_are_sorted_by_y() {
+        let mut app = app(TmxPlugin::default());
+        spawn(&mut app, "objects.tmx");
+
+        // the objects appear in the order y = 40, 10, 25 in the file
+        let mut objects = objects(&mut app, "objects");
+        objects.sort_by(|a, b| a.1.translation.z.partial_cmp(&b.1.translation.z).unwrap());
+        let names: Vec<Option<String>> = objects.into_iter().map(|(name, _)| name).collect();
+        assert_eq!(
+            names,
+            vec![
+                None,
+                Some("Chest".to_string()),
+                Some("PlayerStart".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn tile_offsets_of_tilesets_shift_their_tiles() {
+        let mut app = app(TmxPlugin::default());
+        spawn(&mut app, "offset.tmx");
+
+        let mut tiles: Vec<Vec<Vec2>> = mesh_uvs(&mut app)
+            .chunks(4)
+            .map(|tile| {
+                tile.iter()
+                    .map(|(position, _)| Vec2::new(position[0], position[1]))
+                    .collect()
+            })
+            .collect();
+        tiles.sort_by(|a, b| a[0].x.partial_cmp(&b[0].x).unwrap());
+        assert_eq!(tiles.len(), 2);
+
+        // layer meshes are built in pixels with the y axis pointing down, like the offset
+        let shift = Vec2::new(16.0 + 4.0, -8.0);
+        for (plain, shifted) in tiles[0].iter().zip(&tiles[1]) {
+            assert_eq!(*shifted - *plain, shift);
+        }
+    }
+
+    #[test]
+    fn flipping_y_keeps_objects_rotated_clockwise() {
+        let quarter = std::f32::consts::FRAC_PI_2;
+        for &(flip_y, y, angle) in &[(true, -40.0, -quarter), (false, 40.0, quarter)] {
+            let mut app = app(TmxPlugin::default().flip_y(flip_y));
+            spawn(&mut app, "objects.tmx");
+
+            let objects = objects(&mut app, "rotated");
+            let transform = objects[0].1;
+            assert_eq!(transform.translation.truncate(), Vec2::new(8.0, y));
+            // a clockwise rotation in the map editor turns the x axis of the object towards the y axis of the map
+            let x_axis = transform.rotation * Vec3::X;
+            assert!((x_axis - Vec3::new(0.0, y.signum(), 0.0)).length() < 1e-6);
+            assert!(transform
+                .rotation
+                .abs_diff_eq(Quat::from_rotation_z(angle), 1e-6));
+        }
+    }
+
+    #[test]
+    fn every_entity_descends_from_the_map_root() {
+        let mut app = app(TmxPlugin::default().per_tile_entities(true));
+        spawn(&mut app, "sparse.tmx");
+        spawn(&mut app, "objects.tmx");
+
+        let mut roots = app.world.query_filtered::<Entity, With<MapRoot>>();
+        let roots: Vec<Entity> = roots.iter(&app.world).collect();
+        assert_eq!(roots.len(), 2);
+
+        let mut entities = app.world.query::<(Entity, Option<&Parent>)>();
+        let parents: HashMap<Entity, Option<Entity>> = entities
+            .iter(&app.world)
+            .map(|(entity, parent)| (entity, parent.map(|parent| parent.0)))
+            .collect();
+        assert!(parents.len() > 2);
+        for &entity in parents.keys() {
+            let mut ancestor = entity;
+            while let Some(parent) = parents[&ancestor] {
+                ancestor = parent;
+            }
+            assert!(roots.contains(&ancestor), "{:?} is not in a map", entity);
+        }
+    }
+
+    /// Show or hide the layer named `name`.
+    fn set_layer_visible(app: &mut App, name: &str, visible: bool) {
+        let mut layers = app.world.query::<&mut LayerMetadata>();
+        for mut layer in layers.iter_mut(&mut app.world) {
+            if layer.name == name {
+                layer.visible = visible;
+            }
+        }
+        app.update();
+    }
+
+    #[test]
+    fn groups_are_parents_that_hide_their_layers() {
+        let mut app = app(TmxPlugin::default());
+        spawn(&mut app, "groups.tmx");
+
+        let mut layers = app.world.query::<(&LayerMetadata, &Parent)>();
+        let mut parents: Vec<(String, Option<String>)> = layers
+            .iter(&app.world)
+            .map(|(layer, parent)| {
+                let parent = app.world.get::<LayerMetadata>(parent.0);
+                (layer.name.clone(), parent.map(|parent| parent.name.clone()))
+            })
+            .collect();
+        parents.sort();
+        let name = |name: &str| Some(name.to_string());
+        assert_eq!(
+            parents,
+            vec![
+                ("a".to_string(), name("outer")),
+                ("b".to_string(), name("inner")),
+                ("c".to_string(), name("inner")),
+                ("inner".to_string(), name("outer")),
+                ("outer".to_string(), None),
+            ]
+        );
+
+        let visibility = |app: &mut App, name: &str| {
+            let mut visible = layer_visibility(app).remove(name).unwrap();
+            visible.sort_unstable();
+            visible
+        };
+        let shown = |app: &mut App| {
+            assert_eq!(visibility(app, "a"), vec![true]);
+            assert_eq!(visibility(app, "b"), vec![true]);
+            assert_eq!(visibility(app, "c"), vec![false, true]);
+        };
+        shown(&mut app);
+
+        set_layer_visible(&mut app, "outer", false);
+        assert_eq!(visibility(&mut app, "a"), vec![false]);
+        assert_eq!(visibility(&mut app, "b"), vec![false]);
+        assert_eq!(visibility(&mut app, "c"), vec![false, false]);
+
+        // the object that is hidden in the map editor stays hidden
+        set_layer_visible(&mut app, "outer", true);
+        shown(&mut app);
+
+        set_layer_visible(&mut app, "inner", false);
+        assert_eq!(visibility(&mut app, "a"), vec![true]);
+        assert_eq!(visibility(&mut app, "b"), vec![false]);
+        assert_eq!(visibility(&mut app, "c"), vec![false, false]);
+    }
+
+    #[test]
+    fn tile_objects_rotate_around_their_anchor() {
+        let mut app = app(TmxPlugin::default());
+        spawn(&mut app, "objects.tmx");
+
+        let mut layers = app.world.query::<(&LayerMetadata, &Children)>();
+        let object = layers
+            .iter(&app.world)
+            .find(|(layer, _)| layer.name == "rotated tile")
+            .map(|(_, children)| children[0])
+            .unwrap();
+        let object = app.world.entity(object);
+        let transform = object.get::<GlobalTransform>().unwrap();
+        let size = object.get::<Sprite>().unwrap().size;
+        let meshes = app.world.get_resource::<Assets<Mesh>>().unwrap();
+        let corners: Vec<Vec2> = match meshes
+            .get(object.get::<Handle<Mesh>>().unwrap())
+            .unwrap()
+            .attribute(Mesh::ATTRIBUTE_POSITION)
+        {
+            Some(VertexAttributeValues::Float3(positions)) => positions
+                .iter()
+                .map(|&[x, y, _]| {
+                    transform
+                        .mul_vec3((Vec2::new(x, y) * size).extend(0.0))
+                        .truncate()
+                })
+                .collect(),
+            _ => panic!("mesh without positions"),
+        };
+
+        // tiled turns the object clockwise around its bottom left corner at (32, 48),
+        //  which moves the top left corner at (32, 32) to (48, 48) and the top right corner at (48, 32) to (48, 64)
+        let expected = [Vec2::new(48.0, -48.0), Vec2::new(48.0, -64.0)];
+        for (corner, expected) in corners.iter().zip(expected.iter()) {
+            assert!(
+                corner.distance(*expected) < 1e-4,
+                "{} != {}",
+                corner,
+                expected
+            );
+        }
+        // the pivot itself doesn't move
+        assert!(corners[2].distance(Vec2::new(32.0, -48.0)) < 1e-4);
+    }
+
+    #[test]
+    fn flipped_tile_objects_mirror_their_tile() {
+        let mut app = app(TmxPlugin::default().tile_uv_inset(0.0));
+        spawn(&mut app, "flipped_object.tmx");
+
+        // gid 2 with the horizontal flip flag resolves to the second tile, with its left and right swapped
+        let (l, r, t, b) = (0.5, 1.0, 0.0, 0.5);
+        let uvs: Vec<[f32; 2]> = mesh_uvs(&mut app).into_iter().map(|(_, uv)| uv).collect();
+        assert_eq!(uvs, vec![[r, t], [l, t], [r, b], [l, b]]);
+    }
+
+    fn object_corners(alignment: &str) -> Vec<Vec2> {
+        let mut app = app(TmxPlugin::default());
+        spawn(&mut app, &format!("align_{}.tmx", alignment));
+        mesh_uvs(&mut app)
+            .into_iter()
+            .map(|([x, y, _], _)| Vec2::new(x, y))
+            .collect()
+    }
+
+    #[test]
+    fn object_alignment_moves_the_tile_object_mesh() {
+        // the quad is in object units with the y axis pointing down, the anchor sits at the origin
+        let bottom_left = object_corners("bottomleft");
+        let center = object_corners("center");
+        assert_eq!(
+            bottom_left,
+            vec![
+                Vec2::new(0.0, -1.0),
+                Vec2::new(1.0, -1.0),
+                Vec2::new(0.0, 0.0),
+                Vec2::new(1.0, 0.0),
+            ]
+        );
+        assert_eq!(
+            center,
+            vec![
+                Vec2::new(-0.5, -0.5),
+                Vec2::new(0.5, -0.5),
+                Vec2::new(-0.5, 0.5),
+                Vec2::new(0.5, 0.5),
+            ]
+        );
+        // the same quad, shifted by half the object size
+        for (b, c) in bottom_left.iter().zip(center.iter()) {
+            assert_eq!(*c - *b, Vec2::new(-0.5, 0.5));
+        }
+    }
+
+    #[test]
+    fn hidden_groups_hide_every_descendant() {
+        let mut app = app(TmxPlugin::default());
+        spawn(&mut app, "hidden_group.tmx");
+
+        let mut layers = app.world.query::<(Entity, &LayerMetadata)>();
+        let group = layers
+            .iter(&app.world)
+            .find(|(_, layer)| layer.name == "outer")
+            .map(|(entity, _)| entity)
+            .unwrap();
+
+        let mut pending = vec![group];
+        let mut visibles = 0;
+        while let Some(entity) = pending.pop() {
+            if let Some(visible) = app.world.get::<Visible>(entity) {
+                assert!(!visible.is_visible);
+                visibles += 1;
+            }
+            if let Some(children) = app.world.get::<Children>(entity) {
+                pending.extend(children.iter().copied());
+            }
+        }
+        // the tiles of layers a and b, and both objects in c
+        assert_eq!(visibles, 4);
+    }
+
+    #[test]
+    fn tile_objects_from_templates_use_the_tileset_of_the_template() {
+        let mut app = app(TmxPlugin::default().tile_uv_inset(0.0));
+        spawn(&mut app, "template_tileset.tmx");
+
+        // the crate is the second tile of tiles.tsx, which the map itself doesn't use
+        let (l, r, t, b) = (0.5, 1.0, 0.0, 0.5);
+        let uvs: Vec<[f32; 2]> = mesh_uvs(&mut app).into_iter().map(|(_, uv)| uv).collect();
+        assert_eq!(uvs, vec![[l, t], [r, t], [l, b], [r, b]]);
+    }
+
+    #[test]
+    fn repeating_image_layers_are_marked() {
+        let mut app = app(TmxPlugin::default());
+        spawn(&mut app, "repeat.tmx");
+
+        let mut images = app.world.query::<&RepeatImage>();
+        let repeats: Vec<_> = images
+            .iter(&app.world)
+            .map(|repeat| (repeat.repeat_x, repeat.repeat_y, repeat.size.abs()))
+            .collect();
+        assert_eq!(repeats, vec![(true, false, Vec2::new(32.0, 32.0))]);
+    }
+
+    #[test]
+    fn the_map_handle_resolves_to_the_spawned_map() {
+        let mut app = app(TmxPlugin::default());
+        spawn(&mut app, "objects.tmx");
+
+        let mut roots = app.world.query::<&MapHandle>();
+        let handles: Vec<Handle<Map>> = roots.iter(&app.world).map(|root| root.0.clone()).collect();
+        assert_eq!(handles.len(), 1);
+        let maps = app.world.get_resource::<Assets<Map>>().unwrap();
+        let map = maps.get(&handles[0]).expect("the map asset isn't loaded");
+
+        let source = std::fs::read_to_string(fixtures().join("objects.tmx")).unwrap();
+        let expected = parse(&source);
+        assert_eq!((map.width, map.height), (expected.width, expected.height));
+        let names = |map: &Map| -> Vec<String> {
+            map.layers
+                .iter()
+                .map(|layer| layer.name().to_string())
+                .collect()
+        };
+        assert_eq!(names(map), names(&expected));
+        assert_eq!(
+            map.objects()
+                .map(|(_, object)| object.id)
+                .collect::<Vec<_>>(),
+            expected
+                .objects()
+                .map(|(_, object)| object.id)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn objects_carry_their_custom_properties() {
+        let mut app = app(TmxPlugin::default());
+        spawn(&mut app, "properties.tmx");
+
+        let mut objects = app
+            .world
+            .query_filtered::<(&Name, Option<&ObjectProperties>), Without<LayerMetadata>>();
+        let mut properties: Vec<(String, Option<Vec<String>>)> = objects
+            .iter(&app.world)
+            .map(|(name, properties)| {
+                let keys = properties.map(|properties| {
+                    let mut keys: Vec<String> = properties.0.keys().cloned().collect();
+                    keys.sort();
+                    keys
+                });
+                (name.as_str().to_string(), keys)
+            })
+            .collect();
+        properties.sort();
+
+        // the tileset of the template is recorded in an internal property while parsing, which isn't kept
+        let keys = vec!["amount".to_string(), "contents".to_string()];
+        assert_eq!(
+            properties,
+            vec![
+                ("loot".to_string(), Some(keys)),
+                ("plain".to_string(), None)
+            ]
+        );
+        let mut loot = app.world.query::<&ObjectProperties>();
+        let loot = loot.iter(&app.world).next().unwrap();
+        assert_eq!(loot.get_str("contents"), Some("gold"));
+        assert_eq!(loot.get_int("amount"), Some(12));
+    }
+
+    #[test]
+    fn the_map_root_carries_the_map_properties() {
+        let mut app = app(TmxPlugin::default());
+        spawn(&mut app, "properties.tmx");
+
+        let mut roots = app
+            .world
+            .query_filtered::<Option<&MapProperties>, With<MapRoot>>();
+        let properties: Vec<_> = roots.iter(&app.world).collect();
+        assert_eq!(properties.len(), 1);
+        let properties = properties[0].expect("the map root has no properties");
+        assert_eq!(properties.0.len(), 2);
+        assert_eq!(properties.get_int("difficulty"), Some(3));
+        assert_eq!(properties.get_str("music"), Some("caves.ogg"));
+    }
+
+    #[test]
+    fn layer_metadata_describes_the_source_layer() {
+        let mut app = app(TmxPlugin::default());
+        spawn(&mut app, "layers.tmx");
+
+        let mut layers = app.world.query::<&LayerMetadata>();
+        let mut metadata: Vec<(usize, String, Vec2, f32, bool)> = layers
+            .iter(&app.world)
+            .map(|layer| {
+                (
+                    layer.index,
+                    layer.name.clone(),
+                    layer.parallax,
+                    layer.opacity,
+                    layer.visible,
+                )
+            })
+            .collect();
+        metadata.sort_by_key(|(index, ..)| *index);
+
+        // grouped layers combine their parallax and opacity with those of the group
+        let layer = |index, name: &str, parallax: Vec2, opacity, visible| {
+            (index, name.to_string(), parallax, opacity, visible)
+        };
+        assert_eq!(
+            metadata,
+            vec![
+                layer(0, "ground", Vec2::ONE, 0.5, true),
+                layer(1, "markers", Vec2::ONE, 1.0, false),
+                layer(2, "sky", Vec2::new(0.5, 0.25), 1.0, true),
+                layer(3, "group", Vec2::ONE, 1.0, true),
+                layer(4, "trees", Vec2::new(2.0, 1.0), 0.25, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn overlapping_isometric_tiles_move_forward_down_the_screen() {
+        let mut app = app(TmxPlugin::default());
+        spawn(&mut app, "isometric.tmx");
+
+        // the top left corner of each tile quad in the batched mesh
+        let tiles: Vec<[f32; 3]> = mesh_uvs(&mut app).chunks(4).map(|quad| quad[0].0).collect();
+        assert_eq!(tiles.len(), 9);
+
+        // the tiles are twice as tall as a row, so they cover the tiles behind them that they overlap
+        let mut overlaps = 0;
+        for [x0, y0, z0] in tiles.iter().copied() {
+            for [x1, y1, z1] in tiles.iter().copied() {
+                if (x1 - x0).abs() < 32.0 && y1 > y0 {
+                    overlaps += 1;
+                    assert!(
+                        z1 > z0,
+                        "tile at ({}, {}) is behind ({}, {})",
+                        x1,
+                        y1,
+                        x0,
+                        y0
+                    );
+                }
+            }
+        }
+        assert!(overlaps > 0);
+    }
+
+    fn tile_map(path: &str) -> TileMap {
+        let mut app = app(TmxPlugin::default());
+        spawn(&mut app, path);
+        let mut roots = app.world.query_filtered::<&TileMap, With<MapRoot>>();
+        roots.iter(&app.world).next().unwrap().clone()
+    }
+
+    #[test]
+    fn the_tile_map_holds_the_grid_of_every_tile_layer() {
+        let tiles = tile_map("sparse.tmx");
+
+        // layers in groups are included as well
+        let names: Vec<&str> = tiles
+            .layers
+            .iter()
+            .map(|layer| layer.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["ground", "flowers"]);
+        let ground = tiles.layer("ground").unwrap();
+        assert_eq!(ground.size, UVec2::new(4, 2));
+        assert_eq!(ground.data, vec![1, 0, 2, 0, 0, 3, 0, 4]);
+        let source = parse(&std::fs::read_to_string(fixtures().join("sparse.tmx")).unwrap());
+        for layer in tiles.layers.iter() {
+            let source = source.layer_by_name(&layer.name).unwrap();
+            for y in 0..layer.size.y as i32 {
+                for x in 0..layer.size.x as i32 {
+                    let gid = layer.data[(y * layer.size.x as i32 + x) as usize];
+                    assert_eq!(source.tile_at(x, y).unwrap_or(0), gid);
+                    assert_eq!(layer.tile_at(x, y), source.tile_at(x, y));
+                }
+            }
+        }
+
+        // the flip flags are cleared
+        assert_eq!(tile_map("flips.tmx").layers[0].data, vec![1; 4]);
+    }
+
+    #[test]
+    fn tile_objects_of_collections_use_the_image_of_their_tile() {
+        let mut app = app(TmxPlugin::default().tile_uv_inset(0.0));
+        spawn(&mut app, "collection_objects.tmx");
+
+        // the whole image of the tile is shown, rather than a part of an atlas
+        let uvs: Vec<[f32; 2]> = mesh_uvs(&mut app).into_iter().map(|(_, uv)| uv).collect();
+        assert_eq!(uvs, vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]]);
+
+        let mut query = app.world.query::<&Handle<ColorMaterial>>();
+        let material = query.iter(&app.world).next().unwrap().clone();
+        let materials = app.world.get_resource::<Assets<ColorMaterial>>().unwrap();
+        let texture = materials.get(material).unwrap().texture.clone().unwrap();
+        let textures = app.world.get_resource::<Assets<Texture>>().unwrap();
+        // blue.png is 16x24, the other tile of the collection uses the 8x8 red.png
+        let size = textures.get(texture).unwrap().size;
+        assert_eq!((size.width, size.height), (16, 24));
+    }
+
+    #[test]
+    fn the_z_property_overrides_the_depth_of_an_object() {
+        let mut app = app(TmxPlugin::default());
+        spawn(&mut app, "z.tmx");
+
+        let mut objects = app
+            .world
+            .query::<(&Name, &GlobalTransform, Option<&YSort>)>();
+        let depths: HashMap<String, (f32, bool)> = objects
+            .iter(&app.world)
+            .map(|(name, transform, sort)| {
+                (name.to_string(), (transform.translation.z, sort.is_some()))
+            })
+            .collect();
+
+        // the bird is at the given depth even though it's at the bottom of the map, and isn't y sorted
+        assert_eq!(depths["bird"], (5.0, false));
+        let (tree, sorted) = depths["tree"];
+        assert!(sorted);
+        assert!(tree > 1.0 && tree < 2.0, "{}", tree);
+    }
+
+    #[test]
+    fn preserve_aspect_fit_letterboxes_tile_objects() {
+        let mut app = app(TmxPlugin::default());
+        spawn(&mut app, "fillmode.tmx");
+
+        let mut sprites = app.world.query::<&Sprite>();
+        let size = sprites.iter(&app.world).next().unwrap().size;
+        assert_eq!(size.abs(), Vec2::new(32.0, 16.0));
+
+        // the square tile covers the middle half of the 32x16 object, in object units
+        let corners: Vec<Vec2> = mesh_uvs(&mut app)
+            .into_iter()
+            .map(|([x, y, _], _)| Vec2::new(x, y))
+            .collect();
+        assert_eq!(
+            corners,
+            vec![
+                Vec2::new(0.25, -1.0),
+                Vec2::new(0.75, -1.0),
+                Vec2::new(0.25, 0.0),
+                Vec2::new(0.75, 0.0),
+            ]
+        );
+        let pixels = (corners[3] - corners[0]) * size.abs();
+        assert_eq!(pixels, Vec2::new(16.0, 16.0));
+    }
+
+    #[test]
+    fn despawning_a_map_removes_all_of_its_entities() {
+        let mut app = app(TmxPlugin::default());
+        spawn(&mut app, "objects.tmx");
+        let entities = app.world.entities().len();
+        spawn(&mut app, "sparse.tmx");
+        let mut roots = app.world.query_filtered::<Entity, With<MapRoot>>();
+        let mut sparse = app.world.query::<(Entity, &LayerMetadata, &Parent)>();
+        let root = sparse
+            .iter(&app.world)
+            .find(|(_, layer, _)| layer.name == "ground")
+            .map(|(_, _, parent)| parent.0)
+            .unwrap();
+        assert_eq!(roots.iter(&app.world).count(), 2);
+
+        let mut queue = CommandQueue::default();
+        despawn_map(&mut Commands::new(&mut queue, &app.world), root);
+        queue.apply(&mut app.world);
+        app.update();
+
+        // only the entities of the other map are left
+        assert_eq!(app.world.entities().len(), entities);
+        assert!(app.world.get_entity(root).is_none());
+        assert_eq!(roots.iter(&app.world).count(), 1);
+    }
+
+    #[test]
+    fn layer_materials_combine_tint_and_opacity() {
+        let mut app = app(TmxPlugin::default());
+        spawn(&mut app, "tinted.tmx");
+
+        let mut query = app.world.query::<&Handle<ColorMaterial>>();
+        let material = query.iter(&app.world).next().unwrap().clone();
+        let materials = app.world.get_resource::<Assets<ColorMaterial>>().unwrap();
+        let color = materials.get(material).unwrap().color;
+        let rgba = Vec4::new(color.r(), color.g(), color.b(), color.a());
+        assert!(
+            rgba.abs_diff_eq(Vec4::new(1.0, 0.0, 0.0, 0.5), 0.01),
+            "{}",
+            rgba
+        );
+    }
+
+    #[test]
+    fn rectangle_objects_are_drawn_as_quads_when_enabled() {
+        fn rotated_object(plugin: TmxPlugin) -> (Option<Vec<[f32; 3]>>, Option<bool>) {
+            let mut app = app(plugin);
+            spawn(&mut app, "objects.tmx");
+            let mut layers = app.world.query::<(&LayerMetadata, &Children)>();
+            let object = layers
+                .iter(&app.world)
+                .find(|(metadata, _)| metadata.name == "rotated")
+                .map(|(_, children)| children[0])
+                .unwrap();
+            let meshes = app.world.get_resource::<Assets<Mesh>>().unwrap();
+            let positions = app.world.get::<Handle<Mesh>>(object).map(|handle| {
+                match meshes
+                    .get(handle)
+                    .unwrap()
+                    .attribute(Mesh::ATTRIBUTE_POSITION)
+                {
+                    Some(VertexAttributeValues::Float3(positions)) => positions.clone(),
+                    _ => panic!("mesh without positions"),
+                }
+            });
+            let visible = app
+                .world
+                .get::<Visible>(object)
+                .map(|visible| visible.is_visible);
+            (positions, visible)
+        }
+
+        assert_eq!(rotated_object(TmxPlugin::default()), (None, None));
+
+        let (positions, visible) = rotated_object(TmxPlugin::default().draw_object_rects(true));
+        assert_eq!(visible, Some(true));
+        let positions = positions.unwrap();
+        let min = positions.iter().fold(Vec2::splat(f32::MAX), |min, p| {
+            min.min(Vec2::new(p[0], p[1]))
+        });
+        let max = positions.iter().fold(Vec2::splat(f32::MIN), |max, p| {
+            max.max(Vec2::new(p[0], p[1]))
+        });
+        // the quad covers the 16x8 object, it's rotated by the transform of the entity
+        assert_eq!(max - min, Vec2::new(16.0, 8.0));
+    }
+
+    #[test]
+    fn tile_objects_of_layers_with_different_opacity_dont_share_a_material() {
+        let mut app = app(TmxPlugin::default());
+        spawn(&mut app, "object_opacity.tmx");
+
+        let mut layers = app.world.query::<(&LayerMetadata, &Children)>();
+        let objects: HashMap<String, Entity> = layers
+            .iter(&app.world)
+            .map(|(metadata, children)| (metadata.name.clone(), children[0]))
+            .collect();
+        let materials = app.world.get_resource::<Assets<ColorMaterial>>().unwrap();
+        let alpha = |layer: &str| {
+            let material = app
+                .world
+                .get::<Handle<ColorMaterial>>(objects[layer])
+                .unwrap();
+            materials.get(material).unwrap().color.a()
+        };
+        assert_eq!(alpha("opaque"), 1.0);
+        assert_eq!(alpha("translucent"), 0.5);
+    }
+
+    #[test]
+    fn layers_with_more_vertices_than_u16_can_address_use_u32_indices() {
+        let mut app = app(TmxPlugin::default());
+        spawn(&mut app, "large.tmx");
+
+        let mut query = app.world.query::<&Handle<Mesh>>();
+        let handles: Vec<Handle<Mesh>> = query.iter(&app.world).cloned().collect();
+        assert_eq!(handles.len(), 1);
+        let meshes = app.world.get_resource::<Assets<Mesh>>().unwrap();
+        let mesh = meshes.get(&handles[0]).unwrap();
+
+        // 200x200 tiles of 4 vertices each
+        assert_eq!(mesh.count_vertices(), 160_000);
+        let indices = match mesh.indices() {
+            Some(Indices::U32(indices)) => indices,
+            _ => panic!("expected 32 bit indices"),
+        };
+        assert_eq!(indices.len(), 240_000);
+        // the last tile refers to the last vertices instead of wrapping around
+        assert_eq!(
+            indices[indices.len() - 6..],
+            [159_996, 159_997, 159_998, 159_998, 159_997, 159_999]
+        );
+    }
+
+    #[test]
+    fn the_background_quad_covers_the_map_behind_its_layers() {
+        let mut app = app(TmxPlugin::default().background(true));
+        spawn(&mut app, "background.tmx");
+
+        let mut query = app
+            .world
+            .query::<(&Handle<ColorMaterial>, &GlobalTransform, Option<&Sprite>)>();
+        let entities: Vec<_> = query
+            .iter(&app.world)
+            .map(|(material, transform, sprite)| {
+                (
+                    material.clone(),
+                    transform.translation,
+                    sprite.map(|sprite| sprite.size),
+                )
+            })
+            .collect();
+        let materials = app.world.get_resource::<Assets<ColorMaterial>>().unwrap();
+        let (background, layers): (Vec<_>, Vec<_>) = entities
+            .into_iter()
+            .partition(|(material, ..)| materials.get(material).unwrap().texture.is_none());
+
+        assert_eq!(background.len(), 1);
+        let (material, translation, size) = &background[0];
+        let color = materials.get(material).unwrap().color;
+        assert_eq!(color, Color::rgba_u8(255, 0, 0, 128));
+        // the quad covers the 4x2 tiles of 16 pixels, centered on the map with the y axis flipped
+        assert_eq!(*size, Some(Vec2::new(64.0, -32.0)));
+        assert_eq!(translation.truncate(), Vec2::new(32.0, -16.0));
+        assert!(!layers.is_empty());
+        for (_, layer, _) in layers {
+            assert!(layer.z > translation.z);
+        }
+    }
+
+    #[test]
+    fn triangular_tile_shapes_are_reachable_from_the_tile_entities() {
+        let mut app = app(TmxPlugin::default().per_tile_entities(true));
+        spawn(&mut app, "triangle.tmx");
+
+        let mut query = app.world.query::<(&TilePos, &TileColliders)>();
+        let mut tiles: Vec<(i32, TileColliders)> = query
+            .iter(&app.world)
+            .map(|(pos, colliders)| (pos.x, colliders.clone()))
+            .collect();
+        tiles.sort_by_key(|(x, _)| *x);
+        let shapes = app.world.get_resource::<Assets<TileShapes>>().unwrap();
+        let colliders: Vec<(Vec2, Vec<Collider>)> = tiles
+            .into_iter()
+            .map(|(_, tile)| {
+                (
+                    tile.origin,
+                    shapes.get(&tile.shapes).unwrap().colliders.clone(),
+                )
+            })
+            .collect();
+
+        // a slope rising to the right, with the y axis flipped to point up
+        let slope = vec![
+            Vec2::new(0.0, -16.0),
+            Vec2::new(16.0, -16.0),
+            Vec2::new(16.0, 0.0),
+        ];
+        // the second tile is mirrored, so its slope rises to the left
+        let mirrored = vec![
+            Vec2::new(16.0, -16.0),
+            Vec2::new(0.0, -16.0),
+            Vec2::new(0.0, 0.0),
+        ];
+        let polygons: Vec<(Vec2, &[Vec2])> = colliders
+            .iter()
+            .map(|(origin, colliders)| match colliders.as_slice() {
+                [Collider::Polygon(points)] => (*origin, points.as_slice()),
+                _ => panic!("expected a single polygon"),
+            })
+            .collect();
+        assert_eq!(
+            polygons,
+            vec![
+                (Vec2::new(0.0, 0.0), slope.as_slice()),
+                (Vec2::new(16.0, 0.0), mirrored.as_slice()),
+            ]
+        );
+    }
+}
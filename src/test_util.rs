@@ -0,0 +1,187 @@
+//! Helpers shared by the unit tests of the crate.
+
+use std::path::PathBuf;
+
+use std::collections::HashMap;
+
+use crate::tmx::{Layer, Map, Object};
+
+/// The directory with the files that test maps refer to.
+pub(crate) fn fixtures() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+/// Parse a map from its source, reading external files from the fixtures directory.
+pub(crate) fn try_parse(source: &str) -> anyhow::Result<Map> {
+    futures_lite::future::block_on(Map::from_bytes(source.as_bytes(), fixtures()))
+}
+
+/// Parse a map from its source, panicking if it's invalid.
+pub(crate) fn parse(source: &str) -> Map {
+    try_parse(source).unwrap()
+}
+
+/// A map of `width` by `height` 16x16 tiles with `extra` attributes, containing `content`.
+/// The map uses `tiles.png` from the fixtures directory as its first tileset, so gids 1 to 4 are valid.
+pub(crate) fn map_xml(width: u32, height: u32, extra: &str, content: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" renderorder="right-down" width="{}" height="{}" tilewidth="16" tileheight="16" infinite="0" {}>
+ <tileset firstgid="1" name="tiles" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+  <image source="tiles.png" width="32" height="32"/>
+ </tileset>
+{}
+</map>"#,
+        width, height, extra, content
+    )
+}
+
+/// A description of an object that doesn't depend on the iteration order of its properties.
+pub(crate) fn describe_object(object: &Object) -> String {
+    let properties = format!("{:?}", object.properties_sorted());
+    let object = Object {
+        properties: HashMap::new(),
+        ..object.clone()
+    };
+    format!("{:?} {}", object, properties)
+}
+
+/// A description of the contents of a layer and the layers in it.
+pub(crate) fn describe_layer(layer: &Layer) -> String {
+    match layer {
+        Layer::TileLayer {
+            name,
+            size,
+            position,
+            offset,
+            parallax,
+            color,
+            opacity,
+            visible,
+            data,
+        } => format!(
+            "tiles {} {} {} {} {} {} {} {} {:?}",
+            name, size, position, offset, parallax, color, opacity, visible, data
+        ),
+        Layer::ObjectLayer {
+            name,
+            draworder_index,
+            objects,
+            offset,
+            parallax,
+            color,
+            opacity,
+            visible,
+            object_color,
+        } => format!(
+            "objects {} {} {} {} {} {} {} {} {:?}",
+            name,
+            draworder_index,
+            offset,
+            parallax,
+            color,
+            opacity,
+            visible,
+            object_color,
+            objects.iter().map(describe_object).collect::<Vec<_>>()
+        ),
+        Layer::ImageLayer { name, .. } => format!("image {}", name),
+        Layer::Group {
+            name,
+            offset,
+            layers,
+        } => format!(
+            "group {} {} {:?}",
+            name,
+            offset,
+            layers.iter().map(describe_layer).collect::<Vec<_>>()
+        ),
+    }
+}
+
+/// A headless app with the `plugin` and everything it needs to load scenes, reading assets from the fixtures directory.
+#[cfg(feature = "render")]
+pub(crate) fn app(plugin: crate::TmxPlugin) -> bevy_app::App {
+    use bevy_asset::{AddAsset, AssetServerSettings, Handle};
+    use bevy_render::{
+        draw::*, mesh::Mesh, pipeline::RenderPipelines, render_graph::base::MainPass,
+    };
+    use bevy_sprite::{ColorMaterial, Sprite, TextureAtlas};
+
+    let mut builder = bevy_app::App::build();
+    builder
+        .insert_resource(AssetServerSettings {
+            asset_folder: "tests/fixtures".to_string(),
+        })
+        .add_plugin(bevy_core::CorePlugin)
+        .add_plugin(bevy_transform::TransformPlugin)
+        .add_plugin(bevy_asset::AssetPlugin)
+        .add_plugin(bevy_scene::ScenePlugin)
+        .add_asset::<Mesh>()
+        .add_asset::<bevy_render::texture::Texture>()
+        .add_asset::<ColorMaterial>()
+        .add_asset::<TextureAtlas>()
+        .register_type::<Draw>()
+        .register_type::<Visible>()
+        .register_type::<RenderPipelines>()
+        .register_type::<MainPass>()
+        .register_type::<Handle<Mesh>>()
+        .register_type::<Handle<ColorMaterial>>()
+        .register_type::<Handle<TextureAtlas>>()
+        .register_type::<Sprite>()
+        .add_plugin(plugin);
+    builder.app
+}
+
+/// Load an asset from the fixtures and wait until it's loaded.
+#[cfg(feature = "render")]
+pub(crate) fn load<T: bevy_asset::Asset>(
+    app: &mut bevy_app::App,
+    path: &str,
+) -> bevy_asset::Handle<T> {
+    use bevy_asset::{AssetServer, LoadState};
+
+    let server = app.world.get_resource::<AssetServer>().unwrap().clone();
+    let handle = server.load::<T, _>(path);
+    for _ in 0..1000 {
+        app.update();
+        match server.get_load_state(&handle) {
+            LoadState::Loaded => return handle,
+            LoadState::Failed => panic!("failed to load `{}`", path),
+            _ => std::thread::sleep(std::time::Duration::from_millis(1)),
+        }
+    }
+    panic!("timed out loading `{}`", path);
+}
+
+/// Load the scene of the map at `path` in the fixtures directory and spawn it, running `app` until it's spawned.
+#[cfg(feature = "render")]
+pub(crate) fn spawn(app: &mut bevy_app::App, path: &str) {
+    use bevy_asset::{AssetServer, LoadState};
+    use bevy_scene::{Scene, SceneSpawner};
+
+    let server = app.world.get_resource::<AssetServer>().unwrap().clone();
+    let handle = server.load::<Scene, _>(path);
+    let instance = app
+        .world
+        .get_resource_mut::<SceneSpawner>()
+        .unwrap()
+        .spawn(handle.clone());
+    for _ in 0..1000 {
+        app.update();
+        let spawner = app.world.get_resource::<SceneSpawner>().unwrap();
+        if spawner.instance_is_ready(instance) {
+            // one more update, so the systems of the plugin see the spawned entities
+            app.update();
+            return;
+        }
+        assert_ne!(
+            server.get_load_state(&handle),
+            LoadState::Failed,
+            "failed to load `{}`",
+            path
+        );
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+    panic!("timed out loading `{}`", path);
+}
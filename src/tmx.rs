@@ -2,22 +2,32 @@ use std::collections::HashMap;
 use std::future::Future;
 use std::sync::Arc;
 
-use bevy_math::Vec2;
+use bevy_math::{IVec2, Vec2};
+#[cfg(feature = "render")]
+use bevy_reflect::TypeUuid;
 
+#[cfg(any(not(feature = "render"), feature = "zip"))]
+pub(crate) use json::is_json_file;
 pub use layer::Layer;
-pub use map::Map;
-pub use property::Property;
+pub use map::{Map, TileIndex};
+pub use property::{Properties, Property};
 pub use texture::Texture;
-#[cfg(feature = "plugin")]
+#[cfg(feature = "render")]
 pub(crate) use texture::TexturePtr;
 pub use tile_type::TileType;
+pub use world::{World, WorldMap};
 
+mod de;
+mod json;
 mod layer;
 mod map;
 mod parse;
 mod property;
+mod shape;
 mod texture;
 mod tile_type;
+mod world;
+mod write;
 
 /// Render order for tiles in layers.
 #[derive(Debug, Clone, Copy)]
@@ -29,7 +39,31 @@ pub enum RenderOrder {
     LeftUp,
 }
 
+impl RenderOrder {
+    /// Iterate over the indices of a `width` by `height` row by row tile array, in the order that tiles should be drawn.
+    pub fn tile_indices(self, width: u32, height: u32) -> impl Iterator<Item = usize> {
+        let (width, height) = (width as usize, height as usize);
+        let (reverse_x, reverse_y) = match self {
+            RenderOrder::RightDown => (false, false),
+            RenderOrder::RightUp => (false, true),
+            RenderOrder::LeftDown => (true, false),
+            RenderOrder::LeftUp => (true, true),
+        };
+
+        (0..height).flat_map(move |y| {
+            let y = if reverse_y { height - 1 - y } else { y };
+            (0..width).map(move |x| {
+                let x = if reverse_x { width - 1 - x } else { x };
+                y * width + x
+            })
+        })
+    }
+}
+
 /// A tileset
+#[derive(Clone)]
+#[cfg_attr(feature = "render", derive(TypeUuid))]
+#[cfg_attr(feature = "render", uuid = "a3c5e7f1-8b2d-4e6a-9c0f-1d3b5a7e9f24")]
 pub struct Tileset {
     /// The global tile id of the first tile in this tileset.
     pub first_gid: u32,
@@ -41,9 +75,98 @@ pub struct Tileset {
     pub image: Option<Texture>,
     /// The size in pixels of tiles in this tileset
     pub tile_size: Vec2,
+    /// The number of tile columns in the tileset image, or 0 if all tiles provide their own image.
+    pub columns: u32,
+    /// Offset in pixels applied when drawing tiles from this tileset.
+    pub tile_offset: IVec2,
+    /// Orientation of the grid that tiled uses for tile objects and terrain overlays of this tileset.
+    pub grid_orientation: GridOrientation,
+    /// Size in pixels of a cell in the grid, this defaults to the tile size.
+    pub grid_size: Vec2,
+    /// The point of a tile object's image that is placed at the position of the object.
+    pub object_alignment: ObjectAlignment,
+    /// How the image of a tile object fills the object when their sizes don't match.
+    pub fill_mode: FillMode,
+}
+
+/// How the image of a tile object is fit to the size of the object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FillMode {
+    /// The image is stretched to the size of the object.
+    Stretch,
+    /// The image is scaled to the largest size that fits in the object while keeping its aspect ratio,
+    ///  centered within the object.
+    PreserveAspectFit,
+}
+
+/// Orientation of the grid of a tileset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub enum GridOrientation {
+    Orthogonal,
+    Isometric,
+}
+
+/// Alignment of tile objects relative to their position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub enum ObjectAlignment {
+    /// Bottom left for orthogonal maps and bottom center for isometric maps.
+    Unspecified,
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl ObjectAlignment {
+    /// The anchor point of a tile object as a fraction of its size, where `(0, 0)` is the top left
+    ///  and `(1, 1)` the bottom right corner of the image.
+    pub fn anchor(self, tile_type: &TileType) -> Vec2 {
+        match self {
+            ObjectAlignment::Unspecified => match tile_type {
+                TileType::Isometric { .. } => Vec2::new(0.5, 1.0),
+                _ => Vec2::new(0.0, 1.0),
+            },
+            ObjectAlignment::TopLeft => Vec2::new(0.0, 0.0),
+            ObjectAlignment::Top => Vec2::new(0.5, 0.0),
+            ObjectAlignment::TopRight => Vec2::new(1.0, 0.0),
+            ObjectAlignment::Left => Vec2::new(0.0, 0.5),
+            ObjectAlignment::Center => Vec2::new(0.5, 0.5),
+            ObjectAlignment::Right => Vec2::new(1.0, 0.5),
+            ObjectAlignment::BottomLeft => Vec2::new(0.0, 1.0),
+            ObjectAlignment::Bottom => Vec2::new(0.5, 1.0),
+            ObjectAlignment::BottomRight => Vec2::new(1.0, 1.0),
+        }
+    }
+}
+
+impl FillMode {
+    /// The size of the image of a tile of `tile_size` within an object of `object_size`,
+    ///  as a fraction of the object size.
+    pub fn fit(self, tile_size: Vec2, object_size: Vec2) -> Vec2 {
+        match self {
+            FillMode::PreserveAspectFit
+                if tile_size.x > 0.0
+                    && tile_size.y > 0.0
+                    && object_size.x > 0.0
+                    && object_size.y > 0.0 =>
+            {
+                let scale = (object_size / tile_size).min_element();
+                tile_size * scale / object_size
+            }
+            _ => Vec2::ONE,
+        }
+    }
 }
 
 /// A single tile description
+#[derive(Clone)]
 pub struct Tile {
     /// The image that this tile was taken from
     pub image: Option<Texture>,
@@ -61,11 +184,31 @@ pub struct Tile {
     pub properties: HashMap<String, Property>,
     /// ObjectGroup attached to this tile
     pub object_group: Vec<Object>,
+    /// Relative chance of this tile being picked by the terrain and random brushes of tiled, defaults to 1.0.
+    pub probability: f32,
+    /// Custom type for the tile, called "class" since tiled 1.9
+    pub ty: String,
+}
+
+impl Tile {
+    /// The collision shapes of this tile, as defined in the tile collision editor of tiled.
+    /// The shapes are relative to the top left corner of the tile, in pixels.
+    pub fn colliders(&self) -> impl Iterator<Item = Collider> + '_ {
+        self.object_group
+            .iter()
+            .map(|object| object.collider().translated(Vec2::new(object.x, object.y)))
+    }
+
+    /// The custom properties of this tile sorted by name, for a deterministic order.
+    pub fn properties_sorted(&self) -> Vec<(&str, &Property)> {
+        property::sorted_properties(&self.properties)
+    }
 }
 
 /// Animation frame within a tile
+#[derive(Clone)]
 pub struct Frame {
-    /// Global tile id of the animation frame.
+    /// Local tile id within the tileset of the animation frame.
     pub tile: u32,
     /// Duration in ms
     pub duration: u32,
@@ -78,13 +221,13 @@ pub struct Object {
     pub id: u32,
     /// Custom properties defined on the object.
     pub properties: HashMap<String, Property>,
-    /// Global tile id defining an optional sprite for this object.
-    pub tile: Option<u32>,
-    /// The shape of this object.
+    /// The kind of object, as specified in the tiled editor, along with the data that belongs to it.
+    pub kind: ObjectKind,
+    /// The outline of this object, for drawing it. For polygons and polylines these are the points of the kind.
     pub shape: Shape,
     /// Custom name for the object
     pub name: String,
-    /// Custom type for the object
+    /// Custom type for the object, called "class" since tiled 1.9
     pub ty: String,
     /// left X coordinate in pixels where the object is positioned.
     pub x: f32,
@@ -98,6 +241,253 @@ pub struct Object {
     pub rotation: f32,
     /// Whether the object is visible. Invisible objects have their `Draw` component set to invisible.
     pub visible: bool,
+    /// The point of a tile object that its position refers to, as a fraction of its size with the y axis pointing down.
+    /// This is the `object_alignment` of the tileset of the tile, which is the bottom left corner by default.
+    pub tile_anchor: Vec2,
+}
+
+impl Object {
+    /// Global tile id defining the sprite of this object, including the flip flags of the sprite.
+    /// Only objects of the kind `ObjectKind::Tile` have a tile.
+    pub fn tile(&self) -> Option<u32> {
+        match self.kind {
+            ObjectKind::Tile(gid) => Some(gid),
+            _ => None,
+        }
+    }
+
+    /// Text and font information, if this is a text object.
+    pub fn text(&self) -> Option<&TextData> {
+        match &self.kind {
+            ObjectKind::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// The collision shape of this object, relative to the object's position in tiled pixel coordinates.
+    /// Use `Collider::scaled` to convert it to the coordinate system of the loaded scene.
+    /// Tile objects are anchored at their `tile_anchor`.
+    pub fn collider(&self) -> Collider {
+        let size = Vec2::new(self.width, self.height);
+        match &self.kind {
+            ObjectKind::Tile(_) => Collider::Rect {
+                center: (Vec2::splat(0.5) - self.tile_anchor) * size,
+                half_extents: size * 0.5,
+            },
+            ObjectKind::Rectangle | ObjectKind::Text(_) => Collider::Rect {
+                center: size * 0.5,
+                half_extents: size * 0.5,
+            },
+            ObjectKind::Ellipse => Collider::Ellipse {
+                center: size * 0.5,
+                radii: size * 0.5,
+            },
+            ObjectKind::Polygon(points) => Collider::Polygon(points.clone()),
+            ObjectKind::Polyline(points) => Collider::Polyline(points.clone()),
+            ObjectKind::Point => Collider::Point(Vec2::ZERO),
+        }
+    }
+
+    /// The center of this object in tiled pixel coordinates, with the rotation of the object applied.
+    /// For polygons and polylines this is the center of their bounding box, and points are their own center.
+    /// Tile objects are anchored at their `tile_anchor`, like in `Object::collider`.
+    pub fn center(&self) -> Vec2 {
+        let size = Vec2::new(self.width, self.height);
+        let local = match &self.kind {
+            ObjectKind::Rectangle | ObjectKind::Ellipse | ObjectKind::Text(_) => size * 0.5,
+            ObjectKind::Tile(_) => (Vec2::splat(0.5) - self.tile_anchor) * size,
+            ObjectKind::Polygon(points) | ObjectKind::Polyline(points) => {
+                let mut points = points.iter().copied();
+                let first = points.next().unwrap_or_default();
+                let (min, max) =
+                    points.fold((first, first), |(min, max), p| (min.min(p), max.max(p)));
+                (min + max) * 0.5
+            }
+            ObjectKind::Point => Vec2::ZERO,
+        };
+
+        // tiled rotates clockwise around the position of the object, with the y axis pointing down
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        Vec2::new(
+            self.x + local.x * cos - local.y * sin,
+            self.y + local.x * sin + local.y * cos,
+        )
+    }
+
+    /// The custom properties of this object sorted by name, for a deterministic order.
+    pub fn properties_sorted(&self) -> Vec<(&str, &Property)> {
+        property::sorted_properties(&self.properties)
+    }
+
+    /// Deserialize the custom properties of this object into a user defined type, with a field per property:
+    /// ```ignore
+    /// #[derive(Deserialize)]
+    /// struct Enemy {
+    ///     hp: i32,
+    ///     speed: f32,
+    ///     #[serde(default)]
+    ///     boss: bool,
+    /// }
+    ///
+    /// let enemy: Enemy = object.deserialize_properties()?;
+    /// ```
+    /// Class properties deserialize as nested structs, colors as `[a, r, g, b]` arrays and object references as `u32`.
+    pub fn deserialize_properties<T: serde::de::DeserializeOwned>(&self) -> anyhow::Result<T> {
+        de::from_properties(&self.properties).map_err(|e| {
+            anyhow::anyhow!(
+                "invalid properties for object {} `{}`: {}",
+                self.id,
+                self.name,
+                e
+            )
+        })
+    }
+}
+
+/// An object template, loaded from a standalone template file (.tx).
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "render", derive(TypeUuid))]
+#[cfg_attr(feature = "render", uuid = "6e1f9a3c-2d75-4b08-a4c6-8f0e3b7d5a19")]
+pub struct ObjectTemplate {
+    /// The object that instances of the template start out as.
+    /// For tile objects, the tile of `ObjectKind::Tile` is the tile id within `tileset`, instead of a global tile id.
+    pub object: Object,
+    /// The source file of the tileset that the tile of a tile object is taken from.
+    pub tileset: Option<String>,
+}
+
+/// The kind of an object, with the data that belongs to it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ObjectKind {
+    /// A rectangle spanning `width` and `height`.
+    Rectangle,
+    /// An ellipse that fits within `width` and `height`.
+    Ellipse,
+    /// A single point at the position of the object, like a waypoint or spawn marker.
+    Point,
+    /// A closed polygon, with points relative to the position of the object.
+    Polygon(Vec<Vec2>),
+    /// An open line of points, relative to the position of the object.
+    Polyline(Vec<Vec2>),
+    /// A tile object, showing the tile with this global tile id stretched to `width` and `height`.
+    /// The gid includes the flip flags of the sprite.
+    Tile(u32),
+    /// A text label.
+    Text(TextData),
+}
+
+/// The contents of a text object
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextData {
+    /// The text to display.
+    pub text: String,
+    /// The font family used, defaults to "sans-serif".
+    pub font_family: String,
+    /// The size of the font in pixels, defaults to 16.
+    pub pixel_size: u32,
+    /// Color of the text in the format `[a, r, g, b]`, defaults to black.
+    pub color: [u8; 4],
+    /// Whether word wrapping is enabled.
+    pub wrap: bool,
+    /// Horizontal alignment of the text within the object.
+    pub halign: HorizontalAlignment,
+    /// Vertical alignment of the text within the object.
+    pub valign: VerticalAlignment,
+}
+
+/// Horizontal alignment of text
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub enum HorizontalAlignment {
+    Left,
+    Center,
+    Right,
+    Justify,
+}
+
+/// Vertical alignment of text
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub enum VerticalAlignment {
+    Top,
+    Center,
+    Bottom,
+}
+
+/// Collision geometry of an object, relative to the position of the object.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Collider {
+    /// An axis aligned rectangle.
+    Rect {
+        /// Center of the rectangle.
+        center: Vec2,
+        /// Half of the width and height of the rectangle.
+        half_extents: Vec2,
+    },
+    /// An axis aligned ellipse.
+    Ellipse {
+        /// Center of the ellipse.
+        center: Vec2,
+        /// Radius of the ellipse along the x and y axis.
+        radii: Vec2,
+    },
+    /// A closed polygon.
+    Polygon(Vec<Vec2>),
+    /// An open line of points.
+    Polyline(Vec<Vec2>),
+    /// A single point. For point objects this is always at the position of the object.
+    Point(Vec2),
+}
+
+impl Collider {
+    /// Scale the collider to world coordinates. Pass the same scale as the one given to `TmxPlugin::scale`
+    ///  to get a collider that matches the transform of the spawned object entity.
+    pub fn scaled(&self, scale: Vec2) -> Collider {
+        match self {
+            Collider::Rect {
+                center,
+                half_extents,
+            } => Collider::Rect {
+                center: *center * scale,
+                half_extents: (*half_extents * scale).abs(),
+            },
+            Collider::Ellipse { center, radii } => Collider::Ellipse {
+                center: *center * scale,
+                radii: (*radii * scale).abs(),
+            },
+            Collider::Polygon(points) => {
+                Collider::Polygon(points.iter().map(|&p| p * scale).collect())
+            }
+            Collider::Polyline(points) => {
+                Collider::Polyline(points.iter().map(|&p| p * scale).collect())
+            }
+            Collider::Point(point) => Collider::Point(*point * scale),
+        }
+    }
+
+    /// Move the collider by `offset`.
+    pub fn translated(&self, offset: Vec2) -> Collider {
+        match self {
+            Collider::Rect {
+                center,
+                half_extents,
+            } => Collider::Rect {
+                center: *center + offset,
+                half_extents: *half_extents,
+            },
+            Collider::Ellipse { center, radii } => Collider::Ellipse {
+                center: *center + offset,
+                radii: *radii,
+            },
+            Collider::Polygon(points) => {
+                Collider::Polygon(points.iter().map(|&p| p + offset).collect())
+            }
+            Collider::Polyline(points) => {
+                Collider::Polyline(points.iter().map(|&p| p + offset).collect())
+            }
+            Collider::Point(point) => Collider::Point(*point + offset),
+        }
+    }
 }
 
 /// A shape.
@@ -108,3 +498,286 @@ pub struct Shape {
     /// Whether the last point should be connected to the first point.
     pub closed: bool,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{fixtures, map_xml, parse};
+
+    #[test]
+    fn render_orders_iterate_tiles_in_drawing_order() {
+        let order = |render_order: RenderOrder| render_order.tile_indices(3, 2).collect::<Vec<_>>();
+        // indices of a 3x2 grid:
+        // 0 1 2
+        // 3 4 5
+        assert_eq!(order(RenderOrder::RightDown), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(order(RenderOrder::RightUp), vec![3, 4, 5, 0, 1, 2]);
+        assert_eq!(order(RenderOrder::LeftDown), vec![2, 1, 0, 5, 4, 3]);
+        assert_eq!(order(RenderOrder::LeftUp), vec![5, 4, 3, 2, 1, 0]);
+    }
+
+    /// The objects of a map with an object layer containing `objects`.
+    fn objects(objects: &str) -> Vec<Object> {
+        let map = parse(&map_xml(
+            4,
+            4,
+            "",
+            &format!(
+                r#" <objectgroup id="2" name="objects">{}</objectgroup>"#,
+                objects
+            ),
+        ));
+        map.objects().map(|(_, object)| object.clone()).collect()
+    }
+
+    #[test]
+    fn every_object_kind_has_a_collider() {
+        let objects = objects(
+            r#"<object id="1" x="0" y="0" width="20" height="10"/>
+               <object id="2" x="0" y="0" width="20" height="10"><ellipse/></object>
+               <object id="3" x="0" y="0"><polygon points="0,0 10,0 0,5"/></object>
+               <object id="4" x="0" y="0"><polyline points="0,0 10,5"/></object>
+               <object id="5" x="3" y="4"><point/></object>
+               <object id="6" gid="1" x="0" y="16" width="16" height="16"/>
+               <object id="7" x="0" y="0" width="20" height="10"><text>hi</text></object>"#,
+        );
+        let scale = Vec2::new(2.0, -2.0);
+        let colliders: Vec<Collider> = objects
+            .iter()
+            .map(|object| object.collider().scaled(scale))
+            .collect();
+        assert_eq!(
+            colliders,
+            vec![
+                Collider::Rect {
+                    center: Vec2::new(20.0, -10.0),
+                    half_extents: Vec2::new(20.0, 10.0),
+                },
+                Collider::Ellipse {
+                    center: Vec2::new(20.0, -10.0),
+                    radii: Vec2::new(20.0, 10.0),
+                },
+                Collider::Polygon(vec![
+                    Vec2::new(0.0, 0.0),
+                    Vec2::new(20.0, 0.0),
+                    Vec2::new(0.0, -10.0),
+                ]),
+                Collider::Polyline(vec![Vec2::new(0.0, 0.0), Vec2::new(20.0, -10.0)]),
+                Collider::Point(Vec2::ZERO),
+                // tile objects are anchored at their bottom left corner in orthogonal maps
+                Collider::Rect {
+                    center: Vec2::new(16.0, 16.0),
+                    half_extents: Vec2::new(16.0, 16.0),
+                },
+                Collider::Rect {
+                    center: Vec2::new(20.0, -10.0),
+                    half_extents: Vec2::new(20.0, 10.0),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn object_properties_deserialize_into_structs() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        enum Kind {
+            Goblin,
+            Troll,
+        }
+
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Stats {
+            armor: i32,
+        }
+
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Enemy {
+            hp: i32,
+            speed: f32,
+            #[serde(default)]
+            boss: bool,
+            kind: Kind,
+            tint: [u8; 4],
+            target: Option<u32>,
+            stats: Stats,
+        }
+
+        let objects = objects(
+            r##"<object id="1" x="0" y="0">
+                <properties>
+                    <property name="hp" type="int" value="30"/>
+                    <property name="speed" type="float" value="1.5"/>
+                    <property name="kind" value="Troll"/>
+                    <property name="tint" type="color" value="#80ff0000"/>
+                    <property name="target" type="object" value="7"/>
+                    <property name="stats" type="class">
+                        <properties>
+                            <property name="armor" type="int" value="4"/>
+                        </properties>
+                    </property>
+                </properties>
+            </object>
+            <object id="2" x="0" y="0">
+                <properties>
+                    <property name="hp" value="lots"/>
+                </properties>
+            </object>"##,
+        );
+
+        let enemy: Enemy = objects[0].deserialize_properties().unwrap();
+        assert_eq!(
+            enemy,
+            Enemy {
+                hp: 30,
+                speed: 1.5,
+                boss: false,
+                kind: Kind::Troll,
+                tint: [0x80, 0xff, 0, 0],
+                target: Some(7),
+                stats: Stats { armor: 4 },
+            }
+        );
+        assert_ne!(enemy.kind, Kind::Goblin);
+
+        // a property of the wrong type names the object in the error
+        let error = objects[1].deserialize_properties::<Enemy>().unwrap_err();
+        assert!(error.to_string().contains("object 2"), "{}", error);
+    }
+
+    #[test]
+    fn every_object_kind_is_parsed() {
+        // the same objects, in the xml and the json format
+        for file in ["kinds.tmx", "kinds.tmj"] {
+            let map = parse(&std::fs::read_to_string(fixtures().join(file)).unwrap());
+            let objects: Vec<&Object> = map.objects().map(|(_, object)| object).collect();
+            assert_eq!(objects.len(), 7, "{}", file);
+
+            // every kind carries the data that belongs to it
+            assert_eq!(objects[0].kind, ObjectKind::Rectangle);
+            assert_eq!(objects[1].kind, ObjectKind::Ellipse);
+            match &objects[2].kind {
+                ObjectKind::Polygon(points) => assert_eq!(
+                    points,
+                    &vec![
+                        Vec2::new(0.0, 0.0),
+                        Vec2::new(10.0, 0.0),
+                        Vec2::new(0.0, 5.0)
+                    ]
+                ),
+                kind => panic!("expected a polygon in {}, got {:?}", file, kind),
+            }
+            match &objects[3].kind {
+                ObjectKind::Polyline(points) => {
+                    assert_eq!(points, &vec![Vec2::new(0.0, 0.0), Vec2::new(10.0, 5.0)])
+                }
+                kind => panic!("expected a polyline in {}, got {:?}", file, kind),
+            }
+            assert_eq!(objects[4].kind, ObjectKind::Point);
+            match objects[5].kind {
+                ObjectKind::Tile(gid) => assert_eq!(gid, 3),
+                ref kind => panic!("expected a tile object in {}, got {:?}", file, kind),
+            }
+            match &objects[6].kind {
+                ObjectKind::Text(text) => {
+                    assert_eq!(text.text, "hi");
+                    assert!(text.wrap);
+                }
+                kind => panic!("expected a text object in {}, got {:?}", file, kind),
+            }
+
+            // the outlines of polygons and polylines are made of their points
+            assert!(objects[2].shape.closed);
+            assert_eq!(objects[2].shape.points.len(), 3);
+            assert!(!objects[3].shape.closed);
+            assert_eq!(objects[3].shape.points.len(), 2);
+            for (i, object) in objects.iter().enumerate() {
+                assert_eq!(object.tile().is_some(), i == 5);
+                assert_eq!(object.text().is_some(), i == 6);
+            }
+        }
+    }
+
+    #[test]
+    fn object_centers_account_for_size_and_rotation() {
+        let objects = objects(
+            r#"<object id="1" x="10" y="20" width="20" height="10"/>
+               <object id="2" x="10" y="20" width="20" height="10" rotation="90"/>
+               <object id="3" x="3" y="4"><point/></object>
+               <object id="4" gid="1" x="0" y="16" width="16" height="16"/>
+               <object id="5" x="10" y="10"><polygon points="0,0 10,0 10,-20"/></object>"#,
+        );
+        let centers: Vec<Vec2> = objects.iter().map(Object::center).collect();
+        let expected = [
+            Vec2::new(20.0, 25.0),
+            // turned clockwise around the top left corner, the center ends up below and left of it
+            Vec2::new(5.0, 30.0),
+            Vec2::new(3.0, 4.0),
+            // tile objects are anchored at their bottom left corner
+            Vec2::new(8.0, 8.0),
+            Vec2::new(15.0, 0.0),
+        ];
+        for (center, expected) in centers.iter().zip(expected.iter()) {
+            assert!(
+                center.abs_diff_eq(*expected, 1e-4),
+                "{} != {}",
+                center,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn sorted_properties_have_a_stable_order() {
+        let source = map_xml(
+            1,
+            1,
+            "",
+            r#" <properties>
+  <property name="zeta" value="1"/>
+  <property name="alpha" value="2"/>
+  <property name="mu" value="3"/>
+ </properties>
+ <tileset firstgid="5" name="props" tilewidth="16" tileheight="16" tilecount="1" columns="1">
+  <image source="tiles.png" width="16" height="16"/>
+  <tile id="0">
+   <properties>
+    <property name="solid" type="bool" value="true"/>
+    <property name="friction" type="float" value="0.5"/>
+    <property name="kind" value="ice"/>
+   </properties>
+  </tile>
+ </tileset>
+ <objectgroup id="1" name="objects">
+  <object id="1" x="0" y="0">
+   <properties>
+    <property name="b" value="1"/>
+    <property name="c" value="2"/>
+    <property name="a" value="3"/>
+   </properties>
+  </object>
+ </objectgroup>"#,
+        );
+        let names = |properties: Vec<(&str, &Property)>| -> Vec<String> {
+            properties
+                .into_iter()
+                .map(|(name, _)| name.to_string())
+                .collect()
+        };
+        let orders = |map: &Map| {
+            (
+                names(map.properties_sorted()),
+                names(map.get_tile(5).unwrap().properties_sorted()),
+                names(map.objects().next().unwrap().1.properties_sorted()),
+            )
+        };
+
+        let first = orders(&parse(&source));
+        assert_eq!(first.0, vec!["alpha", "mu", "zeta"]);
+        assert_eq!(first.1, vec!["friction", "kind", "solid"]);
+        assert_eq!(first.2, vec!["a", "b", "c"]);
+        // every map has its own hash maps, with their own iteration order
+        for _ in 0..10 {
+            assert_eq!(orders(&parse(&source)), first);
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+
+use serde::de::value::{Error, MapDeserializer, SeqDeserializer};
+use serde::de::{DeserializeOwned, IntoDeserializer, Visitor};
+use serde::{forward_to_deserialize_any, Deserializer};
+
+use super::Property;
+
+/// Deserialize a set of custom properties as if it was a map of property names to values.
+/// Colors are deserialized as `[a, r, g, b]` sequences, and string properties can be deserialized as unit enum variants.
+pub(crate) fn from_properties<T: DeserializeOwned>(
+    properties: &HashMap<String, Property>,
+) -> Result<T, Error> {
+    T::deserialize(PropertiesDeserializer(properties))
+}
+
+struct PropertiesDeserializer<'a>(&'a HashMap<String, Property>);
+
+struct PropertyDeserializer<'a>(&'a Property);
+
+impl<'de> Deserializer<'de> for PropertiesDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_map(MapDeserializer::new(
+            self.0
+                .iter()
+                .map(|(key, value)| (key.as_str(), PropertyDeserializer(value))),
+        ))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de> Deserializer<'de> for PropertyDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Property::String(x) | Property::File(x) => visitor.visit_borrowed_str(x.as_str()),
+            Property::Int(x) => visitor.visit_i32(*x),
+            Property::Float(x) => visitor.visit_f64(*x),
+            Property::Bool(x) => visitor.visit_bool(*x),
+            Property::Color(x) => visitor.visit_seq(SeqDeserializer::new(x.iter().copied())),
+            Property::Object(x) => visitor.visit_u32(*x),
+            Property::Class(x) => PropertiesDeserializer(x).deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.0 {
+            Property::String(x) => x
+                .as_str()
+                .into_deserializer()
+                .deserialize_enum(name, variants, visitor),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for PropertyDeserializer<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
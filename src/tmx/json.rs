@@ -0,0 +1,671 @@
+use std::path::Path;
+use std::pin::Pin;
+
+use anyhow::*;
+use bevy_math::{IVec2, UVec2, Vec4};
+use serde_json::Value;
+
+use crate::tmx::map::Map;
+use crate::tmx::parse::*;
+use crate::TmxLoadContext;
+
+use super::*;
+
+/// Whether `path` refers to a file in the json format, based on its extension.
+pub(crate) fn is_json_file(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("json") | Some("tmj") | Some("tsj") | Some("tj")
+    )
+}
+
+fn get_str<'v>(value: &'v Value, key: &str) -> Option<&'v str> {
+    value.get(key).and_then(Value::as_str)
+}
+
+fn get_f64(value: &Value, key: &str) -> Option<f64> {
+    value.get(key).and_then(Value::as_f64)
+}
+
+fn get_i32(value: &Value, key: &str) -> Option<i32> {
+    get_f64(value, key).map(|v| v as i32)
+}
+
+fn get_u32(value: &Value, key: &str) -> Option<u32> {
+    get_f64(value, key).map(|v| v as u32)
+}
+
+fn get_bool(value: &Value, key: &str) -> Option<bool> {
+    value.get(key).and_then(Value::as_bool)
+}
+
+fn get_array<'v>(value: &'v Value, key: &str) -> &'v [Value] {
+    value
+        .get(key)
+        .and_then(Value::as_array)
+        .map_or(&[], |a| a.as_slice())
+}
+
+impl World {
+    pub(crate) fn load_from_json(bytes: &[u8]) -> Result<Self> {
+        let value: Value = serde_json::from_slice(bytes)?;
+        let maps = get_array(&value, "maps")
+            .iter()
+            .map(|map| {
+                Ok(WorldMap {
+                    file_name: get_str(map, "fileName")
+                        .ok_or_else(|| anyhow!("world map without a file name"))?
+                        .to_string(),
+                    position: IVec2::new(
+                        get_i32(map, "x").unwrap_or(0),
+                        get_i32(map, "y").unwrap_or(0),
+                    ),
+                    size: UVec2::new(
+                        get_u32(map, "width").unwrap_or(0),
+                        get_u32(map, "height").unwrap_or(0),
+                    ),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(World {
+            maps,
+            only_show_adjacent_maps: get_bool(&value, "onlyShowAdjacentMaps").unwrap_or(false),
+        })
+    }
+}
+
+impl Map {
+    pub(crate) async fn load_from_json(env: TmxLoadContext<'_>, bytes: &[u8]) -> Result<Self> {
+        let value: Value = serde_json::from_slice(bytes)?;
+        Map::parse_json(env, &value).await
+    }
+
+    async fn parse_json(env: TmxLoadContext<'_>, value: &Value) -> Result<Self> {
+        let mut result = Map {
+            properties: parse_json_properties(value)?,
+            tilesets: Vec::new(),
+            layers: Vec::new(),
+
+            width: get_u32(value, "width").unwrap_or(0),
+            height: get_u32(value, "height").unwrap_or(0),
+            infinite: get_bool(value, "infinite").unwrap_or(false),
+            tile_type: TileType::new(
+                get_str(value, "orientation").unwrap_or("orthogonal"),
+                get_u32(value, "tilewidth").unwrap_or(0),
+                get_u32(value, "tileheight").unwrap_or(0),
+                get_str(value, "renderorder").map_or(RenderOrder::RightDown, parse_render_order),
+                get_str(value, "staggeraxis")
+                    .map(parse_stagger_axis)
+                    .unwrap_or(false),
+                get_str(value, "staggerindex")
+                    .map(parse_stagger_index)
+                    .unwrap_or(true),
+                get_u32(value, "hexsidelength").unwrap_or(0),
+            ),
+
+            background: get_str(value, "backgroundcolor").map_or(Ok([0; 4]), parse_color)?,
+            parallax_origin: Vec2::new(
+                get_f64(value, "parallaxoriginx").unwrap_or(0.0) as f32,
+                get_f64(value, "parallaxoriginy").unwrap_or(0.0) as f32,
+            ),
+            // older versions of tiled wrote the version as a number
+            version: match value.get("version") {
+                Some(Value::String(version)) => version.clone(),
+                Some(Value::Number(version)) => version.to_string(),
+                _ => String::new(),
+            },
+            tiled_version: get_str(value, "tiledversion").unwrap_or("").to_string(),
+        };
+
+        for tileset in get_array(value, "tilesets") {
+            result
+                .tilesets
+                .push(Arc::new(Tileset::parse_json(env.clone(), tileset).await?));
+        }
+
+        for layer in get_array(value, "layers") {
+            let layer = Layer::parse_json(env.clone(), layer).await?;
+            result = layer.process(env.clone(), result).await?;
+        }
+
+        result.tilesets.sort_by_key(|tileset| tileset.first_gid);
+
+        Ok(result)
+    }
+}
+
+impl Tileset {
+    /// Parse a tileset object. This can be either an external reference or an actual tileset.
+    async fn parse_json(env: TmxLoadContext<'_>, value: &Value) -> Result<Self> {
+        let tileset = Tileset::new(
+            get_u32(value, "firstgid").unwrap_or(0),
+            format!("embedded#{}", get_str(value, "name").unwrap_or("")),
+        );
+
+        if let Some(source) = get_str(value, "source") {
+            Tileset::load_external(tileset, env, source).await
+        } else {
+            Tileset::parse_json_tileset(tileset, env, value).await
+        }
+    }
+
+    /// Parse the actual tileset content
+    pub(super) async fn parse_json_tileset(
+        mut tileset: Tileset,
+        env: TmxLoadContext<'_>,
+        value: &Value,
+    ) -> Result<Tileset> {
+        let grid = TileGrid {
+            tile_width: get_i32(value, "tilewidth").unwrap_or(0),
+            tile_height: get_i32(value, "tileheight").unwrap_or(0),
+            spacing: get_i32(value, "spacing").unwrap_or(0),
+            margin: get_i32(value, "margin").unwrap_or(0),
+            tile_count: get_u32(value, "tilecount"),
+            // collection tilesets have 0 columns, which would otherwise leave the image tiles empty
+            columns: get_i32(value, "columns").filter(|&c| c > 0),
+        };
+
+        tileset.tile_size.x = grid.tile_width as f32;
+        tileset.tile_size.y = grid.tile_height as f32;
+        tileset.grid_size = tileset.tile_size;
+
+        if let Some(grid) = value.get("grid") {
+            if let Some(orientation) = get_str(grid, "orientation") {
+                tileset.grid_orientation = parse_grid_orientation(orientation)?;
+            }
+            tileset.grid_size.x = get_f64(grid, "width").unwrap_or(0.0) as f32;
+            tileset.grid_size.y = get_f64(grid, "height").unwrap_or(0.0) as f32;
+        }
+
+        if let Some(alignment) = get_str(value, "objectalignment") {
+            tileset.object_alignment = parse_object_alignment(alignment)?;
+        }
+
+        if let Some(fill_mode) = get_str(value, "fillmode") {
+            tileset.fill_mode = parse_fill_mode(fill_mode)?;
+        }
+
+        if let Some(offset) = value.get("tileoffset") {
+            tileset.tile_offset.x = get_i32(offset, "x").unwrap_or(0);
+            tileset.tile_offset.y = get_i32(offset, "y").unwrap_or(0);
+        }
+
+        if let Some(image) = get_str(value, "image") {
+            let image = load_image(
+                env.clone(),
+                Some(image.to_string()),
+                None,
+                get_str(value, "transparentcolor")
+                    .map(parse_transparent_color)
+                    .transpose()?,
+                get_u32(value, "imagewidth"),
+                get_u32(value, "imageheight"),
+            )
+            .await?;
+            tileset.add_image_tiles(image, &grid);
+        }
+
+        for tile in get_array(value, "tiles") {
+            let (id, tile) = Tile::parse_json(env.clone(), tile).await?;
+            tileset.add_tile(id, tile);
+        }
+
+        Ok(tileset)
+    }
+}
+
+impl Tile {
+    async fn parse_json(env: TmxLoadContext<'_>, value: &Value) -> Result<(usize, Tile)> {
+        let id = get_u32(value, "id").unwrap_or(0) as usize;
+
+        let mut result = Tile {
+            image: None,
+            top_left: Vec2::new(0.0, 0.0),
+            bottom_right: Vec2::new(1.0, 1.0),
+            width: 0,
+            height: 0,
+            animation: get_array(value, "animation")
+                .iter()
+                .map(|frame| Frame {
+                    tile: get_u32(frame, "tileid").unwrap_or(0),
+                    duration: get_u32(frame, "duration").unwrap_or(0),
+                })
+                .collect(),
+            properties: parse_json_properties(value)?,
+            object_group: Vec::new(),
+            probability: get_f64(value, "probability").unwrap_or(1.0) as f32,
+            ty: get_str(value, "type")
+                .or_else(|| get_str(value, "class"))
+                .unwrap_or("")
+                .to_string(),
+        };
+
+        if let Some(image) = get_str(value, "image") {
+            let image = load_image(
+                env.clone(),
+                Some(image.to_string()),
+                None,
+                get_str(value, "transparentcolor")
+                    .map(parse_transparent_color)
+                    .transpose()?,
+                get_u32(value, "imagewidth"),
+                get_u32(value, "imageheight"),
+            )
+            .await?;
+            result.width = image.width() as i32;
+            result.height = image.height() as i32;
+            result.image = Some(image);
+            result.select_sub_rectangle(
+                IVec2::new(
+                    get_i32(value, "x").unwrap_or(0),
+                    get_i32(value, "y").unwrap_or(0),
+                ),
+                IVec2::new(
+                    get_i32(value, "width").unwrap_or(0),
+                    get_i32(value, "height").unwrap_or(0),
+                ),
+            );
+        }
+
+        if let Some(group) = value.get("objectgroup") {
+            if let Layer::ObjectLayer { objects, .. } = Layer::parse_json(env, group).await? {
+                result.object_group = objects;
+            }
+        }
+
+        Ok((id, result))
+    }
+}
+
+impl Layer {
+    fn parse_json<'a>(
+        env: TmxLoadContext<'a>,
+        value: &'a Value,
+    ) -> Pin<Box<dyn Future<Output = Result<Self>> + Send + 'a>> {
+        Box::pin(async move {
+            let name = get_str(value, "name").unwrap_or("").to_string();
+            let offset = IVec2::new(
+                get_i32(value, "offsetx").unwrap_or(0),
+                get_i32(value, "offsety").unwrap_or(0),
+            );
+            let parallax = Vec2::new(
+                get_f64(value, "parallaxx").unwrap_or(1.0) as f32,
+                get_f64(value, "parallaxy").unwrap_or(1.0) as f32,
+            );
+            let mut color = Vec4::new(1.0, 1.0, 1.0, 1.0);
+            let opacity = get_f64(value, "opacity").unwrap_or(1.0) as f32;
+            if let Some(tint) = get_str(value, "tintcolor") {
+                color *= parse_color_vec4(tint)?;
+            }
+            let visible = get_bool(value, "visible").unwrap_or(true);
+
+            match get_str(value, "type").unwrap_or("") {
+                "tilelayer" => {
+                    let mut position = IVec2::new(
+                        get_i32(value, "x").unwrap_or(0),
+                        get_i32(value, "y").unwrap_or(0),
+                    );
+                    let mut size = UVec2::new(
+                        get_u32(value, "width").unwrap_or(0),
+                        get_u32(value, "height").unwrap_or(0),
+                    );
+
+                    let mut encoding = DataEncoding::default();
+                    encoding.set_encoding(get_str(value, "encoding").unwrap_or("csv"));
+                    encoding.set_compression(get_str(value, "compression").unwrap_or(""));
+
+                    let mut data = parse_json_data(&encoding, value.get("data"))?;
+
+                    let chunks = get_array(value, "chunks");
+                    if !chunks.is_empty() {
+                        let chunks = chunks
+                            .iter()
+                            .map(|chunk| {
+                                Ok(Chunk {
+                                    position: IVec2::new(
+                                        get_i32(chunk, "x").unwrap_or(0),
+                                        get_i32(chunk, "y").unwrap_or(0),
+                                    ),
+                                    size: UVec2::new(
+                                        get_u32(chunk, "width").unwrap_or(0),
+                                        get_u32(chunk, "height").unwrap_or(0),
+                                    ),
+                                    data: parse_json_data(&encoding, chunk.get("data"))?,
+                                })
+                            })
+                            .collect::<Result<Vec<_>>>()?;
+                        let (chunk_position, chunk_size, chunk_data) =
+                            Data::merge_chunks(chunks)
+                                .with_context(|| format!("invalid data in layer `{}`", name))?;
+                        position += chunk_position;
+                        size = chunk_size;
+                        data = chunk_data;
+                    }
+
+                    validate_tile_data(&mut data, size)
+                        .with_context(|| format!("invalid data in layer `{}`", name))?;
+
+                    Ok(Layer::TileLayer {
+                        name,
+                        position,
+                        size,
+                        color,
+                        opacity,
+                        visible,
+                        offset,
+                        parallax,
+                        data,
+                    })
+                }
+                "objectgroup" => {
+                    let mut objects = Vec::new();
+                    for object in get_array(value, "objects") {
+                        objects.push(Object::parse_json(env.clone(), object).await?);
+                    }
+
+                    Ok(Layer::ObjectLayer {
+                        name,
+                        offset,
+                        parallax,
+                        color,
+                        opacity,
+                        visible,
+                        draworder_index: get_str(value, "draworder") == Some("index"),
+                        object_color: match get_str(value, "color") {
+                            Some(color) => parse_color_vec4(color)?,
+                            None => Layer::DEFAULT_OBJECT_COLOR,
+                        },
+                        objects,
+                    })
+                }
+                "imagelayer" => {
+                    let image = match get_str(value, "image") {
+                        Some(image) if !image.is_empty() => image,
+                        _ => bail!("no image found"),
+                    };
+                    let image = load_image(
+                        env.clone(),
+                        Some(image.to_string()),
+                        None,
+                        get_str(value, "transparentcolor")
+                            .map(parse_transparent_color)
+                            .transpose()?,
+                        get_u32(value, "imagewidth"),
+                        get_u32(value, "imageheight"),
+                    )
+                    .await?;
+
+                    Ok(Layer::ImageLayer {
+                        name,
+                        image,
+                        color,
+                        opacity,
+                        visible,
+                        offset,
+                        parallax,
+                        repeat_x: get_bool(value, "repeatx").unwrap_or(false),
+                        repeat_y: get_bool(value, "repeaty").unwrap_or(false),
+                    })
+                }
+                "group" => {
+                    let mut layers = Vec::new();
+                    for layer in get_array(value, "layers") {
+                        layers.push(Layer::parse_json(env.clone(), layer).await?);
+                    }
+
+                    for l in layers.iter_mut() {
+                        l.mul_parallax(parallax.x, parallax.y);
+                        l.mul_color(color);
+                        l.mul_opacity(opacity);
+                        if !visible {
+                            l.hide();
+                        }
+                    }
+                    Ok(Layer::Group {
+                        name,
+                        offset,
+                        layers,
+                    })
+                }
+                _ => bail!("invalid layer type"),
+            }
+        })
+    }
+}
+
+impl Object {
+    fn parse_json<'a>(
+        env: TmxLoadContext<'a>,
+        value: &'a Value,
+    ) -> Pin<Box<dyn Future<Output = Result<Object>> + Send + 'a>> {
+        Box::pin(async move {
+            let mut result = if let Some(template) = get_str(value, "template") {
+                Object::load_template(env.clone(), template).await?
+            } else {
+                Object {
+                    id: 0,
+                    properties: HashMap::new(),
+                    kind: ObjectKind::Rectangle,
+                    shape: Shape::point(),
+                    name: String::from(""),
+                    ty: String::from(""),
+                    x: 0.0,
+                    y: 0.0,
+                    width: 0.0,
+                    height: 0.0,
+                    rotation: 0.0,
+                    visible: true,
+                    tile_anchor: Vec2::new(0.0, 1.0),
+                }
+            };
+
+            // apply properties
+            if let Some(id) = get_u32(value, "id") {
+                result.id = id;
+            }
+            if let Some(gid) = get_u32(value, "gid") {
+                // the tile of the instance is a tile of the map, not of the template's tileset
+                result.kind = ObjectKind::Tile(gid);
+                result.properties.remove("__include_tileset__");
+            }
+            if let Some(name) = get_str(value, "name") {
+                result.name = name.to_string();
+            }
+            if let Some(ty) = get_str(value, "type").or_else(|| get_str(value, "class")) {
+                result.ty = ty.to_string();
+            }
+            if let Some(x) = get_f64(value, "x") {
+                result.x = x as f32;
+            }
+            if let Some(y) = get_f64(value, "y") {
+                result.y = y as f32;
+            }
+            if let Some(width) = get_f64(value, "width") {
+                result.width = width as f32;
+            }
+            if let Some(height) = get_f64(value, "height") {
+                result.height = height as f32;
+            }
+            if let Some(rotation) = get_f64(value, "rotation") {
+                result.rotation = rotation as f32;
+            }
+            if let Some(visible) = get_bool(value, "visible") {
+                result.visible = visible;
+            }
+            for (k, v) in parse_json_properties(value)? {
+                result.properties.insert(k, v);
+            }
+            result.apply_template_overrides();
+
+            if let Some(polygon) = value.get("polygon") {
+                let points = parse_json_points(polygon)?;
+                result.shape = Shape {
+                    points: points.clone(),
+                    closed: true,
+                };
+                result.kind = ObjectKind::Polygon(points);
+            }
+            if let Some(polyline) = value.get("polyline") {
+                let points = parse_json_points(polyline)?;
+                result.shape = Shape {
+                    points: points.clone(),
+                    closed: false,
+                };
+                result.kind = ObjectKind::Polyline(points);
+            }
+            if get_bool(value, "ellipse") == Some(true) {
+                result.kind = ObjectKind::Ellipse;
+                result.shape = Shape::ellipse(result.width, result.height);
+            }
+            if get_bool(value, "point") == Some(true) {
+                result.kind = ObjectKind::Point;
+                result.shape = Shape::point();
+            }
+            if let Some(text) = value.get("text") {
+                result.kind = ObjectKind::Text(parse_json_text(text)?);
+            }
+
+            Ok(result)
+        })
+    }
+
+    pub(super) async fn parse_json_template(
+        env: TmxLoadContext<'_>,
+        value: &Value,
+    ) -> Result<Object> {
+        let mut object = match value.get("object") {
+            Some(object) => Object::parse_json(env.clone(), object).await?,
+            None => bail!("object not found"),
+        };
+
+        if let ObjectKind::Tile(tile) = &mut object.kind {
+            let tileset = value
+                .get("tileset")
+                .ok_or_else(|| anyhow!("tileset not found"))?;
+            let first_gid = get_u32(tileset, "firstgid").unwrap_or(0);
+            let source = get_str(tileset, "source").unwrap_or("");
+            let source = format!("{}", env.file_path(Path::new(source)).display());
+            *tile -= first_gid;
+            object
+                .properties
+                .insert("__include_tileset__".to_string(), Property::File(source));
+        }
+        Ok(object)
+    }
+}
+
+/// Parse tile data, which is either an array of global tile ids or an encoded string.
+fn parse_json_data(encoding: &DataEncoding, data: Option<&Value>) -> Result<Vec<u32>> {
+    match data {
+        Some(Value::Array(gids)) => Ok(gids
+            .iter()
+            .map(|gid| gid.as_u64().unwrap_or(0) as u32)
+            .collect()),
+        Some(Value::String(text)) => encoding.decode(text.as_str())?.into_vec_u32(),
+        Some(_) => bail!("invalid tile data"),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn parse_json_points(points: &Value) -> Result<Vec<Vec2>> {
+    points
+        .as_array()
+        .ok_or_else(|| anyhow!("invalid point"))?
+        .iter()
+        .map(|point| match (get_f64(point, "x"), get_f64(point, "y")) {
+            (Some(x), Some(y)) => Ok(Vec2::new(x as f32, y as f32)),
+            _ => Err(anyhow!("invalid point")),
+        })
+        .collect()
+}
+
+fn parse_json_properties(value: &Value) -> Result<HashMap<String, Property>> {
+    let mut result = HashMap::new();
+
+    for property in get_array(value, "properties") {
+        let key = get_str(property, "name").unwrap_or("").to_string();
+        let ty = get_str(property, "type").unwrap_or("string");
+        let value = match property.get("value") {
+            Some(value) if ty == "class" => parse_json_class(value),
+            Some(Value::String(value)) => parse_property_value(ty, value.as_str())?,
+            Some(value) => parse_property_value(ty, value.to_string().as_str())?,
+            None => Property::Int(0),
+        };
+        result.insert(key, value);
+    }
+
+    Ok(result)
+}
+
+/// Class members in json files are stored without their types, so they are inferred from the value.
+fn parse_json_class(value: &Value) -> Property {
+    match value {
+        Value::Object(members) => Property::Class(
+            members
+                .iter()
+                .map(|(k, v)| (k.clone(), parse_json_class(v)))
+                .collect(),
+        ),
+        Value::Bool(x) => Property::Bool(*x),
+        Value::Number(x) => match x.as_i64() {
+            Some(x) => Property::Int(x as i32),
+            None => Property::Float(x.as_f64().unwrap_or(0.0)),
+        },
+        Value::String(x) => Property::String(x.clone()),
+        _ => Property::Int(0),
+    }
+}
+
+fn parse_json_text(value: &Value) -> Result<TextData> {
+    Ok(TextData {
+        text: get_str(value, "text").unwrap_or("").to_string(),
+        font_family: get_str(value, "fontfamily")
+            .unwrap_or("sans-serif")
+            .to_string(),
+        pixel_size: get_u32(value, "pixelsize").unwrap_or(16),
+        color: get_str(value, "color").map_or(Ok([255, 0, 0, 0]), parse_color)?,
+        wrap: get_bool(value, "wrap").unwrap_or(false),
+        halign: get_str(value, "halign").map_or(Ok(HorizontalAlignment::Left), parse_halign)?,
+        valign: get_str(value, "valign").map_or(Ok(VerticalAlignment::Top), parse_valign)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{describe_layer, fixtures, parse};
+
+    #[test]
+    fn json_and_xml_maps_are_equivalent() {
+        let load = |file: &str| parse(&std::fs::read_to_string(fixtures().join(file)).unwrap());
+        let (xml, json) = (load("equivalent.tmx"), load("equivalent.tmj"));
+
+        assert_eq!((xml.width, xml.height), (json.width, json.height));
+        assert_eq!(xml.infinite, json.infinite);
+        assert_eq!(
+            format!("{:?}", xml.tile_type),
+            format!("{:?}", json.tile_type)
+        );
+        assert_eq!(xml.background, json.background);
+        assert_eq!(xml.version, json.version);
+        assert_eq!(xml.tiled_version, json.tiled_version);
+        assert_eq!(
+            format!("{:?}", xml.properties_sorted()),
+            format!("{:?}", json.properties_sorted())
+        );
+
+        assert_eq!(xml.tilesets.len(), json.tilesets.len());
+        for (xml, json) in xml.tilesets.iter().zip(json.tilesets.iter()) {
+            assert_eq!(xml.first_gid, json.first_gid);
+            assert_eq!(xml.source, json.source);
+            assert_eq!(xml.tiles.len(), json.tiles.len());
+            assert_eq!(xml.tile_size, json.tile_size);
+            assert_eq!(xml.columns, json.columns);
+        }
+
+        let layers = |map: &Map| map.layers.iter().map(describe_layer).collect::<Vec<_>>();
+        assert_eq!(layers(&xml), layers(&json));
+        assert_eq!(xml.layers.len(), 3);
+    }
+}
@@ -1,4 +1,4 @@
-use bevy_math::{IVec2, UVec2, Vec4};
+use bevy_math::{const_vec4, IVec2, UVec2, Vec4};
 
 use super::*;
 
@@ -6,6 +6,8 @@ use super::*;
 pub enum Layer {
     /// A layer densely populated with tiles.
     TileLayer {
+        /// The name of the layer, as set in the map editor.
+        name: String,
         /// The amount of tiles in the x and y axis.
         size: UVec2,
         /// Position offset of the layer, measured in tiles.
@@ -16,14 +18,19 @@ pub enum Layer {
         parallax: Vec2,
         /// Color to multiply the contents of this layer with.
         color: Vec4,
+        /// Opacity of this layer, which is not included in the alpha of `color`.
+        opacity: f32,
         /// Whether this layer is visible or not.
         /// Contents of invisible layers will have their `Draw` component set to invisible.
         visible: bool,
         /// Tile data (global tile ids) for this layer, row by row.
+        /// For infinite maps, all chunks are merged into a single area spanning `position` and `size`.
         data: Vec<u32>,
     },
     /// A layer populated with individual objects.
     ObjectLayer {
+        /// The name of the layer, as set in the map editor.
+        name: String,
         /// Whether to draw objects ordered by index of appearance (true) or y coordinate (false).
         draworder_index: bool,
         /// The objects in the layer.
@@ -34,12 +41,19 @@ pub enum Layer {
         parallax: Vec2,
         /// Color to multiply the contents of this layer with.
         color: Vec4,
+        /// Opacity of this layer, which is not included in the alpha of `color`.
+        opacity: f32,
         /// Whether this layer is visible or not.
         /// Contents of invisible layers will have their `Draw` component set to invisible.
         visible: bool,
+        /// Color that the map editor draws the shapes of the objects in this layer with,
+        ///  or `Layer::DEFAULT_OBJECT_COLOR` if it isn't set. Unlike `color`, this doesn't tint tile objects.
+        object_color: Vec4,
     },
     /// A layer populated with a single big image, like a background.
     ImageLayer {
+        /// The name of the layer, as set in the map editor.
+        name: String,
         /// The image contained in this layer.
         image: Texture,
         /// Position offset of the layer, measured in tiles.
@@ -48,48 +62,75 @@ pub enum Layer {
         parallax: Vec2,
         /// Color to multiply the contents of this layer with.
         color: Vec4,
+        /// Opacity of this layer, which is not included in the alpha of `color`.
+        opacity: f32,
         /// Whether this layer is visible or not.
         /// Contents of invisible layers will have their `Draw` component set to invisible.
         visible: bool,
+        /// Whether the image is repeated along the x axis.
+        repeat_x: bool,
+        /// Whether the image is repeated along the y axis.
+        repeat_y: bool,
     },
     /// A set of layers grouped together, mainly for convenience in the map editor.
+    /// The parallax factor, color, opacity and visibility of the group are already applied to the grouped layers,
+    ///  but the offset isn't, since the group is spawned as the parent entity of its layers.
     Group {
+        /// The name of the layer, as set in the map editor.
+        name: String,
+        /// Position offset of the group, measured in pixels.
+        offset: IVec2,
         /// The layers that were grouped together.
         layers: Vec<Layer>,
     },
 }
 
 impl Layer {
-    /*pub(crate) fn set_visible(&mut self, new_visible: bool) {
+    /// The color of objects in the map editor for object layers without a color of their own.
+    pub const DEFAULT_OBJECT_COLOR: Vec4 = const_vec4!([0.627451, 0.627451, 0.643137, 1.0]);
+
+    /// The name of the layer, as set in the map editor.
+    pub fn name(&self) -> &str {
         match self {
-            Layer::TileLayer { visible, .. }
-            | Layer::ObjectLayer { visible, .. }
-            | Layer::ImageLayer { visible, .. } => *visible = new_visible,
-            Layer::Group { layers } => {
-                for l in layers.iter_mut() {
-                    l.set_visible(new_visible);
+            Layer::TileLayer { name, .. }
+            | Layer::ObjectLayer { name, .. }
+            | Layer::ImageLayer { name, .. }
+            | Layer::Group { name, .. } => name.as_str(),
+        }
+    }
+
+    /// The global tile id at tile coordinate (`x`, `y`) of a tile layer, including the flip flags.
+    /// Coordinates are measured in tiles from the map origin, so the `position` of the layer is accounted for.
+    /// Returns `None` for empty cells, coordinates outside of the layer and layers that are not tile layers.
+    pub fn tile_at(&self, x: i32, y: i32) -> Option<u32> {
+        match self {
+            Layer::TileLayer {
+                size,
+                position,
+                data,
+                ..
+            } => {
+                let x = x - position.x;
+                let y = y - position.y;
+                if x < 0 || y < 0 || x >= size.x as i32 || y >= size.y as i32 {
+                    return None;
                 }
+                data.get((y as u32 * size.x + x as u32) as usize)
+                    .copied()
+                    .filter(|&gid| gid != 0)
             }
+            _ => None,
         }
-    }*/
+    }
 
-    pub(crate) fn add_offset(&mut self, x: i32, y: i32) {
+    pub(crate) fn hide(&mut self) {
         match self {
-            Layer::TileLayer { offset, .. } => {
-                offset.x += x;
-                offset.y += y;
-            }
-            Layer::ObjectLayer { offset, .. } => {
-                offset.x += x;
-                offset.y += y;
-            }
-            Layer::ImageLayer { offset, .. } => {
-                offset.x += x;
-                offset.y += y;
-            }
-            Layer::Group { layers } => {
+            Layer::TileLayer { visible, .. }
+            | Layer::ObjectLayer { visible, .. }
+            | Layer::ImageLayer { visible, .. } => *visible = false,
+            Layer::Group { layers, .. } => {
                 for l in layers.iter_mut() {
-                    l.add_offset(x, y);
+                    l.hide();
                 }
             }
         }
@@ -109,7 +150,7 @@ impl Layer {
                 parallax.x *= x;
                 parallax.y *= y;
             }
-            Layer::Group { layers } => {
+            Layer::Group { layers, .. } => {
                 for l in layers.iter_mut() {
                     l.mul_parallax(x, y);
                 }
@@ -117,6 +158,21 @@ impl Layer {
         }
     }
 
+    pub(crate) fn mul_opacity(&mut self, o: f32) {
+        match self {
+            Layer::TileLayer { opacity, .. }
+            | Layer::ObjectLayer { opacity, .. }
+            | Layer::ImageLayer { opacity, .. } => {
+                *opacity *= o;
+            }
+            Layer::Group { layers, .. } => {
+                for l in layers.iter_mut() {
+                    l.mul_opacity(o);
+                }
+            }
+        }
+    }
+
     pub(crate) fn mul_color(&mut self, o: Vec4) {
         match self {
             Layer::TileLayer { color, .. }
@@ -124,7 +180,7 @@ impl Layer {
             | Layer::ImageLayer { color, .. } => {
                 *color *= o;
             }
-            Layer::Group { layers } => {
+            Layer::Group { layers, .. } => {
                 for l in layers.iter_mut() {
                     l.mul_color(o);
                 }
@@ -132,3 +188,58 @@ impl Layer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{map_xml, parse};
+
+    #[test]
+    fn tiles_are_looked_up_by_coordinate() {
+        let map = parse(&map_xml(
+            3,
+            2,
+            "",
+            r#"<layer id="1" name="ground" width="3" height="2">
+                <data encoding="csv">1,0,2,3,2147483652,0</data>
+            </layer>"#,
+        ));
+        let layer = &map.layers[0];
+        assert_eq!(layer.tile_at(0, 0), Some(1));
+        assert_eq!(layer.tile_at(2, 0), Some(2));
+        assert_eq!(layer.tile_at(0, 1), Some(3));
+        // flip flags are kept, empty cells and coordinates outside of the layer have no tile
+        assert_eq!(layer.tile_at(1, 1), Some(4 | Map::FLIPPED_HORIZONTALLY));
+        assert_eq!(layer.tile_at(1, 0), None);
+        assert_eq!(layer.tile_at(3, 0), None);
+        assert_eq!(layer.tile_at(0, 2), None);
+        assert_eq!(layer.tile_at(-1, 0), None);
+    }
+
+    #[test]
+    fn tiles_of_infinite_layers_are_looked_up_by_map_coordinate() {
+        let map = parse(
+            &map_xml(
+                4,
+                4,
+                "",
+                r#"<layer id="1" name="chunks" width="4" height="4">
+                    <data encoding="csv">
+                        <chunk x="-2" y="-2" width="2" height="2">1,0,0,2</chunk>
+                        <chunk x="4" y="0" width="2" height="2">0,3,4,0</chunk>
+                    </data>
+                </layer>"#,
+            )
+            .replace(r#"infinite="0""#, r#"infinite="1""#),
+        );
+        let layer = &map.layers[0];
+        assert_eq!(layer.tile_at(-2, -2), Some(1));
+        assert_eq!(layer.tile_at(-1, -1), Some(2));
+        assert_eq!(layer.tile_at(5, 0), Some(3));
+        assert_eq!(layer.tile_at(4, 1), Some(4));
+        // the gap between the chunks is empty
+        assert_eq!(layer.tile_at(1, 0), None);
+        assert_eq!(layer.tile_at(-3, -2), None);
+        assert_eq!(layer.tile_at(6, 1), None);
+    }
+}
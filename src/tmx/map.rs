@@ -1,14 +1,18 @@
 use super::*;
 
-use bevy_reflect::TypeUuid;
+use std::path::Path;
+
+use bevy_math::{Rect, UVec2};
+
+use crate::TmxLoadContext;
 
 /// A tiled map loaded from a .tmx file.
-#[derive(TypeUuid)]
-#[uuid = "387665bd-394f-4c83-8869-dbf135aaa6a4"]
+#[cfg_attr(feature = "render", derive(TypeUuid))]
+#[cfg_attr(feature = "render", uuid = "387665bd-394f-4c83-8869-dbf135aaa6a4")]
 pub struct Map {
     /// Custom properties.
     pub properties: HashMap<String, Property>,
-    /// Tilesets used in the map.
+    /// Tilesets used in the map, sorted by their first gid.
     pub tilesets: Vec<Arc<Tileset>>,
     /// Layers contained in the map.
     pub layers: Vec<Layer>,
@@ -17,11 +21,26 @@ pub struct Map {
     pub width: u32,
     /// The total height of the map, measured in tiles.
     pub height: u32,
+    /// Whether the map is infinite. The tile layers of infinite maps can extend beyond `width` and `height`,
+    ///  use `Map::tile_bounds` to find the area that is covered by tiles.
+    pub infinite: bool,
     /// The rendering type of the map.
     pub tile_type: TileType,
 
-    /// Background color of the map.
+    /// Background color of the map in the format `[a, r, g, b]`, or fully transparent if none is set.
     pub background: [u8; 4],
+    /// The reference point for parallax layers, measured in pixels.
+    /// Layers with a parallax factor are drawn at their regular position when the camera is centered on this point.
+    pub parallax_origin: Vec2,
+    /// Version of the file format that the map was saved in, like `1.10`. Empty if the map doesn't specify it.
+    pub version: String,
+    /// Version of tiled that saved the map, like `1.10.2`. Empty if the map doesn't specify it.
+    pub tiled_version: String,
+}
+
+/// A flat lookup table from global tile ids to tile metadata, created by `Map::build_index`.
+pub struct TileIndex<'a> {
+    tiles: Vec<Option<&'a Tile>>,
 }
 
 pub struct Objects<'a> {
@@ -33,35 +52,212 @@ pub struct Objects<'a> {
 }
 
 impl Map {
+    /// Bit set in a gid when the tile is flipped horizontally.
+    pub const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+    /// Bit set in a gid when the tile is flipped vertically.
+    pub const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+    /// Bit set in a gid when the tile is flipped diagonally.
+    pub const FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+
+    /// Parse a map from the contents of a .tmx or .tmj file, without going through the bevy asset pipeline.
+    /// External tilesets, templates and images are read from the filesystem, relative to `base_dir`.
+    /// Whether the map is in the json format is detected from its contents.
+    pub async fn from_bytes(bytes: &[u8], base_dir: impl AsRef<Path>) -> anyhow::Result<Map> {
+        let env = TmxLoadContext::filesystem(base_dir.as_ref());
+        if bytes.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{') {
+            Map::load_from_json(env, bytes).await
+        } else {
+            Map::load_from_xml_reader(env, xml::EventReader::new(bytes)).await
+        }
+    }
+
+    /// Strip the flip flags from a global tile id (gid), leaving only the actual tile id.
+    pub fn clear_flip_flags(gid: u32) -> u32 {
+        gid & !(Self::FLIPPED_HORIZONTALLY | Self::FLIPPED_VERTICALLY | Self::FLIPPED_DIAGONALLY)
+    }
+
     /// Retrieve the tileset associated with the global tile id (gid).
     /// If no tileset is associated with the gid, `None` is returned.
     pub fn get_tileset(&self, gid: u32) -> Option<Arc<Tileset>> {
-        for tileset in self.tilesets.iter().rev() {
-            if gid >= tileset.first_gid {
-                return Some(tileset.clone());
-            }
-        }
-        None
+        self.find_tileset(gid).cloned()
+    }
+
+    /// Find a tileset by its `source`, which is the resolved path of external tilesets
+    ///  and `embedded#` followed by the name for tilesets embedded in the map.
+    pub fn tileset_by_source(&self, source: &str) -> Option<&Arc<Tileset>> {
+        self.tilesets
+            .iter()
+            .find(|tileset| tileset.source == source)
     }
 
     /// Retrieve the tile metadata associated with the global tile id (gid).
     /// If no tile metadata is associated with the gid, `None` is returned.
     pub fn get_tile(&self, gid: u32) -> Option<&Tile> {
-        for tileset in self.tilesets.iter().rev() {
-            if gid >= tileset.first_gid {
-                let id = gid - tileset.first_gid;
-                return if let Some(&Some(ref tile)) = tileset.tiles.get(id as usize) {
-                    Some(&tile)
-                } else {
-                    None
-                };
+        let tileset = self.find_tileset(gid)?;
+        let id = Self::clear_flip_flags(gid) - tileset.first_gid;
+        tileset.tiles.get(id as usize)?.as_ref()
+    }
+
+    /// Binary search for the last tileset with a first gid that is not greater than `gid`.
+    fn find_tileset(&self, gid: u32) -> Option<&Arc<Tileset>> {
+        let gid = Self::clear_flip_flags(gid);
+        let index = self
+            .tilesets
+            .partition_point(|tileset| tileset.first_gid <= gid);
+        index.checked_sub(1).map(|index| &self.tilesets[index])
+    }
+
+    /// Build a lookup table that resolves global tile ids (gid) to tile metadata in constant time,
+    ///  for when `get_tile` is called very often, like when querying lots of tiles at runtime.
+    /// The table has an entry for every gid up to the last tile of the last tileset, so this is opt-in:
+    ///  maps with very large gid ranges will use a lot of memory for it.
+    pub fn build_index(&self) -> TileIndex<'_> {
+        let mut tiles = Vec::new();
+        for tileset in self.tilesets.iter() {
+            let first_gid = tileset.first_gid as usize;
+            tiles.truncate(first_gid);
+            tiles.resize(first_gid, None);
+            tiles.extend(tileset.tiles.iter().map(Option::as_ref));
+        }
+        TileIndex { tiles }
+    }
+
+    /// The size of the area covered by the map's tiles in pixels, taking the tile layout into account.
+    pub fn pixel_size(&self) -> Vec2 {
+        let bounds = self.pixel_bounds();
+        Vec2::new(bounds.right - bounds.left, bounds.bottom - bounds.top)
+    }
+
+    /// The tile coordinate of the tile at `position` within the scene of the map, for example to find the tile under the cursor.
+    /// Pass the same scale as the one given to `TmxPlugin::scale`. Layer offsets are not taken into account.
+    pub fn world_to_tile(&self, position: Vec2, scale: Vec2) -> IVec2 {
+        let pixel = (position / scale).floor();
+        let (x, y) = self.tile_type.pos_to_coord(
+            self.tile_bounds().1.y as i32,
+            pixel.x as i32,
+            pixel.y as i32,
+        );
+        IVec2::new(x, y)
+    }
+
+    /// The position of the center of the tile at tile coordinate `coord` within the scene of the map.
+    /// Pass the same scale as the one given to `TmxPlugin::scale`. Layer offsets are not taken into account.
+    pub fn tile_to_world(&self, coord: IVec2, scale: Vec2) -> Vec2 {
+        let (x, y) = self
+            .tile_type
+            .coord_to_pos(self.tile_bounds().1.y as i32, coord.x, coord.y);
+        let half = Vec2::new(
+            self.tile_type.tile_width() as f32,
+            self.tile_type.tile_height() as f32,
+        ) * 0.5;
+        (Vec2::new(x as f32, y as f32) + half) * scale
+    }
+
+    /// The area covered by the map's tiles in pixels, taking the tile layout into account.
+    /// The bounds are in tiled's coordinate system, so `top` is smaller than `bottom`.
+    /// Multiply them with the scale the map was loaded with to get the bounds within the scene.
+    pub fn pixel_bounds(&self) -> Rect<f32> {
+        let (position, size) = self.tile_bounds();
+        let (w, h) = (size.x as i32, size.y as i32);
+        let (tile_w, tile_h) = (
+            self.tile_type.tile_width() as i32,
+            self.tile_type.tile_height() as i32,
+        );
+        let mut min = IVec2::new(i32::MAX, i32::MAX);
+        let mut max = IVec2::new(i32::MIN, i32::MIN);
+
+        // The outermost tiles are always within the first or last two rows and columns of the map.
+        for &y in [0, 1, h - 2, h - 1].iter().filter(|&&y| y >= 0 && y < h) {
+            for &x in [0, 1, w - 2, w - 1].iter().filter(|&&x| x >= 0 && x < w) {
+                let (x, y) = self
+                    .tile_type
+                    .coord_to_pos(h, x + position.x, y + position.y);
+                min = min.min(IVec2::new(x, y));
+                max = max.max(IVec2::new(x + tile_w, y + tile_h));
             }
         }
-        None
+
+        if min.x > max.x {
+            Rect::default()
+        } else {
+            Rect {
+                left: min.x as f32,
+                right: max.x as f32,
+                top: min.y as f32,
+                bottom: max.y as f32,
+            }
+        }
+    }
+
+    /// The area covered by the map's tile layers as a position and size, measured in tiles.
+    /// For finite maps this is simply the size of the map, infinite maps are measured by the
+    ///  chunks of all their tile layers.
+    pub fn tile_bounds(&self) -> (IVec2, UVec2) {
+        fn union(layers: &[Layer], bounds: &mut Option<(IVec2, IVec2)>) {
+            for layer in layers {
+                match layer {
+                    Layer::TileLayer { position, size, .. } => {
+                        let end = *position + size.as_i32();
+                        *bounds = Some(match *bounds {
+                            Some((min, max)) => (min.min(*position), max.max(end)),
+                            None => (*position, end),
+                        });
+                    }
+                    Layer::Group { layers, .. } => union(layers, bounds),
+                    _ => (),
+                }
+            }
+        }
+
+        let mut bounds = None;
+        if self.infinite {
+            union(&self.layers, &mut bounds);
+        }
+        match bounds {
+            Some((min, max)) => (min, (max - min).as_u32()),
+            None => (IVec2::ZERO, UVec2::new(self.width, self.height)),
+        }
+    }
+
+    /// The custom properties of the map sorted by name, for a deterministic order.
+    pub fn properties_sorted(&self) -> Vec<(&str, &Property)> {
+        property::sorted_properties(&self.properties)
+    }
+
+    /// Find a layer by its name. Layers nested in groups are searched as well, in drawing order.
+    /// If multiple layers share the same name, the first one is returned.
+    pub fn layer_by_name(&self, name: &str) -> Option<&Layer> {
+        fn find<'a>(layers: &'a [Layer], name: &str) -> Option<&'a Layer> {
+            layers.iter().find_map(|layer| match layer {
+                _ if layer.name() == name => Some(layer),
+                Layer::Group { layers, .. } => find(layers, name),
+                _ => None,
+            })
+        }
+        find(&self.layers, name)
+    }
+
+    /// Build a grid of the tiles in the tile layer named `layer_name` that have the boolean property `property` set to true,
+    ///  for example to find the blocked tiles for pathfinding. Empty cells and tiles without the property are false.
+    /// The grid has the same row by row layout as the `data` of the layer, so it starts at the `position` of the layer.
+    /// Returns `None` if there is no tile layer with the given name.
+    pub fn collision_grid(&self, layer_name: &str, property: &str) -> Option<Vec<bool>> {
+        match self.layer_by_name(layer_name)? {
+            Layer::TileLayer { data, .. } => Some(
+                data.iter()
+                    .map(|&gid| {
+                        self.get_tile(gid)
+                            .and_then(|tile| tile.properties.get_bool(property))
+                            .unwrap_or(false)
+                    })
+                    .collect(),
+            ),
+            _ => None,
+        }
     }
 
     /// Iterate over all the objects in the map
-    pub fn objects(&self) -> Objects {
+    pub fn objects(&self) -> Objects<'_> {
         Objects {
             l: self.layers.as_slice(),
             i: 0,
@@ -69,6 +265,53 @@ impl Map {
             sub: None,
         }
     }
+
+    /// Iterate over all the objects in the map with the given type (or class), in drawing order.
+    pub fn objects_by_type<'a>(&'a self, ty: &'a str) -> impl Iterator<Item = &'a Object> + 'a {
+        self.objects()
+            .map(|(_, object)| object)
+            .filter(move |object| object.ty == ty)
+    }
+
+    /// Find an object by its name. If multiple objects share the same name, the first one in drawing order is returned.
+    pub fn object_by_name(&self, name: &str) -> Option<&Object> {
+        self.objects()
+            .map(|(_, object)| object)
+            .find(|object| object.name == name)
+    }
+
+    /// All images used by the map: tileset images and the images of image layers.
+    #[cfg(feature = "render")]
+    pub(crate) fn images(&self) -> Vec<&Texture> {
+        fn layer_images<'a>(layers: &'a [Layer], images: &mut Vec<&'a Texture>) {
+            for layer in layers {
+                match layer {
+                    Layer::ImageLayer { image, .. } => images.push(image),
+                    Layer::Group { layers, .. } => layer_images(layers, images),
+                    _ => (),
+                }
+            }
+        }
+
+        let mut images: Vec<&Texture> = self
+            .tilesets
+            .iter()
+            .flat_map(|tileset| tileset.images())
+            .collect();
+        layer_images(&self.layers, &mut images);
+        images
+    }
+}
+
+impl<'a> TileIndex<'a> {
+    /// Retrieve the tile metadata associated with the global tile id (gid), like `Map::get_tile`.
+    /// If no tile metadata is associated with the gid, `None` is returned.
+    pub fn get_tile(&self, gid: u32) -> Option<&'a Tile> {
+        self.tiles
+            .get(Map::clear_flip_flags(gid) as usize)
+            .copied()
+            .flatten()
+    }
 }
 
 impl<'a> Iterator for Objects<'a> {
@@ -93,11 +336,9 @@ impl<'a> Iterator for Objects<'a> {
                     }));
                 }
 
-                Layer::ObjectLayer { objects, .. } => {
-                    if self.i < objects.len() {
-                        self.i += 1;
-                        return Some((self.z, &objects[self.i - 1]));
-                    }
+                Layer::ObjectLayer { objects, .. } if self.i < objects.len() => {
+                    self.i += 1;
+                    return Some((self.z, &objects[self.i - 1]));
                 }
 
                 _ => {}
@@ -112,3 +353,366 @@ impl<'a> Iterator for Objects<'a> {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{fixtures, map_xml, parse};
+
+    #[test]
+    fn get_tile_ignores_flip_flags() {
+        let map = parse(&map_xml(
+            3,
+            1,
+            "",
+            r#" <layer id="1" name="l" width="3" height="1">
+  <data encoding="csv">2,2147483650,3221225474</data>
+ </layer>"#,
+        ));
+        let data = match &map.layers[0] {
+            Layer::TileLayer { data, .. } => data.clone(),
+            _ => panic!("expected a tile layer"),
+        };
+        assert_eq!(data, vec![2, 0x8000_0002, 0xc000_0002]);
+
+        let tile = map.get_tile(2).unwrap() as *const Tile;
+        for gid in data {
+            assert_eq!(Map::clear_flip_flags(gid), 2);
+            assert_eq!(map.get_tile(gid).unwrap() as *const Tile, tile);
+            assert_eq!(map.get_tileset(gid).unwrap().first_gid, 1);
+        }
+        let diagonal = 2 | Map::FLIPPED_DIAGONALLY;
+        assert_eq!(map.get_tile(diagonal).unwrap() as *const Tile, tile);
+        assert!(map.get_tile(5 | Map::FLIPPED_HORIZONTALLY).is_none());
+    }
+
+    #[test]
+    fn layers_are_found_by_name() {
+        let map = parse(&map_xml(
+            1,
+            1,
+            "",
+            r#" <layer id="1" name="ground" width="1" height="1">
+  <data encoding="csv">1</data>
+ </layer>
+ <objectgroup id="2" name="spawns"/>
+ <group id="3" name="decoration">
+  <imagelayer id="4" name="sky">
+   <image source="tiles.png" width="32" height="32"/>
+  </imagelayer>
+ </group>"#,
+        ));
+        assert!(matches!(
+            map.layer_by_name("ground"),
+            Some(Layer::TileLayer { .. })
+        ));
+        assert!(matches!(
+            map.layer_by_name("spawns"),
+            Some(Layer::ObjectLayer { .. })
+        ));
+        assert!(matches!(
+            map.layer_by_name("decoration"),
+            Some(Layer::Group { .. })
+        ));
+        assert!(matches!(
+            map.layer_by_name("sky"),
+            Some(Layer::ImageLayer { .. })
+        ));
+        assert!(map.layer_by_name("collision").is_none());
+    }
+
+    #[test]
+    fn objects_are_found_by_type_and_name() {
+        let map = parse(&map_xml(
+            4,
+            4,
+            "",
+            r#" <objectgroup id="1" name="actors">
+  <object id="1" name="slime" type="enemy" x="0" y="0"/>
+  <object id="2" name="boss" class="enemy" x="16" y="0"/>
+  <object id="3" name="slime" type="enemy" x="32" y="0"/>
+  <object id="4" name="chest" x="48" y="0"/>
+ </objectgroup>
+ <group id="2" name="nested">
+  <objectgroup id="3" name="more actors">
+   <object id="5" type="enemy" x="0" y="16"/>
+   <object id="6" name="exit" type="door" x="16" y="16"/>
+  </objectgroup>
+ </group>"#,
+        ));
+        let ids = |objects: Vec<&Object>| objects.iter().map(|o| o.id).collect::<Vec<_>>();
+        assert_eq!(
+            ids(map.objects_by_type("enemy").collect()),
+            vec![1, 2, 3, 5]
+        );
+        assert_eq!(ids(map.objects_by_type("door").collect()), vec![6]);
+        assert_eq!(map.objects_by_type("npc").count(), 0);
+
+        assert_eq!(map.object_by_name("boss").map(|o| o.id), Some(2));
+        assert_eq!(map.object_by_name("exit").map(|o| o.id), Some(6));
+        // the first object with a shared name is found
+        assert_eq!(map.object_by_name("slime").map(|o| o.id), Some(1));
+        assert!(map.object_by_name("dragon").is_none());
+    }
+
+    /// A map with `count` embedded tilesets of 4 tiles each, with 2 unused gids between them.
+    fn many_tilesets(count: u32) -> Map {
+        let tilesets: String = (0..count)
+            .map(|i| {
+                format!(
+                    r#" <tileset firstgid="{}" name="tiles{}" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+  <image source="tiles.png" width="32" height="32"/>
+ </tileset>
+"#,
+                    1 + i * 6,
+                    i
+                )
+            })
+            .collect();
+        parse(&format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" renderorder="right-down" width="1" height="1" tilewidth="16" tileheight="16" infinite="0">
+{}</map>"#,
+            tilesets
+        ))
+    }
+
+    #[test]
+    fn indexed_lookups_match_get_tile() {
+        let map = many_tilesets(32);
+        let index = map.build_index();
+        let last_gid = 32 * 6 + 2;
+        let gids: Vec<u32> = (0..last_gid)
+            .flat_map(|gid| {
+                [
+                    gid,
+                    gid | Map::FLIPPED_HORIZONTALLY | Map::FLIPPED_DIAGONALLY,
+                ]
+            })
+            .collect();
+        for &gid in &gids {
+            assert_eq!(
+                index.get_tile(gid).map(|tile| tile as *const Tile),
+                map.get_tile(gid).map(|tile| tile as *const Tile),
+                "gid {:#x}",
+                gid
+            );
+        }
+    }
+
+    #[test]
+    fn binary_search_finds_the_same_tileset_as_a_linear_scan() {
+        let map = many_tilesets(40);
+        let linear = |gid: u32| {
+            let gid = Map::clear_flip_flags(gid);
+            map.tilesets
+                .iter()
+                .rev()
+                .find(|tileset| tileset.first_gid <= gid)
+                .map(|tileset| tileset.first_gid)
+        };
+        // the gid before, at and after the first gid of each tileset, and past the last tileset
+        let mut gids = vec![0, 1, 40 * 6 + 10];
+        for tileset in map.tilesets.iter() {
+            let first = tileset.first_gid;
+            gids.extend(&[first - 1, first, first + 1, first + 3, first + 4]);
+        }
+        for gid in gids {
+            for &flags in &[0, Map::FLIPPED_VERTICALLY] {
+                let gid = gid | flags;
+                assert_eq!(
+                    map.get_tileset(gid).map(|tileset| tileset.first_gid),
+                    linear(gid),
+                    "gid {:#x}",
+                    gid
+                );
+            }
+        }
+        assert!(map.get_tileset(0).is_none());
+        assert!(map.get_tile(5).is_none());
+        assert!(map.get_tile(7).is_some());
+    }
+
+    /// An empty map with the given orientation and attributes.
+    fn empty_map(attributes: &str) -> Map {
+        parse(&format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" renderorder="right-down" infinite="0" {}>
+</map>"#,
+            attributes
+        ))
+    }
+
+    #[test]
+    fn pixel_bounds_cover_the_rendered_tiles() {
+        let bounds = |map: &Map| {
+            let bounds = map.pixel_bounds();
+            (bounds.left, bounds.top, bounds.right, bounds.bottom)
+        };
+
+        let ortho = empty_map(
+            r#"orientation="orthogonal" width="10" height="8" tilewidth="16" tileheight="16""#,
+        );
+        assert_eq!(ortho.pixel_size(), Vec2::new(160.0, 128.0));
+        assert_eq!(bounds(&ortho), (0.0, 0.0, 160.0, 128.0));
+
+        // a diamond with (width + height) half tiles along both axes
+        let isometric = empty_map(
+            r#"orientation="isometric" width="4" height="3" tilewidth="64" tileheight="32""#,
+        );
+        assert_eq!(isometric.pixel_size(), Vec2::new(7.0 * 32.0, 7.0 * 16.0));
+        assert_eq!(bounds(&isometric), (0.0, 0.0, 224.0, 112.0));
+
+        // shifted rows stick out by half a tile, rows overlap by the part of the tile next to the flat side
+        let hexagonal = empty_map(
+            r#"orientation="hexagonal" width="4" height="3" tilewidth="32" tileheight="28"
+               hexsidelength="14" staggeraxis="y" staggerindex="odd""#,
+        );
+        let row_height = 21.0;
+        assert_eq!(
+            hexagonal.pixel_size(),
+            Vec2::new(4.0 * 32.0 + 16.0, 2.0 * row_height + 28.0)
+        );
+        assert_eq!(bounds(&hexagonal), (0.0, 0.0, 144.0, 70.0));
+    }
+
+    #[test]
+    fn infinite_maps_are_bounded_by_their_chunks() {
+        let map = parse(
+            &map_xml(
+                4,
+                4,
+                "",
+                r#"<layer id="1" name="chunks" width="4" height="4">
+                    <data encoding="csv">
+                        <chunk x="-2" y="0" width="2" height="2">1,0,0,2</chunk>
+                        <chunk x="4" y="2" width="2" height="2">0,3,4,0</chunk>
+                    </data>
+                </layer>
+                <group id="2" name="group">
+                    <layer id="3" name="nested" width="4" height="4">
+                        <data encoding="csv">
+                            <chunk x="0" y="-4" width="2" height="2">1,1,1,1</chunk>
+                        </data>
+                    </layer>
+                </group>"#,
+            )
+            .replace(r#"infinite="0""#, r#"infinite="1""#),
+        );
+
+        assert!(map.infinite);
+        // the chunks span from (-2, -4) to (6, 4), the width and height of the map don't matter
+        assert_eq!(map.tile_bounds(), (IVec2::new(-2, -4), UVec2::new(8, 8)));
+        let bounds = map.pixel_bounds();
+        assert_eq!(
+            (bounds.left, bounds.top, bounds.right, bounds.bottom),
+            (-32.0, -64.0, 96.0, 64.0)
+        );
+        assert_eq!(map.pixel_size(), Vec2::new(128.0, 128.0));
+    }
+
+    #[test]
+    fn maps_are_parsed_from_bytes_on_disk() {
+        let load = |name: &str| {
+            let bytes = std::fs::read(fixtures().join(name)).unwrap();
+            futures_lite::future::block_on(Map::from_bytes(&bytes, fixtures())).unwrap()
+        };
+
+        // the external tileset and template are read relative to the base directory
+        let map = load("external.tmx");
+        assert_eq!(map.tilesets.len(), 1);
+        assert_eq!(map.tilesets[0].tile_count(), 4);
+        assert!(map.layer_by_name("ground").is_some());
+        let (_, object) = map.objects().next().unwrap();
+        assert_eq!(object.name, "crate");
+        assert_eq!(object.tile(), Some(2));
+
+        // json maps are detected from their contents
+        let map = load("equivalent.tmj");
+        assert!(!map.layers.is_empty());
+    }
+
+    #[test]
+    fn world_positions_round_trip_through_tile_coordinates() {
+        let scale = Vec2::new(2.0, -2.0);
+        let ortho = empty_map(
+            r#"orientation="orthogonal" width="5" height="5" tilewidth="16" tileheight="16""#,
+        );
+        assert_eq!(
+            ortho.tile_to_world(IVec2::new(1, 2), scale),
+            Vec2::new(48.0, -80.0)
+        );
+        assert_eq!(
+            ortho.world_to_tile(Vec2::new(33.0, -95.0), scale),
+            IVec2::new(1, 2)
+        );
+
+        let staggered = empty_map(
+            r#"orientation="staggered" staggeraxis="y" staggerindex="odd" width="5" height="5" tilewidth="32" tileheight="16""#,
+        );
+        for map in [&ortho, &staggered] {
+            for y in 0..5 {
+                for x in 0..5 {
+                    let coord = IVec2::new(x, y);
+                    let center = map.tile_to_world(coord, scale);
+                    assert_eq!(map.world_to_tile(center, scale), coord);
+                    // points within the tile but away from its center find the same tile
+                    let quarter = Vec2::new(map.tile_type.tile_width() as f32 * 0.25, 0.0) * scale;
+                    assert_eq!(map.world_to_tile(center + quarter, scale), coord);
+                    assert_eq!(map.world_to_tile(center - quarter, scale), coord);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn collision_grids_mark_tiles_with_the_property() {
+        let map = parse(&map_xml(
+            3,
+            2,
+            "",
+            r#"<tileset firstgid="5" name="walls" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+                <image source="tiles.png" width="32" height="32"/>
+                <tile id="0"><properties><property name="blocked" type="bool" value="true"/></properties></tile>
+                <tile id="1"><properties><property name="blocked" type="bool" value="false"/></properties></tile>
+                <tile id="2"><properties><property name="blocked" value="yes"/></properties></tile>
+            </tileset>
+            <layer id="1" name="walls" width="3" height="2">
+                <data encoding="csv">5,6,0,7,2147483653,1</data>
+            </layer>
+            <objectgroup id="2" name="objects"/>"#,
+        ));
+
+        // flipped tiles are the same tile, and properties of other types don't count
+        assert_eq!(
+            map.collision_grid("walls", "blocked"),
+            Some(vec![true, false, false, false, true, false])
+        );
+        assert_eq!(
+            map.collision_grid("walls", "slippery"),
+            Some(vec![false; 6])
+        );
+        assert_eq!(map.collision_grid("objects", "blocked"), None);
+        assert_eq!(map.collision_grid("missing", "blocked"), None);
+    }
+
+    #[test]
+    fn tilesets_are_found_by_their_source() {
+        let map = parse(&map_xml(
+            1,
+            1,
+            "",
+            r#"<tileset firstgid="5" source="tiles.tsx"/>"#,
+        ));
+
+        let embedded = map.tileset_by_source("embedded#tiles").unwrap();
+        assert_eq!(embedded.first_gid, 1);
+        let external = fixtures().join("tiles.tsx");
+        let external = map
+            .tileset_by_source(&external.display().to_string())
+            .unwrap();
+        assert_eq!(external.first_gid, 5);
+        assert!(map.tileset_by_source("embedded#missing").is_none());
+        assert!(map.tileset_by_source("tiles.tsx").is_none());
+    }
+}
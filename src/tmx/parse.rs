@@ -1,3 +1,4 @@
+use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 use std::io::{BufReader, Read};
 use std::path::Path;
@@ -5,31 +6,52 @@ use std::pin::Pin;
 
 use anyhow::*;
 use bevy_math::{vec2, IVec2, UVec2, Vec4};
-use bevy_utils::AHasher;
 use xml::attribute::OwnedAttribute;
+use xml::common::{Position, TextPosition};
 use xml::reader::{EventReader, XmlEvent};
 
+use crate::tmx::json::is_json_file;
 use crate::tmx::map::Map;
 use crate::TmxLoadContext;
 
 use super::*;
 
-enum Data {
+pub(super) enum Data {
     U8(Vec<u8>),
     U32(Vec<u32>),
+    Chunks(Vec<Chunk>),
+}
+
+/// A rectangular piece of tile data, as found in infinite maps.
+pub(super) struct Chunk {
+    /// Position of the chunk, measured in tiles.
+    pub position: IVec2,
+    /// The amount of tiles in the x and y axis.
+    pub size: UVec2,
+    pub data: Vec<u32>,
+}
+
+#[derive(Default)]
+pub(super) struct DataEncoding {
+    csv: bool,
+    base64: bool,
+    zlib: bool,
+    gzip: bool,
+    zstd: bool,
 }
 
 impl Data {
-    fn into_vec_u8(self) -> Vec<u8> {
+    fn into_vec_u8(self) -> Result<Vec<u8>> {
         match self {
-            Data::U8(v) => v,
-            Data::U32(_) => unimplemented!("u8 to u32 conversion is not needed"),
+            Data::U8(v) => Ok(v),
+            Data::U32(_) => bail!("expected binary data, found csv"),
+            Data::Chunks(_) => bail!("chunks are only supported in tile layers"),
         }
     }
 
-    fn into_vec_u32(self) -> Vec<u32> {
+    pub(super) fn into_vec_u32(self) -> Result<Vec<u32>> {
         match self {
-            Data::U8(v) => v
+            Data::U8(v) => Ok(v
                 .chunks_exact(4)
                 .map(|chunk| {
                     (chunk[0] as u32)
@@ -37,8 +59,147 @@ impl Data {
                         | (chunk[2] as u32) << 16
                         | (chunk[3] as u32) << 24
                 })
-                .collect(),
-            Data::U32(v) => v,
+                .collect()),
+            Data::U32(v) => Ok(v),
+            Data::Chunks(_) => bail!("nested chunks are not supported"),
+        }
+    }
+
+    /// Merge chunks into a single dense array covering all chunks.
+    /// Returns the position and size in tiles of the merged area, along with the tile data.
+    pub(super) fn merge_chunks(mut chunks: Vec<Chunk>) -> Result<(IVec2, UVec2, Vec<u32>)> {
+        for chunk in chunks.iter_mut() {
+            validate_tile_data(&mut chunk.data, chunk.size).with_context(|| {
+                format!(
+                    "invalid chunk at {}, {}",
+                    chunk.position.x, chunk.position.y
+                )
+            })?;
+        }
+
+        // the bounds are computed in 64 bits, chunks far apart could overflow the merged size otherwise
+        let (mut min, mut max) = ([i64::MAX; 2], [i64::MIN; 2]);
+        for chunk in chunks.iter() {
+            let position = [chunk.position.x as i64, chunk.position.y as i64];
+            let size = [chunk.size.x as i64, chunk.size.y as i64];
+            for axis in 0..2 {
+                min[axis] = min[axis].min(position[axis]);
+                max[axis] = max[axis].max(position[axis] + size[axis]);
+            }
+        }
+        let (width, height) = ((max[0] - min[0]).max(0), (max[1] - min[1]).max(0));
+        if width * height > MAX_LAYER_TILES {
+            bail!(
+                "chunks span {}x{} tiles, which is more than the supported {} tiles",
+                width,
+                height,
+                MAX_LAYER_TILES
+            );
+        }
+        let min = if chunks.is_empty() {
+            IVec2::ZERO
+        } else {
+            IVec2::new(min[0] as i32, min[1] as i32)
+        };
+        let size = UVec2::new(width as u32, height as u32);
+
+        let mut data = vec![0; (size.x * size.y) as usize];
+        for chunk in chunks {
+            let origin = (chunk.position - min).as_u32();
+            for (i, gid) in chunk.data.into_iter().enumerate() {
+                let x = origin.x + i as u32 % chunk.size.x;
+                let y = origin.y + i as u32 / chunk.size.x;
+                data[(y * size.x + x) as usize] = gid;
+            }
+        }
+
+        Ok((min, size, data))
+    }
+}
+
+/// The largest area in tiles that a tile layer may cover, including the merged chunks of infinite layers.
+const MAX_LAYER_TILES: i64 = 1 << 24;
+
+/// Check that decoded tile data covers exactly `size` tiles, so the tiles don't end up in the wrong place.
+/// Empty data is filled up with empty tiles.
+pub(super) fn validate_tile_data(data: &mut Vec<u32>, size: UVec2) -> Result<()> {
+    let expected = size.x as usize * size.y as usize;
+    if expected as i64 > MAX_LAYER_TILES {
+        bail!(
+            "an area of {}x{} tiles is more than the supported {} tiles",
+            size.x,
+            size.y,
+            MAX_LAYER_TILES
+        );
+    }
+    if data.is_empty() {
+        data.resize(expected, 0);
+    } else if data.len() != expected {
+        bail!(
+            "found {} tiles of data for an area of {}x{} tiles",
+            data.len(),
+            size.x,
+            size.y
+        );
+    }
+    Ok(())
+}
+
+impl DataEncoding {
+    pub(super) fn set_encoding(&mut self, encoding: &str) {
+        match encoding {
+            "csv" => self.csv = true,
+            "base64" => self.base64 = true,
+            _ => (),
+        }
+    }
+
+    pub(super) fn set_compression(&mut self, compression: &str) {
+        match compression {
+            "zlib" => self.zlib = true,
+            "gzip" | "glib" => self.gzip = true,
+            "zstd" => self.zstd = true,
+            _ => (),
+        }
+    }
+
+    pub(super) fn decode(&self, text: &str) -> Result<Data> {
+        if self.csv {
+            Ok(Data::U32(
+                text.split(',')
+                    .map(str::trim)
+                    .filter(|v| !v.is_empty())
+                    .enumerate()
+                    .map(|(i, v)| {
+                        v.parse()
+                            .with_context(|| format!("invalid csv tile `{}` at index {}", v, i))
+                    })
+                    .collect::<Result<_>>()?,
+            ))
+        } else if self.base64 {
+            let bytes = base64::decode(text.trim().as_bytes())?;
+
+            let bytes = if self.zlib {
+                let mut zd = libflate::zlib::Decoder::new(BufReader::new(&bytes[..]))?;
+                let mut bytes = Vec::new();
+                zd.read_to_end(&mut bytes)?;
+
+                bytes
+            } else if self.gzip {
+                let mut zd = libflate::gzip::Decoder::new(BufReader::new(&bytes[..]))?;
+                let mut bytes = Vec::new();
+                zd.read_to_end(&mut bytes)?;
+
+                bytes
+            } else if self.zstd {
+                decompress_zstd_bytes(&bytes[..])?
+            } else {
+                bytes
+            };
+
+            Ok(Data::U8(bytes))
+        } else {
+            bail!("<tile> based data is not supported");
         }
     }
 }
@@ -54,7 +215,10 @@ impl Map {
             } = reader.next()?
             {
                 if name.local_name == "map" {
-                    return Map::parse(env, attributes, &mut reader).await;
+                    let position = reader.position();
+                    return Map::parse(env, attributes, &mut reader)
+                        .await
+                        .with_context(|| in_element(&name.local_name, position));
                 } else {
                     parse_empty(&mut reader)?;
                 }
@@ -74,6 +238,7 @@ impl Map {
 
             width: 0,
             height: 0,
+            infinite: false,
             tile_type: TileType::Ortho {
                 width: 0,
                 height: 0,
@@ -81,10 +246,13 @@ impl Map {
             },
 
             background: [0; 4],
+            parallax_origin: Vec2::ZERO,
+            version: String::new(),
+            tiled_version: String::new(),
         };
 
         let mut render_order = RenderOrder::RightDown;
-        let mut tile_type = 0;
+        let mut orientation = String::from("orthogonal");
         let mut tile_width = 0;
         let mut tile_height = 0;
         let mut stagger_y = false;
@@ -95,112 +263,75 @@ impl Map {
             match a.name.local_name.as_ref() {
                 "width" => result.width = a.value.parse()?,
                 "height" => result.height = a.value.parse()?,
+                "infinite" => result.infinite = a.value == "1",
+                "version" => result.version = a.value,
+                "tiledversion" => result.tiled_version = a.value,
                 "tilewidth" => tile_width = a.value.parse()?,
                 "tileheight" => tile_height = a.value.parse()?,
-                "renderorder" => {
-                    render_order = match a.value.as_ref() {
-                        "right-down" => RenderOrder::RightDown,
-                        "right-up" => RenderOrder::RightUp,
-                        "left-down" => RenderOrder::LeftDown,
-                        "left-up" => RenderOrder::LeftUp,
-                        _ => bail!("invalid renderorder"),
-                    }
-                }
-                "orientation" => {
-                    tile_type = match a.value.as_ref() {
-                        "orthogonal" => 0,
-                        "isometric" => 1,
-                        "staggered" => 2,
-                        "hexagonal" => 3,
-                        _ => bail!("invalid orientation"),
-                    }
-                }
-                "backgroundcolor" => {
-                    result.background = [1; 4];
-                }
-                "staggeraxis" => {
-                    stagger_y = match a.value.as_ref() {
-                        "x" => false,
-                        "y" => true,
-                        _ => bail!("invalid staggeraxis"),
-                    }
-                }
-                "staggerindex" => {
-                    stagger_i = match a.value.as_ref() {
-                        "odd" => true,
-                        "even" => false,
-                        _ => bail!("invalid staggerindex"),
-                    }
-                }
+                "renderorder" => render_order = parse_render_order(a.value.as_str()),
+                "orientation" => orientation = a.value.clone(),
+                "backgroundcolor" => result.background = parse_color(a.value.as_str())?,
+                "staggeraxis" => stagger_y = parse_stagger_axis(a.value.as_str()),
+                "staggerindex" => stagger_i = parse_stagger_index(a.value.as_str()),
                 "hexsidelength" => hex_side_length = a.value.parse()?,
+                "parallaxoriginx" => result.parallax_origin.x = a.value.parse()?,
+                "parallaxoriginy" => result.parallax_origin.y = a.value.parse()?,
                 _ => (), // skip
             }
         }
 
-        result.tile_type = match tile_type {
-            0 => TileType::Ortho {
-                width: tile_width,
-                height: tile_height,
-                render_order,
-            },
-            1 => TileType::Isometric {
-                width: tile_width,
-                height: tile_height,
-                stagger: false,
-                stagger_odd: stagger_i,
-                stagger_y,
-                render_order,
-            },
-            2 => TileType::Isometric {
-                width: tile_width,
-                height: tile_height,
-                stagger: true,
-                stagger_odd: stagger_i,
-                stagger_y,
-                render_order,
-            },
-            3 => TileType::Hexagonal {
-                width: tile_width,
-                height: tile_width,
-                stagger_odd: stagger_i,
-                stagger_y,
-                side_length: hex_side_length,
-                render_order,
-            },
-            _ => unreachable!(),
-        };
+        result.tile_type = TileType::new(
+            orientation.as_str(),
+            tile_width,
+            tile_height,
+            render_order,
+            stagger_y,
+            stagger_i,
+            hex_side_length,
+        );
 
         while match reader.next()? {
             XmlEvent::StartElement {
                 name, attributes, ..
             } => {
+                let position = reader.position();
+                let context = || in_element(&name.local_name, position);
                 match name.local_name.as_ref() {
                     "properties" => {
-                        result.properties = parse_properties(reader)?;
+                        result.properties = parse_properties(reader).with_context(context)?;
                     }
                     "tileset" => {
                         result.tilesets.push(Arc::new(
-                            Tileset::parse(env.clone(), attributes, reader).await?,
+                            Tileset::parse(env.clone(), attributes, reader)
+                                .await
+                                .with_context(context)?,
                         ));
                     }
                     "layer" => {
-                        result.layers.push(Layer::parse_tiles(attributes, reader)?);
+                        result
+                            .layers
+                            .push(Layer::parse_tiles(attributes, reader).with_context(context)?);
                     }
                     "objectgroup" => {
                         result = Layer::parse_objects(env.clone(), attributes, reader)
-                            .await?
-                            .process(result)
+                            .await
+                            .with_context(context)?
+                            .process(env.clone(), result)
                             .await?;
                     }
                     "imagelayer" => {
-                        result
-                            .layers
-                            .push(Layer::parse_image(env.clone(), attributes, reader).await?);
+                        result.layers.push(
+                            Layer::parse_image(env.clone(), attributes, reader)
+                                .await
+                                .with_context(context)?,
+                        );
                     }
                     "group" => {
-                        result
-                            .layers
-                            .push(Layer::parse_group(env.clone(), attributes, reader).await?);
+                        result = Layer::parse_group(env.clone(), attributes, reader)
+                            .await
+                            .with_context(context)?
+                            .process(env.clone(), result)
+                            .await?;
                     }
                     _ => parse_empty(reader)?, // skip
                 }
@@ -213,24 +344,261 @@ impl Map {
             continue;
         }
 
+        // tiled writes tilesets in order of their first gid, but lookups depend on it
+        result.tilesets.sort_by_key(|tileset| tileset.first_gid);
+
         Ok(result)
     }
 }
 
+impl TileType {
+    /// Construct the tile type for a map with the given orientation.
+    pub(super) fn new(
+        orientation: &str,
+        tile_width: u32,
+        tile_height: u32,
+        render_order: RenderOrder,
+        stagger_y: bool,
+        stagger_odd: bool,
+        hex_side_length: u32,
+    ) -> Self {
+        match orientation {
+            "orthogonal" => TileType::Ortho {
+                width: tile_width,
+                height: tile_height,
+                render_order,
+            },
+            "isometric" => TileType::Isometric {
+                width: tile_width,
+                height: tile_height,
+                stagger: false,
+                stagger_odd,
+                stagger_y,
+                render_order,
+            },
+            "staggered" => TileType::Isometric {
+                width: tile_width,
+                height: tile_height,
+                stagger: true,
+                stagger_odd,
+                stagger_y,
+                render_order,
+            },
+            "hexagonal" => TileType::Hexagonal {
+                width: tile_width,
+                height: tile_height,
+                stagger_odd,
+                stagger_y,
+                side_length: hex_side_length,
+                render_order,
+            },
+            _ => {
+                warn!("unknown orientation `{}`, using orthogonal", orientation);
+                TileType::Ortho {
+                    width: tile_width,
+                    height: tile_height,
+                    render_order,
+                }
+            }
+        }
+    }
+}
+
+pub(super) fn parse_render_order(value: &str) -> RenderOrder {
+    match value {
+        "right-down" => RenderOrder::RightDown,
+        "right-up" => RenderOrder::RightUp,
+        "left-down" => RenderOrder::LeftDown,
+        "left-up" => RenderOrder::LeftUp,
+        _ => {
+            warn!("unknown renderorder `{}`, using right-down", value);
+            RenderOrder::RightDown
+        }
+    }
+}
+
+/// Returns whether the y axis is staggered.
+pub(super) fn parse_stagger_axis(value: &str) -> bool {
+    match value {
+        "x" => false,
+        "y" => true,
+        _ => {
+            warn!("unknown staggeraxis `{}`, using x", value);
+            false
+        }
+    }
+}
+
+/// Returns whether odd columns/rows are staggered.
+pub(super) fn parse_stagger_index(value: &str) -> bool {
+    match value {
+        "odd" => true,
+        "even" => false,
+        _ => {
+            warn!("unknown staggerindex `{}`, using odd", value);
+            true
+        }
+    }
+}
+
+pub(super) fn parse_grid_orientation(value: &str) -> Result<GridOrientation> {
+    Ok(match value {
+        "orthogonal" => GridOrientation::Orthogonal,
+        "isometric" => GridOrientation::Isometric,
+        _ => bail!("invalid grid orientation"),
+    })
+}
+
+pub(super) fn parse_object_alignment(value: &str) -> Result<ObjectAlignment> {
+    Ok(match value {
+        "unspecified" => ObjectAlignment::Unspecified,
+        "topleft" => ObjectAlignment::TopLeft,
+        "top" => ObjectAlignment::Top,
+        "topright" => ObjectAlignment::TopRight,
+        "left" => ObjectAlignment::Left,
+        "center" => ObjectAlignment::Center,
+        "right" => ObjectAlignment::Right,
+        "bottomleft" => ObjectAlignment::BottomLeft,
+        "bottom" => ObjectAlignment::Bottom,
+        "bottomright" => ObjectAlignment::BottomRight,
+        _ => bail!("invalid object alignment"),
+    })
+}
+
+pub(super) fn parse_fill_mode(value: &str) -> Result<FillMode> {
+    Ok(match value {
+        "stretch" => FillMode::Stretch,
+        "preserve-aspect-fit" => FillMode::PreserveAspectFit,
+        _ => bail!("invalid fill mode"),
+    })
+}
+
+/// Layout of the tiles within a tileset image.
+pub(super) struct TileGrid {
+    pub tile_width: i32,
+    pub tile_height: i32,
+    pub spacing: i32,
+    pub margin: i32,
+    pub tile_count: Option<u32>,
+    pub columns: Option<i32>,
+}
+
 impl Tileset {
+    /// Add the tiles of a tileset image, laid out according to `grid`.
+    pub(super) fn add_image_tiles(&mut self, image: Texture, grid: &TileGrid) {
+        let TileGrid {
+            tile_width,
+            tile_height,
+            spacing,
+            margin,
+            tile_count,
+            columns,
+        } = *grid;
+        let mut tiles_added = 0;
+        self.image = Some(image.clone());
+
+        let (width, height) = (image.width(), image.height());
+        let (width, height) = (width as i32, height as i32);
+        // the same formula that tiled uses: the last column or row has no spacing after it
+        let fit = |space: i32, size: i32| {
+            if size + spacing > 0 {
+                ((space - margin * 2 + spacing) / (size + spacing)).max(0)
+            } else {
+                0
+            }
+        };
+        let columns = columns.unwrap_or_else(|| fit(width, tile_width));
+        self.columns = columns as u32;
+        let rows = fit(height, tile_height);
+
+        for y in 0..rows {
+            for x in 0..columns {
+                if !matches!(tile_count, Some(tc) if tiles_added >= tc) {
+                    let u = (margin + x * tile_width + x * spacing) as f32 / width as f32;
+                    let v = (margin + y * tile_height + y * spacing) as f32 / height as f32;
+                    let w = tile_width as f32 / width as f32;
+                    let h = tile_height as f32 / height as f32;
+
+                    self.tiles.push(Some(Tile {
+                        image: Some(image.clone()),
+                        top_left: Vec2::new(u, v),
+                        bottom_right: Vec2::new(u + w, v + h),
+                        width: tile_width,
+                        height: tile_height,
+                        animation: Vec::new(),
+                        properties: HashMap::new(),
+                        object_group: Vec::new(),
+                        probability: 1.0,
+                        ty: String::new(),
+                    }));
+
+                    tiles_added += 1;
+                } else {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// The number of tiles in this tileset.
+    pub fn tile_count(&self) -> usize {
+        self.tiles.iter().flatten().count()
+    }
+
+    /// The number of tile rows in the tileset image, or 0 if all tiles provide their own image.
+    pub fn rows(&self) -> u32 {
+        match self.columns {
+            0 => 0,
+            columns => (self.tiles.len() as u32).div_ceil(columns),
+        }
+    }
+
+    /// Retrieve the tile with the local tile id `id`, if it exists.
+    pub fn tile(&self, id: u32) -> Option<&Tile> {
+        self.tiles.get(id as usize)?.as_ref()
+    }
+
+    /// An empty tileset, to parse the tileset content into.
+    pub(super) fn new(first_gid: u32, source: String) -> Self {
+        Tileset {
+            first_gid,
+            source,
+            tiles: Vec::new(),
+            image: None,
+            tile_size: Vec2::ZERO,
+            columns: 0,
+            tile_offset: IVec2::ZERO,
+            grid_orientation: GridOrientation::Orthogonal,
+            grid_size: Vec2::ZERO,
+            object_alignment: ObjectAlignment::Unspecified,
+            fill_mode: FillMode::Stretch,
+        }
+    }
+
+    /// Add a tile that was described explicitly, merging it with the tile from the tileset image if there is one.
+    pub(super) fn add_tile(&mut self, id: usize, tile: Tile) {
+        if id < self.tiles.len() {
+            if self.tiles[id].is_none() {
+                self.tiles[id].replace(tile);
+            } else {
+                // we already checked if the tile exists, unwrap is safe.
+                self.tiles[id].as_mut().unwrap().join(tile);
+            }
+        } else {
+            while id > self.tiles.len() {
+                self.tiles.push(None);
+            }
+            self.tiles.push(Some(tile));
+        }
+    }
+
     /// Parse a tileset element. This can be either an external reference or an actual tileset.
     async fn parse<R: Read + Send>(
         env: TmxLoadContext<'_>,
         attributes: Vec<OwnedAttribute>,
         reader: &mut EventReader<R>,
     ) -> Result<Self> {
-        let mut result = Tileset {
-            first_gid: 0,
-            source: "embedded#".to_string(),
-            tiles: Vec::new(),
-            image: None,
-            tile_size: Vec2::ZERO,
-        };
+        let mut result = Tileset::new(0, "embedded#".to_string());
 
         let mut found_source = false;
 
@@ -244,28 +612,7 @@ impl Tileset {
                 }
                 "source" => {
                     found_source = true;
-                    let source_path = Path::new(a.value.as_str());
-                    let file_name = env.file_path(source_path);
-                    let sub_env = env.file_directory(source_path);
-                    let file = env.load_file(source_path).await?;
-                    let file = BufReader::new(file.as_slice());
-                    let mut reader = EventReader::new(file);
-                    loop {
-                        if let XmlEvent::StartElement {
-                            name, attributes, ..
-                        } = reader.next()?
-                        {
-                            if name.local_name == "tileset" {
-                                result =
-                                    Tileset::parse_tsx(result, sub_env, attributes, &mut reader)
-                                        .await?;
-                                result.source = format!("{}", file_name.display());
-                                break;
-                            } else {
-                                parse_empty(&mut reader)?;
-                            }
-                        }
-                    }
+                    result = Tileset::load_external(result, env.clone(), a.value.as_str()).await?;
                 }
                 _ => (),
             }
@@ -282,6 +629,105 @@ impl Tileset {
         }
     }
 
+    /// All images used by the tileset, including the images of individual tiles.
+    #[cfg(feature = "render")]
+    pub(crate) fn images(&self) -> impl Iterator<Item = &Texture> {
+        self.image.iter().chain(
+            self.tiles
+                .iter()
+                .flatten()
+                .filter_map(|tile| tile.image.as_ref()),
+        )
+    }
+
+    /// Copy the tileset with its own copies of the images, so they are not shared with the maps using the original.
+    #[cfg(feature = "render")]
+    pub(crate) async fn detached(&self, first_gid: u32) -> Tileset {
+        let mut images = HashMap::<String, Texture>::new();
+        for image in self.images() {
+            if !images.contains_key(image.label()) {
+                images.insert(image.label().to_string(), image.detached().await);
+            }
+        }
+
+        let mut result = self.clone();
+        result.first_gid = first_gid;
+        for image in result.image.iter_mut().chain(
+            result
+                .tiles
+                .iter_mut()
+                .flatten()
+                .filter_map(|tile| tile.image.as_mut()),
+        ) {
+            *image = images[image.label()].clone();
+        }
+        result
+    }
+
+    /// Load a tileset file on its own, without a map that uses it. The first gid of the tileset is 1.
+    #[cfg(feature = "render")]
+    pub(crate) async fn load_standalone(
+        env: TmxLoadContext<'_>,
+        source: impl AsRef<Path>,
+    ) -> Result<Tileset> {
+        let tileset = Tileset::new(1, String::new());
+        let source = source.as_ref().to_string_lossy();
+        Tileset::load_external(tileset, env, source.as_ref()).await
+    }
+
+    /// Load an external tileset file, in either the xml (.tsx) or json format.
+    /// External tilesets are parsed only once per file, maps that use the same file share the parsed result.
+    pub(super) async fn load_external(
+        mut tileset: Tileset,
+        env: TmxLoadContext<'_>,
+        source: &str,
+    ) -> Result<Tileset> {
+        let source_path = Path::new(source);
+        let file_name = env.file_path(source_path);
+        let sub_env = env.file_directory(source_path);
+        env.add_dependency(source_path);
+        let file = env.load_file(source_path).await?;
+
+        let mut h = DefaultHasher::default();
+        file.hash(&mut h);
+        let hash = h.finish();
+        if let Some(cached) = env
+            .cached_tileset(&file_name, hash, tileset.first_gid)
+            .await
+        {
+            return Ok(cached);
+        }
+
+        if is_json_file(source_path) {
+            let value = serde_json::from_slice(file.as_slice())?;
+            tileset = Tileset::parse_json_tileset(tileset, sub_env, &value).await?;
+        } else {
+            let file = BufReader::new(file.as_slice());
+            let mut reader = EventReader::new(file);
+            loop {
+                if let XmlEvent::StartElement {
+                    name, attributes, ..
+                } = reader.next()?
+                {
+                    if name.local_name == "tileset" {
+                        let position = reader.position();
+                        tileset = Tileset::parse_tsx(tileset, sub_env, attributes, &mut reader)
+                            .await
+                            .with_context(|| in_element(&name.local_name, position))
+                            .with_context(|| format!("in {}", file_name.display()))?;
+                        break;
+                    } else {
+                        parse_empty(&mut reader)?;
+                    }
+                }
+            }
+        }
+
+        tileset.source = format!("{}", file_name.display());
+        env.cache_tileset(file_name, hash, &tileset).await;
+        Ok(tileset)
+    }
+
     /// Parse the actual tileset content
     async fn parse_tsx<R: Read + Send>(
         mut tileset: Tileset,
@@ -304,93 +750,68 @@ impl Tileset {
                 "margin" => margin = a.value.parse()?,
                 "tilecount" => tile_count = Some(a.value.parse()?),
                 "columns" => columns = Some(a.value.parse()?),
+                "objectalignment" => {
+                    tileset.object_alignment = parse_object_alignment(a.value.as_str())?
+                }
+                "fillmode" => tileset.fill_mode = parse_fill_mode(a.value.as_str())?,
                 _ => (),
             }
         }
 
         tileset.tile_size.x = tile_width as f32;
         tileset.tile_size.y = tile_height as f32;
+        tileset.grid_size = tileset.tile_size;
 
         while match reader.next()? {
             XmlEvent::StartElement {
                 name, attributes, ..
             } => {
+                let position = reader.position();
+                let context = || in_element(&name.local_name, position);
                 match name.local_name.as_ref() {
                     "image" => {
-                        let columns = columns;
-                        let spacing = spacing;
-                        let margin = margin;
-                        let tile_width = tile_width;
-                        let mut tiles_added = 0;
-                        let image = parse_image(env.clone(), attributes, reader).await?;
-                        tileset.image = Some(image.clone());
-
-                        let (width, height) = (image.width(), image.height());
-                        let (width, height) = (width as i32, height as i32);
-                        let columns = columns.unwrap_or_else(|| {
-                            let mut space = width - margin * 2;
-                            let mut cols = 0;
-                            while space >= tile_width {
-                                space -= tile_width + spacing;
-                                space -= spacing;
-                                cols += 1;
-                            }
-                            cols
-                        });
-                        let rows = {
-                            let mut space = height - margin * 2;
-                            let mut rows = 0;
-                            while space >= tile_height {
-                                space -= tile_height + spacing;
-                                rows += 1;
-                            }
-                            rows
+                        let image = parse_image(env.clone(), attributes, reader)
+                            .await
+                            .with_context(context)?;
+                        let grid = TileGrid {
+                            tile_width,
+                            tile_height,
+                            spacing,
+                            margin,
+                            tile_count,
+                            columns,
                         };
-
-                        for y in 0..rows {
-                            for x in 0..columns {
-                                if tile_count.map_or(true, |tc| tiles_added < tc) {
-                                    let u = (margin + x * tile_width + x * spacing) as f32
-                                        / width as f32;
-                                    let v = (margin + y * tile_height + y * spacing) as f32
-                                        / height as f32;
-                                    let w = tile_width as f32 / width as f32;
-                                    let h = tile_height as f32 / height as f32;
-
-                                    tileset.tiles.push(Some(Tile {
-                                        image: Some(image.clone()),
-                                        top_left: Vec2::new(u, v),
-                                        bottom_right: Vec2::new(u + w, v + h),
-                                        width: tile_width,
-                                        height: tile_height,
-                                        animation: Vec::new(),
-                                        properties: HashMap::new(),
-                                        object_group: Vec::new(),
-                                    }));
-
-                                    tiles_added += 1;
-                                } else {
-                                    break;
+                        tileset.add_image_tiles(image, &grid);
+                    }
+                    "tile" => {
+                        let (id, tile) = Tile::parse(env.clone(), attributes, reader)
+                            .await
+                            .with_context(context)?;
+                        tileset.add_tile(id, tile);
+                    }
+                    "grid" => {
+                        for a in attributes.iter() {
+                            match a.name.local_name.as_ref() {
+                                "orientation" => {
+                                    tileset.grid_orientation =
+                                        parse_grid_orientation(a.value.as_str())?
                                 }
+                                "width" => tileset.grid_size.x = a.value.parse()?,
+                                "height" => tileset.grid_size.y = a.value.parse()?,
+                                _ => (),
                             }
                         }
+                        parse_empty(reader)?;
                     }
-                    "tile" => {
-                        let (id, tile) = Tile::parse(env.clone(), attributes, reader).await?;
-
-                        if id < tileset.tiles.len() {
-                            if tileset.tiles[id].is_none() {
-                                tileset.tiles[id].replace(tile);
-                            } else {
-                                // we already checked if the tile exists, unwrap is safe.
-                                tileset.tiles[id].as_mut().unwrap().join(tile);
-                            }
-                        } else {
-                            while id > tileset.tiles.len() {
-                                tileset.tiles.push(None);
+                    "tileoffset" => {
+                        for a in attributes.iter() {
+                            match a.name.local_name.as_ref() {
+                                "x" => tileset.tile_offset.x = a.value.parse()?,
+                                "y" => tileset.tile_offset.y = a.value.parse()?,
+                                _ => (),
                             }
-                            tileset.tiles.push(Some(tile));
                         }
+                        parse_empty(reader)?;
                     }
                     _ => parse_empty(reader)?, // skip
                 }
@@ -411,9 +832,13 @@ impl Tile {
     fn join(&mut self, mut new_data: Tile) {
         self.properties = new_data.properties;
         self.animation = new_data.animation;
+        self.probability = new_data.probability;
+        self.ty = new_data.ty;
         if new_data.image.is_some() {
             self.top_left = new_data.top_left;
             self.bottom_right = new_data.bottom_right;
+            self.width = new_data.width;
+            self.height = new_data.height;
             self.image = new_data.image;
         }
         self.object_group.append(&mut new_data.object_group);
@@ -425,10 +850,21 @@ impl Tile {
         reader: &mut EventReader<R>,
     ) -> Result<(usize, Tile)> {
         let mut id = 0;
+        let mut probability = 1.0;
+        let mut ty = String::new();
+        let mut rect_position = IVec2::ZERO;
+        let mut rect_size = IVec2::ZERO;
 
         for a in attributes.iter() {
-            if a.name.local_name == "id" {
-                id = a.value.parse()?
+            match a.name.local_name.as_ref() {
+                "id" => id = a.value.parse()?,
+                "probability" => probability = a.value.parse()?,
+                "type" | "class" => ty = a.value.clone(),
+                "x" => rect_position.x = a.value.parse()?,
+                "y" => rect_position.y = a.value.parse()?,
+                "width" => rect_size.x = a.value.parse()?,
+                "height" => rect_size.y = a.value.parse()?,
+                _ => (),
             }
         }
 
@@ -441,6 +877,8 @@ impl Tile {
             animation: Vec::new(),
             properties: HashMap::new(),
             object_group: Vec::new(),
+            probability,
+            ty,
         };
 
         while match reader.next()? {
@@ -483,8 +921,41 @@ impl Tile {
             continue;
         }
 
+        result.select_sub_rectangle(rect_position, rect_size);
+
         Ok((id, result))
     }
+
+    /// Restrict a tile with its own image to the sub-rectangle at `position` with `size` in pixels,
+    ///  as set by the x, y, width and height attributes of tiles in collection tilesets.
+    /// A size of zero along an axis selects the rest of the image along that axis.
+    pub(super) fn select_sub_rectangle(&mut self, position: IVec2, size: IVec2) {
+        let image = match self.image.as_ref() {
+            Some(image) if image.width() > 0 && image.height() > 0 => image,
+            _ => return,
+        };
+        let image_size = IVec2::new(image.width() as i32, image.height() as i32);
+        let size = IVec2::new(
+            if size.x > 0 {
+                size.x
+            } else {
+                image_size.x - position.x
+            },
+            if size.y > 0 {
+                size.y
+            } else {
+                image_size.y - position.y
+            },
+        );
+        if position == IVec2::ZERO && size == image_size {
+            return;
+        }
+
+        self.top_left = position.as_f32() / image_size.as_f32();
+        self.bottom_right = (position + size).as_f32() / image_size.as_f32();
+        self.width = size.x;
+        self.height = size.y;
+    }
 }
 
 impl Layer {
@@ -492,9 +963,11 @@ impl Layer {
         attributes: Vec<OwnedAttribute>,
         reader: &mut EventReader<R>,
     ) -> Result<Self> {
+        let mut name = String::new();
         let mut position = IVec2::ZERO;
         let mut size = UVec2::ZERO;
         let mut color = Vec4::new(1.0, 1.0, 1.0, 1.0);
+        let mut opacity = 1.0;
         let mut visible = true;
         let mut offset = IVec2::ZERO;
         let mut parallax = Vec2::new(1.0, 1.0);
@@ -502,6 +975,7 @@ impl Layer {
 
         for a in attributes {
             match a.name.local_name.as_ref() {
+                "name" => name = a.value,
                 "x" => position.x = a.value.parse()?,
                 "y" => position.y = a.value.parse()?,
                 "width" => size.x = a.value.parse()?,
@@ -510,19 +984,38 @@ impl Layer {
                 "offsety" => offset.y = a.value.parse()?,
                 "parallaxx" => parallax.x = a.value.parse()?,
                 "parallaxy" => parallax.y = a.value.parse()?,
-                "opacity" => color.w *= a.value.parse::<f32>()?,
+                "opacity" => opacity = a.value.parse()?,
                 "tintcolor" => color *= parse_color_vec4(a.value.as_str())?,
-                "visible" => visible = a.value == "true",
+                "visible" => visible = a.value != "0",
                 _ => (), // skip
             }
         }
 
         while match reader.next()? {
             XmlEvent::StartElement {
-                name, attributes, ..
+                name: element,
+                attributes,
+                ..
             } => {
-                match name.local_name.as_ref() {
-                    "data" => data = parse_data(attributes, reader)?.into_vec_u32(),
+                let data_position = reader.position();
+                match element.local_name.as_ref() {
+                    "data" => match parse_data(attributes, reader)
+                        .with_context(|| in_element("data", data_position))?
+                    {
+                        Data::Chunks(chunks) => {
+                            let (chunk_position, chunk_size, chunk_data) =
+                                Data::merge_chunks(chunks)
+                                    .with_context(|| format!("invalid data in layer `{}`", name))?;
+                            position += chunk_position;
+                            size = chunk_size;
+                            data = chunk_data;
+                        }
+                        other => {
+                            data = other
+                                .into_vec_u32()
+                                .with_context(|| in_element("data", data_position))?
+                        }
+                    },
                     _ => parse_empty(reader)?, // skip
                 }
 
@@ -532,10 +1025,15 @@ impl Layer {
             _ => true,
         } {}
 
+        validate_tile_data(&mut data, size)
+            .with_context(|| format!("invalid data in layer `{}`", name))?;
+
         Ok(Layer::TileLayer {
+            name,
             position,
             size,
             color,
+            opacity,
             visible,
             offset,
             parallax,
@@ -551,20 +1049,25 @@ impl Layer {
         let mut offset = IVec2::ZERO;
         let mut parallax = Vec2::new(1.0, 1.0);
         let mut color = Vec4::new(1.0, 1.0, 1.0, 1.0);
+        let mut opacity = 1.0;
         let mut visible = true;
         let mut draworder_index = false;
+        let mut object_color = Layer::DEFAULT_OBJECT_COLOR;
         let mut objects = Vec::new();
+        let mut name = String::new();
 
         for a in attributes {
             match a.name.local_name.as_ref() {
+                "name" => name = a.value,
                 "offsetx" => offset.x = a.value.parse()?,
                 "offsety" => offset.y = a.value.parse()?,
                 "parallaxx" => parallax.x = a.value.parse()?,
                 "parallaxy" => parallax.y = a.value.parse()?,
-                "opacity" => color.w *= a.value.parse::<f32>()?,
+                "opacity" => opacity = a.value.parse()?,
                 "tintcolor" => color *= parse_color_vec4(a.value.as_str())?,
-                "visible" => visible = a.value == "true",
+                "visible" => visible = a.value != "0",
                 "draworder" => draworder_index = a.value == "index",
+                "color" => object_color = parse_color_vec4(a.value.as_str())?,
                 _ => (), // skip
             }
         }
@@ -573,9 +1076,14 @@ impl Layer {
             XmlEvent::StartElement {
                 name, attributes, ..
             } => {
+                let position = reader.position();
                 match name.local_name.as_ref() {
                     "object" => {
-                        objects.push(Object::parse(env.clone(), attributes, reader).await?);
+                        objects.push(
+                            Object::parse(env.clone(), attributes, reader)
+                                .await
+                                .with_context(|| in_element(&name.local_name, position))?,
+                        );
                     }
                     _ => parse_empty(reader)?, // skip
                 }
@@ -589,63 +1097,79 @@ impl Layer {
         }
 
         Ok(Layer::ObjectLayer {
+            name,
             offset,
             parallax,
             color,
+            opacity,
             visible,
             draworder_index,
+            object_color,
             objects,
         })
     }
 
-    async fn process(mut self, mut map: Map) -> Result<Map> {
-        //let mut new_tilesets = Vec::new();
-        //let mut next_first_gid = map.tilesets
-        //	.last()
-        //	.map(|ts| ts.first_gid + ts.tiles.len() as u32)
-        //	.unwrap_or(1);
+    /// Add the layer to `map`, after resolving the tile objects in it to the tilesets of the map.
+    /// Tilesets that are only used by tile objects from templates are added to the map.
+    pub(super) async fn process(mut self, env: TmxLoadContext<'_>, mut map: Map) -> Result<Map> {
+        self.resolve_tile_objects(&env, &mut map).await?;
+        map.layers.push(self);
+
+        Ok(map)
+    }
 
-        match &mut self {
-            Layer::ObjectLayer { objects, .. } => {
-                for object in objects.iter_mut() {
-                    if let Some(&Property::File(ref tileset_source)) =
-                        object.properties.get("__include_tileset__")
-                    {
-                        let mut found = false;
-                        for tileset in map.tilesets.iter() {
-                            if tileset.source == tileset_source.as_ref() {
-                                object.tile = object.tile.map(|t| tileset.first_gid + t);
-                                found = true;
+    /// Resolve the tiles and anchors of tile objects in this layer and its child layers to the tilesets of `map`.
+    fn resolve_tile_objects<'a>(
+        &'a mut self,
+        env: &'a TmxLoadContext<'_>,
+        map: &'a mut Map,
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+        Box::pin(async move {
+            match self {
+                Layer::ObjectLayer { objects, .. } => {
+                    for object in objects.iter_mut() {
+                        if let Some(Property::File(tileset_source)) =
+                            object.properties.remove("__include_tileset__")
+                        {
+                            let tileset = map.tileset_by_source(&tileset_source).cloned();
+                            let tileset = match tileset {
+                                Some(tileset) => tileset,
+                                None => {
+                                    // the tileset is only used by the template, add it after the tilesets of the map
+                                    let first_gid = map
+                                        .tilesets
+                                        .iter()
+                                        .map(|ts| ts.first_gid + ts.tiles.len() as u32)
+                                        .max()
+                                        .unwrap_or(1);
+                                    let tileset = Tileset::load_external(
+                                        Tileset::new(first_gid, String::new()),
+                                        env.root(),
+                                        &tileset_source,
+                                    )
+                                    .await?;
+                                    map.tilesets.push(Arc::new(tileset));
+                                    map.tilesets.last().unwrap().clone()
+                                }
+                            };
+                            if let ObjectKind::Tile(tile) = &mut object.kind {
+                                *tile += tileset.first_gid;
                             }
                         }
-
-                        if !found {
-                            // tileset needs to be added to the map
-                            //object.tile = object.tile.map(|t| tileset.)
-
-                            println!("Can't find the tileset back in the map!!");
-                            println!(
-                                "Tilesets in map: {:#?}",
-                                map.tilesets
-                                    .iter()
-                                    .map(|ts| ts.source.as_str())
-                                    .collect::<Vec<_>>()
-                            );
-                            println!("Tileset in template: {}", tileset_source);
-
-                            todo!("Tilesets referenced in templates must also exist in the map for now.");
-
-                            //
+                        if let Some(tileset) = object.tile().and_then(|gid| map.get_tileset(gid)) {
+                            object.tile_anchor = tileset.object_alignment.anchor(&map.tile_type);
                         }
                     }
                 }
+                Layer::Group { layers, .. } => {
+                    for layer in layers.iter_mut() {
+                        layer.resolve_tile_objects(env, map).await?;
+                    }
+                }
+                _ => (),
             }
-            &mut _ => unreachable!(),
-        }
-
-        map.layers.push(self);
-
-        Ok(map)
+            Ok(())
+        })
     }
 
     async fn parse_image<R: Read + Send>(
@@ -658,17 +1182,24 @@ impl Layer {
         let mut offset = IVec2::ZERO;
         let mut parallax = Vec2::new(1.0, 1.0);
         let mut color = Vec4::new(1.0, 1.0, 1.0, 1.0);
+        let mut opacity = 1.0;
         let mut visible: bool = true;
+        let mut repeat_x = false;
+        let mut repeat_y = false;
+        let mut name = String::new();
 
         for a in attributes {
             match a.name.local_name.as_ref() {
+                "name" => name = a.value,
                 "offsetx" => offset.x = a.value.parse()?,
                 "offsety" => offset.y = a.value.parse()?,
                 "parallaxx" => parallax.x = a.value.parse()?,
                 "parallaxy" => parallax.y = a.value.parse()?,
-                "opacity" => color.w *= a.value.parse::<f32>()?,
+                "opacity" => opacity = a.value.parse()?,
                 "tintcolor" => color *= parse_color_vec4(a.value.as_str())?,
-                "visible" => visible = a.value == "true",
+                "visible" => visible = a.value != "0",
+                "repeatx" => repeat_x = a.value == "1",
+                "repeaty" => repeat_y = a.value == "1",
                 _ => (), // skip
             }
         }
@@ -693,11 +1224,15 @@ impl Layer {
         }
 
         image.map(|image| Layer::ImageLayer {
+            name,
             image,
             color,
+            opacity,
             visible,
             offset,
             parallax,
+            repeat_x,
+            repeat_y,
         })
     }
 
@@ -710,17 +1245,20 @@ impl Layer {
             let mut offset = IVec2::ZERO;
             let mut parallax = Vec2::new(1.0, 1.0);
             let mut color = Vec4::new(1.0, 1.0, 1.0, 1.0);
-            //let mut visible: Option<bool> = None;
+            let mut opacity = 1.0;
+            let mut visible = true;
+            let mut name = String::new();
 
             for a in attributes {
                 match a.name.local_name.as_ref() {
+                    "name" => name = a.value,
                     "offsetx" => offset.x = a.value.parse()?,
                     "offsety" => offset.y = a.value.parse()?,
                     "parallaxx" => parallax.x = a.value.parse()?,
                     "parallaxy" => parallax.y = a.value.parse()?,
-                    "opacity" => color.w *= a.value.parse::<f32>()?,
+                    "opacity" => opacity = a.value.parse()?,
                     "tintcolor" => color *= parse_color_vec4(a.value.as_str())?,
-                    //"visible" => visible = Some(a.value == "true"),
+                    "visible" => visible = a.value != "0",
                     _ => (), // skip
                 }
             }
@@ -757,15 +1295,57 @@ impl Layer {
             }
 
             for l in layers.iter_mut() {
-                l.add_offset(offset.x, offset.y);
                 l.mul_parallax(parallax.x, parallax.y);
                 l.mul_color(color);
+                l.mul_opacity(opacity);
+                if !visible {
+                    l.hide();
+                }
             }
-            Ok(Layer::Group { layers })
+            Ok(Layer::Group {
+                name,
+                offset,
+                layers,
+            })
         })
     }
 }
 
+impl Shape {
+    pub(super) fn rectangle(width: f32, height: f32) -> Self {
+        Shape {
+            points: vec![
+                vec2(0.0, 0.0),
+                vec2(width, 0.0),
+                vec2(width, height),
+                vec2(0.0, height),
+            ],
+            closed: true,
+        }
+    }
+
+    /// Approximate an ellipse that fits within `width` and `height` with 16 points.
+    pub(super) fn ellipse(width: f32, height: f32) -> Self {
+        let offset = vec2(width * 0.5, height * 0.5);
+        Shape {
+            points: (0..16)
+                .map(|i| {
+                    let a = i as f32 * std::f32::consts::PI / 8.0;
+                    offset + vec2(a.cos() * width * 0.5, a.sin() * height * 0.5)
+                })
+                .collect(),
+            closed: true,
+        }
+    }
+
+    pub(super) fn point() -> Self {
+        Shape {
+            points: vec![vec2(0.0, 0.0)],
+            closed: false,
+        }
+    }
+}
+
 impl Object {
     fn parse<'a, R: Read + Send>(
         env: TmxLoadContext<'a>,
@@ -776,7 +1356,7 @@ impl Object {
             let mut result = Object {
                 id: 0,
                 properties: HashMap::new(),
-                tile: None,
+                kind: ObjectKind::Rectangle,
                 shape: Shape {
                     points: Vec::new(),
                     closed: false,
@@ -789,30 +1369,13 @@ impl Object {
                 height: 0.0,
                 rotation: 0.0,
                 visible: true,
+                tile_anchor: Vec2::new(0.0, 1.0),
             };
 
             // see if there is a template
             for a in attributes.iter() {
                 if a.name.local_name == "template" {
-                    let sub_env = env.file_directory(Path::new(a.value.as_str()));
-
-                    let file = env
-                        .load_file(Path::new(a.value.as_str()).to_path_buf())
-                        .await?;
-                    let file = BufReader::new(file.as_slice());
-                    let mut reader = EventReader::new(file);
-
-                    loop {
-                        if let XmlEvent::StartElement { name, .. } = reader.next()? {
-                            if name.local_name == "template" {
-                                result =
-                                    Object::parse_template(sub_env.clone(), &mut reader).await?;
-                                break;
-                            } else {
-                                parse_empty(&mut reader)?;
-                            }
-                        }
-                    }
+                    result = Object::load_template(env.clone(), a.value.as_str()).await?;
                 }
             }
 
@@ -820,36 +1383,35 @@ impl Object {
             for a in attributes.iter() {
                 match a.name.local_name.as_ref() {
                     "id" => result.id = a.value.parse()?,
-                    "gid" => result.tile = Some(a.value.parse()?),
+                    "gid" => {
+                        // the tile of the instance is a tile of the map, not of the template's tileset
+                        result.kind = ObjectKind::Tile(a.value.parse()?);
+                        result.properties.remove("__include_tileset__");
+                    }
                     "name" => result.name = a.value.clone(),
-                    "type" => result.ty = a.value.clone(),
+                    "type" | "class" => result.ty = a.value.clone(),
                     "x" => result.x = a.value.parse()?,
                     "y" => result.y = a.value.parse()?,
                     "width" => result.width = a.value.parse()?,
                     "height" => result.height = a.value.parse()?,
                     "rotation" => result.rotation = a.value.parse()?,
-                    "visible" => result.visible = a.value == "true",
+                    "visible" => result.visible = a.value != "0",
                     _ => (),
                 }
             }
-
-            result.shape = Shape {
-                points: vec![
-                    vec2(0.0, 0.0),
-                    vec2(result.width, 0.0),
-                    vec2(result.width, result.height),
-                    vec2(0.0, result.height),
-                ],
-                closed: true,
-            };
+            result.apply_template_overrides();
 
             while match reader.next()? {
                 XmlEvent::StartElement {
                     name, attributes, ..
                 } => {
+                    let position = reader.position();
                     match name.local_name.as_ref() {
                         "properties" => {
-                            for (k, v) in parse_properties(reader)?.into_iter() {
+                            for (k, v) in parse_properties(reader)
+                                .with_context(|| in_element(&name.local_name, position))?
+                                .into_iter()
+                            {
                                 result.properties.insert(k, v);
                             }
                         }
@@ -867,43 +1429,32 @@ impl Object {
                                         _ => Err(anyhow!("invalid point")),
                                     }
                                 })
-                                .fold(Ok(Vec::new()), |vec, result| match vec {
-                                    Ok(mut vec) => {
-                                        vec.push(result?);
-                                        Ok(vec)
-                                    }
-                                    Err(e) => Err(e),
-                                });
+                                .collect::<Result<Vec<_>>>();
 
+                            let points =
+                                points.with_context(|| in_element(&name.local_name, position))?;
                             result.shape = Shape {
-                                points: points?,
+                                points: points.clone(),
                                 closed: name.local_name == "polygon",
                             };
+                            result.kind = if name.local_name == "polygon" {
+                                ObjectKind::Polygon(points)
+                            } else {
+                                ObjectKind::Polyline(points)
+                            };
                             parse_empty(reader)?;
                         }
                         "ellipse" => {
-                            let offset = vec2(result.width * 0.5, result.height * 0.5);
-                            result.shape = Shape {
-                                points: (0..16)
-                                    .into_iter()
-                                    .map(|i| {
-                                        let a = i as f32 * std::f32::consts::PI / 8.0;
-                                        offset
-                                            + vec2(
-                                                a.cos() * result.width * 0.5,
-                                                a.sin() * result.height * 0.5,
-                                            )
-                                    })
-                                    .collect(),
-                                closed: true,
-                            };
+                            result.kind = ObjectKind::Ellipse;
+                            result.shape = Shape::ellipse(result.width, result.height);
                             parse_empty(reader)?;
                         }
+                        "text" => {
+                            result.kind = ObjectKind::Text(parse_text(attributes, reader)?);
+                        }
                         "point" => {
-                            result.shape = Shape {
-                                points: vec![vec2(0.0, 0.0)],
-                                closed: false,
-                            };
+                            result.kind = ObjectKind::Point;
+                            result.shape = Shape::point();
                             parse_empty(reader)?;
                         }
                         _ => parse_empty(reader)?, // skip
@@ -919,6 +1470,43 @@ impl Object {
         })
     }
 
+    /// Update the object after the attributes of an instance were applied on top of its template, if any.
+    /// The shape of the template is kept, unless it depends on the size of the object, which the instance can override.
+    pub(super) fn apply_template_overrides(&mut self) {
+        match self.kind {
+            ObjectKind::Polygon(_) | ObjectKind::Polyline(_) | ObjectKind::Point => (),
+            ObjectKind::Ellipse => self.shape = Shape::ellipse(self.width, self.height),
+            ObjectKind::Rectangle | ObjectKind::Tile(_) | ObjectKind::Text(_) => {
+                self.shape = Shape::rectangle(self.width, self.height)
+            }
+        }
+    }
+
+    /// Load an object template file, in either the xml (.tx) or json format.
+    pub(super) async fn load_template(env: TmxLoadContext<'_>, source: &str) -> Result<Object> {
+        let source_path = Path::new(source);
+        let sub_env = env.file_directory(source_path);
+        env.add_dependency(source_path);
+        let file = env.load_file(source_path).await?;
+
+        if is_json_file(source_path) {
+            let value = serde_json::from_slice(file.as_slice())?;
+            return Object::parse_json_template(sub_env, &value).await;
+        }
+
+        let file = BufReader::new(file.as_slice());
+        let mut reader = EventReader::new(file);
+        loop {
+            if let XmlEvent::StartElement { name, .. } = reader.next()? {
+                if name.local_name == "template" {
+                    return Object::parse_template(sub_env, &mut reader).await;
+                } else {
+                    parse_empty(&mut reader)?;
+                }
+            }
+        }
+    }
+
     async fn parse_template<R: Read + Send>(
         env: TmxLoadContext<'_>,
         reader: &mut EventReader<R>,
@@ -965,9 +1553,9 @@ impl Object {
         }
 
         let mut object = object?;
-        if object.tile.is_some() {
+        if let ObjectKind::Tile(tile) = &mut object.kind {
             let (first_gid, source) = tileset?;
-            object.tile = object.tile.map(|t| t - first_gid);
+            *tile -= first_gid;
             object
                 .properties
                 .insert("__include_tileset__".to_string(), Property::File(source));
@@ -976,13 +1564,30 @@ impl Object {
     }
 }
 
+impl ObjectTemplate {
+    /// Load a template file on its own, without a map that uses it.
+    #[cfg(feature = "render")]
+    pub(crate) async fn load_standalone(
+        env: TmxLoadContext<'_>,
+        source: impl AsRef<Path>,
+    ) -> Result<ObjectTemplate> {
+        let source = source.as_ref().to_string_lossy();
+        let mut object = Object::load_template(env, source.as_ref()).await?;
+        let tileset = match object.properties.remove("__include_tileset__") {
+            Some(Property::File(tileset)) => Some(tileset),
+            _ => None,
+        };
+        Ok(ObjectTemplate { object, tileset })
+    }
+}
+
 async fn parse_image<R: Read + Send>(
     env: TmxLoadContext<'_>,
     attributes: Vec<OwnedAttribute>,
     reader: &mut EventReader<R>,
 ) -> Result<texture::Texture> {
     let mut source: Option<String> = None;
-    //let mut trans: Option<[u8; 4]> = None;
+    let mut trans: Option<[u8; 3]> = None;
     let mut width: Option<u32> = None;
     let mut height: Option<u32> = None;
     let mut data: Option<Vec<u8>> = None;
@@ -991,7 +1596,7 @@ async fn parse_image<R: Read + Send>(
     for a in attributes.iter() {
         match a.name.local_name.as_ref() {
             "source" => source = Some(a.value.clone()),
-            //"trans" => trans = Some(parse_color(a.value.as_str())),
+            "trans" => trans = Some(parse_transparent_color(a.value.as_str())?),
             "width" => width = Some(a.value.parse()?),
             "height" => height = Some(a.value.parse()?),
             //"format" => format = Some(a.value.clone()),
@@ -1004,7 +1609,7 @@ async fn parse_image<R: Read + Send>(
             name, attributes, ..
         } => {
             match name.local_name.as_ref() {
-                "data" => data = Some(parse_data(attributes, reader)?.into_vec_u8()),
+                "data" => data = Some(parse_data(attributes, reader)?.into_vec_u8()?),
                 _ => parse_empty(reader)?, // skip
             }
 
@@ -1016,86 +1621,141 @@ async fn parse_image<R: Read + Send>(
         continue;
     }
 
+    load_image(env, source, data, trans, width, height).await
+}
+
+/// Parse a transparent color key, in the format `[r, g, b]`.
+pub(super) fn parse_transparent_color(text: &str) -> Result<[u8; 3]> {
+    let [_, r, g, b] = parse_color(text)?;
+    Ok([r, g, b])
+}
+
+/// Load an image from either a `source` path or embedded `data`, and resize it to `width` and `height` if those are known.
+/// Pixels that match the `trans` color are made transparent.
+pub(super) async fn load_image(
+    env: TmxLoadContext<'_>,
+    source: Option<String>,
+    data: Option<Vec<u8>>,
+    trans: Option<[u8; 3]>,
+    width: Option<u32>,
+    height: Option<u32>,
+) -> Result<texture::Texture> {
     let mut image = if let Some(source) = source.as_ref() {
-        Texture::from_path(env.file_path(Path::new(source)))
+        let path = env.file_path(Path::new(source));
+        if env.is_archived(Path::new(source)) {
+            // the asset server can't read from archives, so images in them are read right away
+            let bytes = env.load_file(Path::new(source)).await?;
+            Texture::from_bytes(bytes.as_slice(), format!("{}", path.display()), trans)?
+        } else {
+            Texture::from_path(path, trans)
+        }
     } else if let Some(data) = data {
-        let mut h = AHasher::default();
+        let mut h = DefaultHasher::default();
         data.hash(&mut h);
-        Texture::from_bytes(data.as_slice(), format!("embedded#{}", h.finish()))?
+        Texture::from_bytes(data.as_slice(), format!("embedded#{}", h.finish()), trans)?
     } else {
         bail!("invalid image")
     };
 
     if let (Some(width), Some(height)) = (width, height) {
         image = image.resize(width, height).await?;
+    } else if let (Some(source), 0) = (source.as_ref(), image.width()) {
+        // the size of the image is needed to compute tile coordinates, so read it from the image itself.
+        image = read_image_size(&env, source, image).await?;
     }
     Ok(image)
 }
 
+#[cfg(feature = "image")]
+async fn read_image_size(
+    env: &TmxLoadContext<'_>,
+    source: &str,
+    image: Texture,
+) -> Result<Texture> {
+    let bytes = env.load_file(Path::new(source)).await?;
+    image.with_contents(bytes).await
+}
+
+/// Without the `image` feature the size of the image stays unknown, so no tiles can be cut from it.
+#[cfg(not(feature = "image"))]
+async fn read_image_size(_: &TmxLoadContext<'_>, _: &str, image: Texture) -> Result<Texture> {
+    Ok(image)
+}
+
 fn parse_data<R: Read + Send>(
     attributes: Vec<OwnedAttribute>,
     reader: &mut EventReader<R>,
 ) -> Result<Data> {
-    let mut decode_csv = false;
-    let mut decode_base64 = false;
-    let mut decompress_z = false;
-    let mut decompress_g = false;
+    let mut encoding = DataEncoding::default();
 
     for a in attributes.iter() {
         match a.name.local_name.as_ref() {
-            "encoding" => match a.value.as_ref() {
-                "csv" => decode_csv = true,
-                "base64" => decode_base64 = true,
-                _ => (),
-            },
-            "compression" => match a.value.as_ref() {
-                "zlib" => decompress_z = true,
-                "glib" => decompress_g = true,
-                _ => (),
-            },
+            "encoding" => encoding.set_encoding(a.value.as_str()),
+            "compression" => encoding.set_compression(a.value.as_str()),
             _ => (),
         }
     }
 
     let mut result = Data::U32(Vec::new());
+    let mut chunks = Vec::new();
 
     while match reader.next()? {
-        XmlEvent::StartElement { .. } => {
-            parse_empty(reader)?;
+        XmlEvent::StartElement {
+            name, attributes, ..
+        } => {
+            match name.local_name.as_ref() {
+                "chunk" => chunks.push(parse_chunk(&encoding, attributes, reader)?),
+                _ => parse_empty(reader)?, // skip
+            }
+
             true
         }
         XmlEvent::Characters(s) => {
-            if decode_csv {
-                result = Data::U32(
-                    s.split(',')
-                        .filter(|v| v.trim() != "")
-                        .map(|v| v.replace('\r', "").parse().unwrap_or(0))
-                        .collect(),
-                );
-            } else if decode_base64 {
-                let bytes = base64::decode(s.trim().as_bytes())?;
-
-                let bytes = if decompress_z {
-                    let mut zd = libflate::zlib::Decoder::new(BufReader::new(&bytes[..]))?;
-                    let mut bytes = Vec::new();
-                    zd.read_to_end(&mut bytes)?;
-
-                    bytes
-                } else if decompress_g {
-                    let mut zd = libflate::gzip::Decoder::new(BufReader::new(&bytes[..]))?;
-                    let mut bytes = Vec::new();
-                    zd.read_to_end(&mut bytes)?;
-
-                    bytes
-                } else {
-                    bytes
-                };
+            result = encoding.decode(s.as_str())?;
 
-                result = Data::U8(bytes)
-            } else {
-                bail!("<tile> based data is not supported");
-            }
+            true
+        }
+        XmlEvent::EndElement { .. } => false,
+        _ => true,
+    } {
+        continue;
+    }
+
+    if chunks.is_empty() {
+        Ok(result)
+    } else {
+        Ok(Data::Chunks(chunks))
+    }
+}
+
+fn parse_chunk<R: Read + Send>(
+    encoding: &DataEncoding,
+    attributes: Vec<OwnedAttribute>,
+    reader: &mut EventReader<R>,
+) -> Result<Chunk> {
+    let mut chunk = Chunk {
+        position: IVec2::ZERO,
+        size: UVec2::ZERO,
+        data: Vec::new(),
+    };
 
+    for a in attributes {
+        match a.name.local_name.as_ref() {
+            "x" => chunk.position.x = a.value.parse()?,
+            "y" => chunk.position.y = a.value.parse()?,
+            "width" => chunk.size.x = a.value.parse()?,
+            "height" => chunk.size.y = a.value.parse()?,
+            _ => (), // skip
+        }
+    }
+
+    while match reader.next()? {
+        XmlEvent::StartElement { .. } => {
+            parse_empty(reader)?;
+            true
+        }
+        XmlEvent::Characters(s) => {
+            chunk.data = encoding.decode(s.as_str())?.into_vec_u32()?;
             true
         }
         XmlEvent::EndElement { .. } => false,
@@ -1104,7 +1764,21 @@ fn parse_data<R: Read + Send>(
         continue;
     }
 
-    Ok(result)
+    Ok(chunk)
+}
+
+#[cfg(feature = "zstd")]
+fn decompress_zstd_bytes(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut zd = zstd::stream::read::Decoder::new(BufReader::new(bytes))?;
+    let mut bytes = Vec::new();
+    zd.read_to_end(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+#[cfg(not(feature = "zstd"))]
+fn decompress_zstd_bytes(_: &[u8]) -> Result<Vec<u8>> {
+    bail!("zstd compressed data requires the `zstd` feature")
 }
 
 fn parse_properties<R: Read + Send>(
@@ -1116,9 +1790,11 @@ fn parse_properties<R: Read + Send>(
         XmlEvent::StartElement {
             name, attributes, ..
         } => {
+            let position = reader.position();
             match name.local_name.as_ref() {
                 "property" => {
-                    let (k, v) = parse_property(attributes, reader)?;
+                    let (k, v) = parse_property(attributes, reader)
+                        .with_context(|| in_element(&name.local_name, position))?;
                     result.insert(k, v);
                 }
                 _ => parse_empty(reader)?, // skip
@@ -1140,43 +1816,127 @@ fn parse_property<R: Read + Send>(
     reader: &mut EventReader<R>,
 ) -> Result<(String, Property)> {
     let mut key = String::from("");
-    let mut value = Property::Int(0);
-    let mut ty = 0;
+    let mut ty = String::from("string");
+    let mut value = None;
+    let mut members = HashMap::new();
 
     for a in attributes {
         match a.name.local_name.as_ref() {
             "name" => key = a.value.clone(),
-            "type" => {
-                ty = match a.value.as_ref() {
-                    "string" => 0,
-                    "int" => 1,
-                    "float" => 2,
-                    "bool" => 3,
-                    "color" => 4,
-                    "file" => 5,
-                    _ => bail!("invalid property type"),
-                }
+            "type" => ty = a.value.clone(),
+            "value" => value = Some(a.value.clone()),
+            _ => (), // skip
+        }
+    }
+
+    while match reader.next()? {
+        XmlEvent::StartElement { name, .. } => {
+            match name.local_name.as_ref() {
+                "properties" => members = parse_properties(reader)?,
+                _ => parse_empty(reader)?, // skip
             }
-            "value" => {
-                value = match ty {
-                    0 => Property::String(a.value.clone()),
-                    1 => Property::Int(a.value.parse()?),
-                    2 => Property::Float(a.value.parse()?),
-                    3 => Property::Bool(a.value == "true"),
-                    4 => Property::Color(parse_color(a.value.as_str())?),
-                    5 => Property::File(a.value.clone()),
-                    _ => unreachable!(),
-                }
+            true
+        }
+        XmlEvent::Characters(text) => {
+            // multiline strings are stored as text instead of in the value attribute
+            if value.is_none() {
+                value = Some(text);
             }
-            _ => (), // skip
+            true
         }
+        XmlEvent::EndElement { .. } => false,
+        _ => true,
+    } {
+        continue;
     }
 
-    parse_empty(reader)?;
+    let value = match value {
+        _ if ty == "class" => Property::Class(members),
+        Some(value) => parse_property_value(ty.as_str(), value.as_str())?,
+        None => Property::Int(0),
+    };
 
     Ok((key, value))
 }
 
+/// Parse the value of a property of type `ty`.
+pub(super) fn parse_property_value(ty: &str, value: &str) -> Result<Property> {
+    Ok(match ty {
+        "string" => Property::String(value.to_string()),
+        "int" => Property::Int(value.parse()?),
+        "float" => Property::Float(value.parse()?),
+        "bool" => Property::Bool(value == "true"),
+        "color" => Property::Color(parse_color(value)?),
+        "file" => Property::File(value.to_string()),
+        "object" => Property::Object(value.parse()?),
+        // custom types that are not known to bevy_tmx are stored as their raw value
+        _ => Property::String(value.to_string()),
+    })
+}
+
+fn parse_text<R: Read + Send>(
+    attributes: Vec<OwnedAttribute>,
+    reader: &mut EventReader<R>,
+) -> Result<TextData> {
+    let mut result = TextData {
+        text: String::new(),
+        font_family: String::from("sans-serif"),
+        pixel_size: 16,
+        color: [255, 0, 0, 0],
+        wrap: false,
+        halign: HorizontalAlignment::Left,
+        valign: VerticalAlignment::Top,
+    };
+
+    for a in attributes {
+        match a.name.local_name.as_ref() {
+            "fontfamily" => result.font_family = a.value.clone(),
+            "pixelsize" => result.pixel_size = a.value.parse()?,
+            "color" => result.color = parse_color(a.value.as_str())?,
+            "wrap" => result.wrap = a.value == "1",
+            "halign" => result.halign = parse_halign(a.value.as_str())?,
+            "valign" => result.valign = parse_valign(a.value.as_str())?,
+            _ => (), // skip
+        }
+    }
+
+    while match reader.next()? {
+        XmlEvent::StartElement { .. } => {
+            parse_empty(reader)?;
+            true
+        }
+        XmlEvent::Characters(s) | XmlEvent::Whitespace(s) | XmlEvent::CData(s) => {
+            result.text.push_str(s.as_str());
+            true
+        }
+        XmlEvent::EndElement { .. } => false,
+        _ => true,
+    } {
+        continue;
+    }
+
+    Ok(result)
+}
+
+pub(super) fn parse_halign(value: &str) -> Result<HorizontalAlignment> {
+    Ok(match value {
+        "left" => HorizontalAlignment::Left,
+        "center" => HorizontalAlignment::Center,
+        "right" => HorizontalAlignment::Right,
+        "justify" => HorizontalAlignment::Justify,
+        _ => bail!("invalid halign"),
+    })
+}
+
+pub(super) fn parse_valign(value: &str) -> Result<VerticalAlignment> {
+    Ok(match value {
+        "top" => VerticalAlignment::Top,
+        "center" => VerticalAlignment::Center,
+        "bottom" => VerticalAlignment::Bottom,
+        _ => bail!("invalid valign"),
+    })
+}
+
 fn parse_animation<R: Read + Send>(reader: &mut EventReader<R>) -> Result<Vec<Frame>> {
     let mut result = Vec::new();
 
@@ -1222,6 +1982,16 @@ fn parse_frame<R: Read + Send>(
     Ok(frame)
 }
 
+/// Describe the location of an element for the context of parse errors.
+fn in_element(name: &str, position: TextPosition) -> String {
+    format!(
+        "in <{}> at line {}, column {}",
+        name,
+        position.row + 1,
+        position.column + 1
+    )
+}
+
 fn parse_empty<R: Read + Send>(reader: &mut EventReader<R>) -> Result<()> {
     while match reader.next()? {
         XmlEvent::StartElement { .. } => {
@@ -1236,7 +2006,7 @@ fn parse_empty<R: Read + Send>(reader: &mut EventReader<R>) -> Result<()> {
     Ok(())
 }
 
-fn parse_color(text: &str) -> Result<[u8; 4]> {
+pub(super) fn parse_color(text: &str) -> Result<[u8; 4]> {
     let lowercase: Vec<char> = text
         .chars()
         .filter(|&c| c != '#')
@@ -1283,7 +2053,729 @@ fn parse_color(text: &str) -> Result<[u8; 4]> {
     }
 }
 
-fn parse_color_vec4(text: &str) -> Result<Vec4> {
+pub(super) fn parse_color_vec4(text: &str) -> Result<Vec4> {
     let [a, r, g, b] = parse_color(text)?;
     Ok(Vec4::new(r as f32, g as f32, b as f32, a as f32) * (1.0 / 255.0))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{fixtures, map_xml, parse, try_parse};
+
+    /// Parse the first `<data>` element in `xml`.
+    fn parse_data_element(xml: &str) -> Result<Data> {
+        let mut reader = EventReader::new(xml.as_bytes());
+        loop {
+            if let XmlEvent::StartElement { attributes, .. } = reader.next()? {
+                return parse_data(attributes, &mut reader);
+            }
+        }
+    }
+
+    /// The little endian bytes of the tiles, as stored in base64 encoded tile data.
+    fn tile_bytes(tiles: &[u32]) -> Vec<u8> {
+        tiles.iter().flat_map(|tile| tile.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn base64_data_round_trips() {
+        let tiles = vec![1, 2, 3, 0x8000_0004, 0, 17];
+        let xml = format!(
+            r#"<data encoding="base64">{}</data>"#,
+            base64::encode(tile_bytes(&tiles))
+        );
+        let data = parse_data_element(&xml).unwrap();
+        assert_eq!(data.into_vec_u32().unwrap(), tiles);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_data_round_trips() {
+        let tiles = vec![1, 2, 3, 0x8000_0004, 0, 17];
+        let compressed = zstd::encode_all(tile_bytes(&tiles).as_slice(), 0).unwrap();
+        let xml = format!(
+            r#"<data encoding="base64" compression="zstd">{}</data>"#,
+            base64::encode(compressed)
+        );
+        let data = parse_data_element(&xml).unwrap();
+        assert_eq!(data.into_vec_u32().unwrap(), tiles);
+    }
+
+    #[test]
+    fn gzip_data_decodes() {
+        use std::io::Write;
+
+        let tiles = vec![4, 3, 2, 1, 0x4000_0001];
+        let mut encoder = libflate::gzip::Encoder::new(Vec::new()).unwrap();
+        encoder.write_all(&tile_bytes(&tiles)).unwrap();
+        let compressed = encoder.finish().into_result().unwrap();
+        let xml = format!(
+            r#"<data encoding="base64" compression="gzip">{}</data>"#,
+            base64::encode(compressed)
+        );
+        let data = parse_data_element(&xml).unwrap();
+        assert_eq!(data.into_vec_u32().unwrap(), tiles);
+    }
+
+    #[test]
+    fn background_color_is_parsed() {
+        let map = parse(&map_xml(1, 1, r##"backgroundcolor="#ff8040""##, ""));
+        assert_eq!(map.background, [255, 0xff, 0x80, 0x40]);
+        let map = parse(&map_xml(1, 1, r##"backgroundcolor="#80ff8040""##, ""));
+        assert_eq!(map.background, [0x80, 0xff, 0x80, 0x40]);
+        let map = parse(&map_xml(1, 1, "", ""));
+        assert_eq!(map.background, [0; 4]);
+    }
+
+    /// Parse a map with an object layer containing `objects`, and return the objects of the map.
+    fn parse_objects(objects: &str) -> Vec<Object> {
+        let map = parse(&map_xml(
+            4,
+            4,
+            "",
+            &format!(
+                r#" <objectgroup id="2" name="objects">{}</objectgroup>"#,
+                objects
+            ),
+        ));
+        map.objects().map(|(_, object)| object.clone()).collect()
+    }
+
+    #[test]
+    fn ellipse_objects_are_parsed() {
+        let objects = parse_objects(
+            r#"<object id="1" x="10" y="20" width="30" height="12" rotation="45"><ellipse/></object>
+               <object id="2" x="1" y="2" width="3" height="4"/>"#,
+        );
+        assert_eq!(objects[0].kind, ObjectKind::Ellipse);
+        assert_eq!(
+            (
+                objects[0].x,
+                objects[0].y,
+                objects[0].width,
+                objects[0].height
+            ),
+            (10.0, 20.0, 30.0, 12.0)
+        );
+        assert_eq!(objects[0].rotation, 45.0);
+        assert_eq!(objects[1].kind, ObjectKind::Rectangle);
+    }
+
+    #[test]
+    fn point_objects_are_parsed() {
+        let objects = parse_objects(
+            r#"<object id="1" name="spawn" x="12.5" y="40"><point/></object>
+               <object id="2" name="exit" x="60" y="8"><point/></object>"#,
+        );
+        let points: Vec<_> = objects
+            .iter()
+            .map(|object| {
+                (
+                    object.kind.clone(),
+                    object.name.as_str(),
+                    object.x,
+                    object.y,
+                )
+            })
+            .collect();
+        assert_eq!(
+            points,
+            vec![
+                (ObjectKind::Point, "spawn", 12.5, 40.0),
+                (ObjectKind::Point, "exit", 60.0, 8.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn text_objects_are_parsed() {
+        let objects = parse_objects(
+            r##"<object id="1" x="0" y="0" width="80" height="20">
+                 <text fontfamily="Serif" pixelsize="12" wrap="1" color="#ff0000" halign="center" valign="bottom">Hello
+world</text>
+                </object>
+                <object id="2" x="0" y="0"><text>plain</text></object>"##,
+        );
+        let text = match &objects[0].kind {
+            ObjectKind::Text(text) => text,
+            kind => panic!("expected a text object, got {:?}", kind),
+        };
+        assert_eq!(text.text, "Hello\nworld");
+        assert_eq!(text.font_family, "Serif");
+        assert_eq!(text.pixel_size, 12);
+        assert_eq!(text.color, [255, 255, 0, 0]);
+        assert!(text.wrap);
+        assert_eq!(text.halign, HorizontalAlignment::Center);
+        assert_eq!(text.valign, VerticalAlignment::Bottom);
+
+        let text = objects[1].text().unwrap();
+        assert_eq!(text.text, "plain");
+        assert_eq!(text.pixel_size, 16);
+        assert!(!text.wrap);
+        assert_eq!(text.halign, HorizontalAlignment::Left);
+        assert_eq!(text.valign, VerticalAlignment::Top);
+    }
+
+    #[test]
+    fn object_class_is_an_alias_for_type() {
+        let objects = parse_objects(
+            r#"<object id="1" class="enemy" x="0" y="0"/>
+               <object id="2" type="door" x="0" y="0"/>"#,
+        );
+        assert_eq!(objects[0].ty, "enemy");
+        assert_eq!(objects[1].ty, "door");
+    }
+
+    #[test]
+    fn chunks_are_merged_into_the_layer() {
+        let source = std::fs::read_to_string(fixtures().join("infinite.tmx")).unwrap();
+        let map = parse(&source);
+        assert!(map.infinite);
+        match &map.layers[0] {
+            Layer::TileLayer {
+                position,
+                size,
+                data,
+                ..
+            } => {
+                assert_eq!(*position, IVec2::new(-2, 0));
+                assert_eq!(*size, UVec2::new(8, 4));
+                let tiles: Vec<(u32, u32, u32)> = data
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &gid)| gid != 0)
+                    .map(|(i, &gid)| (i as u32 % size.x, i as u32 / size.x, gid))
+                    .collect();
+                assert_eq!(tiles, vec![(0, 0, 1), (1, 1, 2), (7, 2, 3), (6, 3, 4)]);
+            }
+            _ => panic!("expected a tile layer"),
+        }
+    }
+
+    #[test]
+    fn chunks_far_apart_are_rejected() {
+        let result = try_parse(&map_xml(
+            4,
+            4,
+            r#"infinite="1""#,
+            r#" <layer id="1" name="l" width="4" height="4"><data encoding="csv">
+  <chunk x="-1000000000" y="-1000000000" width="1" height="1">1</chunk>
+  <chunk x="1000000000" y="1000000000" width="1" height="1">1</chunk>
+ </data></layer>"#,
+        ));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn chunks_in_image_data_are_an_error() {
+        let result = try_parse(&map_xml(
+            1,
+            1,
+            "",
+            r#" <imagelayer id="1" name="i"><image width="1" height="1"><data encoding="base64">
+  <chunk x="0" y="0" width="1" height="1">AAAA</chunk>
+ </data></image></imagelayer>"#,
+        ));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn collection_tilesets_give_every_tile_its_own_image() {
+        let map = parse(&map_xml(
+            1,
+            1,
+            "",
+            r#" <tileset firstgid="5" source="collection.tsx"/>"#,
+        ));
+        let image = |gid: u32| {
+            let tile = map.get_tile(gid).unwrap();
+            let image = tile.image.as_ref().unwrap();
+            let name = image.path().unwrap().file_name().unwrap().to_owned();
+            (name.into_string().unwrap(), image.width(), image.height())
+        };
+        assert_eq!(image(5), ("red.png".to_string(), 8, 8));
+        // the size of the image isn't in the tileset, so it's read from the file
+        #[cfg(feature = "image")]
+        assert_eq!(image(8), ("blue.png".to_string(), 16, 24));
+        assert_eq!(image(8 | Map::FLIPPED_VERTICALLY), image(8));
+        assert!(!matches!(
+            map.get_tile(6),
+            Some(Tile { image: Some(_), .. })
+        ));
+        // the tiles of the first tileset still come from its atlas
+        assert_eq!(image(1), ("tiles.png".to_string(), 32, 32));
+
+        let tile = map.get_tile(8).unwrap();
+        assert_eq!((tile.top_left, tile.bottom_right), (Vec2::ZERO, Vec2::ONE));
+    }
+
+    #[test]
+    fn visibility_is_parsed_from_numbers() {
+        let source = std::fs::read_to_string(fixtures().join("hidden.tmx")).unwrap();
+        let map = parse(&source);
+        let visible: Vec<bool> = map
+            .layers
+            .iter()
+            .map(|layer| match layer {
+                Layer::TileLayer { visible, .. } | Layer::ImageLayer { visible, .. } => *visible,
+                _ => panic!("expected a tile or image layer"),
+            })
+            .collect();
+        assert_eq!(visible, vec![true, false, false]);
+
+        let objects = parse_objects(
+            r#"<object id="1" x="0" y="0" visible="1"/>
+               <object id="2" x="0" y="0" visible="0"/>
+               <object id="3" x="0" y="0"/>"#,
+        );
+        let visible: Vec<bool> = objects.iter().map(|object| object.visible).collect();
+        assert_eq!(visible, vec![true, false, true]);
+    }
+
+    #[test]
+    fn object_properties_reference_objects_by_id() {
+        let objects = parse_objects(
+            r#"<object id="1" name="switch" x="0" y="0"/>
+               <object id="2" name="door" x="16" y="0">
+                <properties>
+                 <property name="opened_by" type="object" value="1"/>
+                 <property name="closed_by" type="object" value="0"/>
+                </properties>
+               </object>"#,
+        );
+        let door = &objects[1].properties;
+        assert!(matches!(door["opened_by"], Property::Object(1)));
+        assert_eq!(door["opened_by"].as_object(), Some(objects[0].id));
+        // 0 means the property doesn't reference an object
+        assert!(matches!(door["closed_by"], Property::Object(0)));
+        assert_eq!(door["closed_by"].as_object(), None);
+    }
+
+    #[test]
+    fn enum_and_class_properties_are_parsed() {
+        let objects = parse_objects(
+            r#"<object id="1" x="0" y="0">
+                <properties>
+                 <property name="facing" type="string" propertytype="Direction" value="north"/>
+                 <property name="mood" type="Mood" value="angry"/>
+                 <property name="stats" type="class" propertytype="Stats">
+                  <properties>
+                   <property name="hp" type="int" value="10"/>
+                   <property name="speed" type="float" value="1.5"/>
+                   <property name="weapon" type="class" propertytype="Weapon">
+                    <properties>
+                     <property name="name" value="sword"/>
+                    </properties>
+                   </property>
+                  </properties>
+                 </property>
+                 <property name="defaults" type="class" propertytype="Stats"/>
+                </properties>
+               </object>"#,
+        );
+        let properties = &objects[0].properties;
+        assert_eq!(properties["facing"].as_str(), Some("north"));
+        // enums with a type that isn't known keep their raw value
+        assert_eq!(properties["mood"].as_str(), Some("angry"));
+
+        let stats = properties["stats"].as_class().unwrap();
+        assert_eq!(stats["hp"].as_int(), Some(10));
+        assert_eq!(stats["speed"].as_float(), Some(1.5));
+        let weapon = stats["weapon"].as_class().unwrap();
+        assert_eq!(weapon["name"].as_str(), Some("sword"));
+        // classes without members use the default values of their type
+        assert!(properties["defaults"].as_class().unwrap().is_empty());
+    }
+
+    #[test]
+    fn multiline_properties_keep_their_newlines() {
+        let objects = parse_objects(
+            "<object id=\"1\" x=\"0\" y=\"0\">
+              <properties>
+               <property name=\"notes\">first line
+second line
+
+last line</property>
+               <property name=\"short\" value=\"single line\"/>
+              </properties>
+             </object>",
+        );
+        let properties = &objects[0].properties;
+        assert_eq!(
+            properties["notes"].as_str(),
+            Some("first line\nsecond line\n\nlast line")
+        );
+        assert_eq!(properties["short"].as_str(), Some("single line"));
+    }
+
+    #[test]
+    fn tile_probabilities_are_parsed() {
+        let map = parse(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" renderorder="right-down" width="1" height="1" tilewidth="16" tileheight="16" infinite="0">
+ <tileset firstgid="1" name="tiles" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+  <image source="tiles.png" width="32" height="32"/>
+  <tile id="1" probability="0.25"/>
+  <tile id="2" probability="3"/>
+  <tile id="3" probability="0"/>
+ </tileset>
+</map>"#,
+        );
+        let probabilities: Vec<f32> = (1..=4)
+            .map(|gid| map.get_tile(gid).unwrap().probability)
+            .collect();
+        assert_eq!(probabilities, vec![1.0, 0.25, 3.0, 0.0]);
+    }
+
+    #[test]
+    fn tileset_grids_are_parsed() {
+        let map = parse(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="isometric" renderorder="right-down" width="1" height="1" tilewidth="64" tileheight="32" infinite="0">
+ <tileset firstgid="1" name="tiles" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+  <grid orientation="isometric" width="64" height="32"/>
+  <image source="tiles.png" width="32" height="32"/>
+ </tileset>
+ <tileset firstgid="5" name="plain" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+  <image source="tiles.png" width="32" height="32"/>
+ </tileset>
+</map>"#,
+        );
+        let grid = &map.tilesets[0];
+        assert_eq!(grid.grid_orientation, GridOrientation::Isometric);
+        assert_eq!(grid.grid_size, Vec2::new(64.0, 32.0));
+
+        // without a grid element, the grid is orthogonal with the size of the tiles
+        let plain = &map.tilesets[1];
+        assert_eq!(plain.grid_orientation, GridOrientation::Orthogonal);
+        assert_eq!(plain.grid_size, Vec2::new(16.0, 16.0));
+    }
+
+    #[test]
+    fn the_transparent_color_is_parsed() {
+        let map = parse(&map_xml(
+            1,
+            1,
+            "",
+            r#" <imagelayer id="1" name="background">
+  <image source="magenta.png" trans="ff00ff" width="4" height="4"/>
+ </imagelayer>"#,
+        ));
+        match &map.layers[0] {
+            Layer::ImageLayer { image, .. } => {
+                assert_eq!(image.transparent_color(), Some([0xff, 0x00, 0xff]))
+            }
+            _ => panic!("expected an image layer"),
+        }
+    }
+
+    #[test]
+    fn tilesets_of_templates_are_added_to_the_map() {
+        let map = parse(&map_xml(
+            2,
+            2,
+            "",
+            r#"<objectgroup id="1" name="objects">
+                <object id="1" template="crate.tx" x="0" y="32"/>
+            </objectgroup>"#,
+        ));
+
+        // the embedded tileset of the map doesn't come from tiles.tsx, so the template's tileset follows it
+        assert_eq!(map.tilesets.len(), 2);
+        assert_eq!(map.tilesets[1].first_gid, 5);
+        let object = match &map.layers[0] {
+            Layer::ObjectLayer { objects, .. } => &objects[0],
+            _ => panic!("expected an object layer"),
+        };
+        assert_eq!(object.kind, ObjectKind::Tile(6));
+        let tile = map.get_tile(6).unwrap();
+        assert_eq!(tile.top_left, Vec2::new(0.5, 0.0));
+        assert_eq!(tile.bottom_right, Vec2::new(1.0, 0.5));
+    }
+
+    #[test]
+    fn tileset_accessors_of_atlas_and_collection_tilesets() {
+        let map = parse(&map_xml(
+            2,
+            2,
+            "",
+            r#"<tileset firstgid="5" source="collection.tsx"/>"#,
+        ));
+
+        let atlas = &map.tilesets[0];
+        assert_eq!(atlas.tile_count(), 4);
+        assert_eq!((atlas.columns, atlas.rows()), (2, 2));
+        assert_eq!(atlas.tile(3).unwrap().top_left, Vec2::new(0.5, 0.5));
+        assert!(atlas.tile(4).is_none());
+
+        // the tiles of a collection have their own images, and their ids can have gaps
+        let collection = &map.tilesets[1];
+        assert_eq!(collection.tile_count(), 2);
+        assert_eq!((collection.columns, collection.rows()), (0, 0));
+        assert!(collection.tile(0).is_some());
+        assert!(collection.tile(1).is_none());
+        let image = collection.tile(3).unwrap().image.as_ref().unwrap();
+        assert_eq!(image.path(), Some(fixtures().join("blue.png").as_path()));
+    }
+
+    #[test]
+    fn tile_data_that_doesnt_fill_the_layer_is_an_error() {
+        // three tiles for a 2x2 layer, as csv and as base64
+        for data in [
+            r#"<data encoding="csv">1,2,3</data>"#,
+            r#"<data encoding="base64">AQAAAAIAAAADAAAA</data>"#,
+        ] {
+            let error = try_parse(&map_xml(
+                2,
+                2,
+                "",
+                &format!(
+                    r#"<layer id="1" name="short" width="2" height="2">{}</layer>"#,
+                    data
+                ),
+            ))
+            .err()
+            .expect("short data should be rejected");
+            let message = format!("{:#}", error);
+            assert!(
+                message.contains("invalid data in layer `short`"),
+                "{}",
+                message
+            );
+            assert!(
+                message.contains("found 3 tiles of data for an area of 2x2 tiles"),
+                "{}",
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_csv_tiles_are_an_error() {
+        let error = try_parse(&map_xml(
+            2,
+            2,
+            "",
+            r#"<layer id="1" name="l" width="2" height="2"><data encoding="csv">1,2,x3,4</data></layer>"#,
+        ))
+        .err()
+        .expect("malformed data should be rejected");
+        let message = format!("{:#}", error);
+        assert!(
+            message.contains("invalid csv tile `x3` at index 2"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn image_layer_repeat_flags_are_parsed() {
+        let map = parse(&map_xml(
+            1,
+            1,
+            "",
+            r#"<imagelayer id="1" name="sky" repeatx="1">
+                <image source="tiles.png" width="32" height="32"/>
+            </imagelayer>
+            <imagelayer id="2" name="ground" repeaty="1">
+                <image source="tiles.png" width="32" height="32"/>
+            </imagelayer>"#,
+        ));
+        let repeats: Vec<(bool, bool)> = map
+            .layers
+            .iter()
+            .map(|layer| match layer {
+                Layer::ImageLayer {
+                    repeat_x, repeat_y, ..
+                } => (*repeat_x, *repeat_y),
+                _ => panic!("expected an image layer"),
+            })
+            .collect();
+        assert_eq!(repeats, vec![(true, false), (false, true)]);
+    }
+
+    #[test]
+    fn columns_and_rows_fit_margin_and_spacing() {
+        // 4x3 tiles of 16x16, with a margin of 2 and a spacing of 1: 2 + 4 * 16 + 3 * 1 + 2 = 71 pixels wide
+        let map = parse(&map_xml(
+            1,
+            1,
+            "",
+            r#"<tileset firstgid="5" name="spaced" tilewidth="16" tileheight="16" spacing="1" margin="2">
+                <image source="tiles.png" width="71" height="54"/>
+            </tileset>"#,
+        ));
+
+        let tileset = &map.tilesets[1];
+        assert_eq!((tileset.columns, tileset.rows()), (4, 3));
+        assert_eq!(tileset.tile_count(), 12);
+        let last = tileset.tile(11).unwrap();
+        assert_eq!(last.top_left, Vec2::new(53.0 / 71.0, 36.0 / 54.0));
+        assert_eq!(last.bottom_right, Vec2::new(69.0 / 71.0, 52.0 / 54.0));
+    }
+
+    #[test]
+    fn map_versions_are_parsed() {
+        let map = parse(&map_xml(1, 1, r#"tiledversion="1.10.2""#, ""));
+        assert_eq!(map.version, "1.10");
+        assert_eq!(map.tiled_version, "1.10.2");
+
+        // maps saved by other tools may not have a tiled version
+        let map = parse(&map_xml(1, 1, "", ""));
+        assert_eq!(map.tiled_version, "");
+    }
+
+    #[test]
+    fn unknown_orientations_fall_back_to_orthogonal() {
+        let source = map_xml(
+            2,
+            1,
+            r#"staggeraxis="z" staggerindex="both""#,
+            r#"<layer id="1" name="l" width="2" height="1"><data encoding="csv">1,2</data></layer>"#,
+        )
+        .replace(r#"orientation="orthogonal""#, r#"orientation="octagonal""#)
+        .replace(r#"renderorder="right-down""#, r#"renderorder="spiral""#);
+        let map = parse(&source);
+
+        match map.tile_type {
+            TileType::Ortho {
+                width,
+                height,
+                render_order: RenderOrder::RightDown,
+            } => assert_eq!((width, height), (16, 16)),
+            _ => panic!("expected the orthogonal fallback"),
+        }
+        assert_eq!(map.layers.len(), 1);
+    }
+
+    #[test]
+    fn parse_errors_name_the_element_and_its_position() {
+        let error = try_parse(&map_xml(
+            4,
+            4,
+            "",
+            r#" <objectgroup id="1" name="zones">
+  <object id="1" x="0" y="0">
+   <polygon points="0,0 16,abc 16,16"/>
+  </object>
+ </objectgroup>"#,
+        ))
+        .err()
+        .expect("the malformed point should be rejected");
+        // the map template puts the tileset on lines 3 to 5
+        assert_eq!(
+            format!("{:#}", error),
+            "in <map> at line 2, column 1: in <objectgroup> at line 6, column 2: \
+             in <object> at line 7, column 3: in <polygon> at line 8, column 4: invalid point"
+        );
+    }
+
+    #[test]
+    fn instances_override_their_template() {
+        let map = parse(&map_xml(
+            4,
+            4,
+            "",
+            r#"<objectgroup id="1" name="objects">
+                <object id="1" template="chest.tx" name="big chest" type="treasure" x="8" y="24" rotation="90">
+                    <properties>
+                        <property name="loot" value="gold"/>
+                    </properties>
+                </object>
+                <object id="2" template="chest.tx" x="32" y="40"/>
+            </objectgroup>"#,
+        ));
+        let objects: Vec<&Object> = map.objects().map(|(_, object)| object).collect();
+
+        let instance = objects[0];
+        assert_eq!((instance.id, instance.name.as_str()), (1, "big chest"));
+        assert_eq!(instance.ty, "treasure");
+        assert_eq!(
+            (instance.x, instance.y, instance.rotation),
+            (8.0, 24.0, 90.0)
+        );
+        assert_eq!(instance.properties.get_str("loot"), Some("gold"));
+        // properties that the instance doesn't set come from the template
+        assert_eq!(instance.properties.get_bool("locked"), Some(true));
+        assert_eq!((instance.width, instance.height), (16.0, 8.0));
+
+        let plain = objects[1];
+        assert_eq!((plain.id, plain.name.as_str()), (2, "chest"));
+        assert_eq!(plain.ty, "container");
+        assert_eq!((plain.x, plain.y, plain.rotation), (32.0, 40.0, 45.0));
+        assert_eq!(plain.properties.get_str("loot"), Some("copper"));
+    }
+
+    #[test]
+    fn tile_types_are_parsed_from_type_and_class() {
+        let map = parse(&map_xml(
+            1,
+            1,
+            "",
+            r#"<tileset firstgid="5" source="typed.tsx"/>"#,
+        ));
+        let types: Vec<&str> = (5..9)
+            .map(|gid| map.get_tile(gid).unwrap().ty.as_str())
+            .collect();
+        assert_eq!(types, vec!["wall", "water", "", "door"]);
+        assert_eq!(
+            map.get_tile(8).unwrap().properties.get_bool("locked"),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn tint_and_opacity_are_kept_apart() {
+        let map = parse(&map_xml(
+            2,
+            1,
+            "",
+            r##"<layer id="1" name="ground" width="2" height="1" tintcolor="#ff0000" opacity="0.5">
+                <data encoding="csv">1,2</data>
+            </layer>
+            <group id="2" name="group" opacity="0.5">
+                <layer id="3" name="nested" width="2" height="1" tintcolor="#80ff0000" opacity="0.5">
+                    <data encoding="csv">1,2</data>
+                </layer>
+            </group>"##,
+        ));
+        let tint_and_opacity = |name: &str| match map.layer_by_name(name).unwrap() {
+            Layer::TileLayer { color, opacity, .. } => (*color, *opacity),
+            _ => panic!("expected a tile layer"),
+        };
+
+        assert_eq!(
+            tint_and_opacity("ground"),
+            (Vec4::new(1.0, 0.0, 0.0, 1.0), 0.5)
+        );
+        // the opacity of the group is combined with the opacity of the layer, the alpha of the tint is kept as is
+        let (color, opacity) = tint_and_opacity("nested");
+        assert!(color.abs_diff_eq(Vec4::new(1.0, 0.0, 0.0, 128.0 / 255.0), 1e-6));
+        assert_eq!(opacity, 0.25);
+    }
+
+    #[test]
+    fn object_layer_editor_colors_are_parsed_apart_from_the_tint() {
+        let map = parse(&map_xml(
+            1,
+            1,
+            "",
+            r##"<objectgroup id="1" name="colored" color="#ff8000" tintcolor="#0000ff"/>
+            <objectgroup id="2" name="plain"/>"##,
+        ));
+        let colors = |name: &str| match map.layer_by_name(name).unwrap() {
+            Layer::ObjectLayer {
+                color,
+                object_color,
+                ..
+            } => (*color, *object_color),
+            _ => panic!("expected an object layer"),
+        };
+
+        let (color, object_color) = colors("colored");
+        assert_eq!(color, Vec4::new(0.0, 0.0, 1.0, 1.0));
+        assert!(object_color.abs_diff_eq(Vec4::new(1.0, 128.0 / 255.0, 0.0, 1.0), 1e-6));
+        assert_eq!(colors("plain"), (Vec4::ONE, Layer::DEFAULT_OBJECT_COLOR));
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 /// A custom property
@@ -11,6 +12,10 @@ pub enum Property {
     /// A color in the format `[a, r, g, b]`
     Color([u8; 4]),
     File(String),
+    /// The id of another object in the map, `0` if no object is referenced
+    Object(u32),
+    /// A custom class with its own set of member properties
+    Class(HashMap<String, Property>),
 }
 
 impl Property {
@@ -63,4 +68,155 @@ impl Property {
             _ => None,
         }
     }
+
+    /// Return the referenced object id if this property is an object that references another
+    /// object, `None` otherwise.
+    pub fn as_object(&self) -> Option<u32> {
+        match *self {
+            Property::Object(x) if x != 0 => Some(x),
+            _ => None,
+        }
+    }
+
+    /// Return the member properties if this property is a class, `None` otherwise.
+    pub fn as_class(&self) -> Option<&HashMap<String, Property>> {
+        match self {
+            Property::Class(x) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+/// Typed lookups in a set of custom properties, like those of a `Map`, `Tile` or `Object`.
+/// Each lookup returns `None` if the property is absent, or if it can't be converted to the requested type.
+pub trait Properties {
+    /// Look up a property by its name.
+    fn property(&self, name: &str) -> Option<&Property>;
+
+    /// Look up a string property.
+    fn get_str(&self, name: &str) -> Option<&str> {
+        self.property(name).and_then(Property::as_str)
+    }
+
+    /// Look up an int property. Float properties are truncated.
+    fn get_int(&self, name: &str) -> Option<i32> {
+        self.property(name).and_then(Property::as_int)
+    }
+
+    /// Look up a float property. Int properties are converted.
+    fn get_float(&self, name: &str) -> Option<f64> {
+        self.property(name).and_then(Property::as_float)
+    }
+
+    /// Look up a bool property.
+    fn get_bool(&self, name: &str) -> Option<bool> {
+        self.property(name).and_then(Property::as_bool)
+    }
+
+    /// Look up a color property, in the format `[a, r, g, b]`.
+    fn get_color(&self, name: &str) -> Option<[u8; 4]> {
+        self.property(name).and_then(Property::as_color)
+    }
+
+    /// Look up a file property.
+    fn get_file(&self, name: &str) -> Option<PathBuf> {
+        self.property(name).and_then(Property::as_file)
+    }
+
+    /// Look up an object property that references another object.
+    fn get_object(&self, name: &str) -> Option<u32> {
+        self.property(name).and_then(Property::as_object)
+    }
+
+    /// Look up the members of a class property.
+    fn get_class(&self, name: &str) -> Option<&HashMap<String, Property>> {
+        self.property(name).and_then(Property::as_class)
+    }
+}
+
+impl Properties for HashMap<String, Property> {
+    fn property(&self, name: &str) -> Option<&Property> {
+        self.get(name)
+    }
+}
+
+/// The properties sorted by name.
+pub(crate) fn sorted_properties(properties: &HashMap<String, Property>) -> Vec<(&str, &Property)> {
+    let mut properties: Vec<_> = properties
+        .iter()
+        .map(|(key, value)| (key.as_str(), value))
+        .collect();
+    properties.sort_by(|a, b| a.0.cmp(b.0));
+    properties
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    #[test]
+    fn typed_lookups_convert_present_properties() {
+        let map = parse(
+            r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" renderorder="right-down" width="1" height="1" tilewidth="16" tileheight="16" infinite="0">
+ <properties>
+  <property name="title" value="Forest"/>
+  <property name="tint" type="color" value="#80ff0000"/>
+  <property name="music" type="file" value="forest.ogg"/>
+ </properties>
+ <tileset firstgid="1" name="tiles" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+  <image source="tiles.png" width="32" height="32"/>
+  <tile id="0">
+   <properties>
+    <property name="solid" type="bool" value="true"/>
+    <property name="friction" type="float" value="0.5"/>
+   </properties>
+  </tile>
+ </tileset>
+ <objectgroup id="1" name="objects">
+  <object id="1" x="0" y="0">
+   <properties>
+    <property name="hp" type="int" value="10"/>
+    <property name="target" type="object" value="1"/>
+   </properties>
+  </object>
+ </objectgroup>
+</map>"##,
+        );
+        assert_eq!(map.properties.get_str("title"), Some("Forest"));
+        assert_eq!(map.properties.get_color("tint"), Some([0x80, 0xff, 0, 0]));
+        assert_eq!(
+            map.properties.get_file("music"),
+            Some(PathBuf::from("forest.ogg"))
+        );
+
+        let tile = map.get_tile(1).unwrap();
+        assert_eq!(tile.properties.get_bool("solid"), Some(true));
+        assert_eq!(tile.properties.get_float("friction"), Some(0.5));
+
+        let (_, object) = map.objects().next().unwrap();
+        assert_eq!(object.properties.get_int("hp"), Some(10));
+        // ints convert to floats
+        assert_eq!(object.properties.get_float("hp"), Some(10.0));
+        assert_eq!(object.properties.get_object("target"), Some(1));
+    }
+
+    #[test]
+    fn typed_lookups_of_absent_or_mismatched_properties_are_none() {
+        let mut properties = HashMap::new();
+        properties.insert("name".to_string(), Property::String("slime".to_string()));
+        properties.insert("hp".to_string(), Property::Int(3));
+        properties.insert("hostile".to_string(), Property::Bool(true));
+
+        assert_eq!(properties.get_str("missing"), None);
+        assert_eq!(properties.get_int("missing"), None);
+        assert_eq!(properties.get_int("name"), None);
+        assert_eq!(properties.get_bool("hp"), None);
+        assert_eq!(properties.get_str("hostile"), None);
+        assert_eq!(properties.get_color("name"), None);
+        assert_eq!(properties.get_file("hp"), None);
+        assert_eq!(properties.get_object("hp"), None);
+        assert!(properties.get_class("name").is_none());
+    }
 }
@@ -0,0 +1,275 @@
+use bevy_math::Vec2;
+#[cfg(feature = "render")]
+use bevy_render::{
+    mesh::{Indices, Mesh},
+    pipeline::PrimitiveTopology,
+};
+
+use super::Shape;
+
+impl Shape {
+    /// Triangulate the area enclosed by a closed shape using ear clipping.
+    /// Returns the vertices of the shape and the indices of the triangles, which are wound counter clockwise
+    ///  in the coordinate system of the points. Open shapes and shapes with less than three points have no area,
+    ///  so for those the result is empty.
+    pub fn triangulate(&self) -> (Vec<[f32; 3]>, Vec<u16>) {
+        if !self.closed || self.points.len() < 3 {
+            return (Vec::new(), Vec::new());
+        }
+
+        let vertices = self.points.iter().map(|p| [p.x, p.y, 0.0]).collect();
+
+        // work on a list of remaining points in counter clockwise order
+        let mut remaining: Vec<usize> = (0..self.points.len()).collect();
+        if signed_area(&self.points) < 0.0 {
+            remaining.reverse();
+        }
+
+        let mut indices = Vec::with_capacity((self.points.len() - 2) * 3);
+        let mut i = 0;
+        let mut attempts = 0;
+        while remaining.len() > 3 {
+            let n = remaining.len();
+            let (a, b, c) = (
+                remaining[(i + n - 1) % n],
+                remaining[i % n],
+                remaining[(i + 1) % n],
+            );
+            if cross(self.points[a], self.points[b], self.points[c]) == 0.0 {
+                // collinear or duplicate points don't enclose any area, so they are dropped
+                remaining.remove(i % n);
+                attempts = 0;
+            } else if self.is_ear(a, b, c, &remaining) {
+                indices.extend_from_slice(&[a as u16, b as u16, c as u16]);
+                remaining.remove(i % n);
+                attempts = 0;
+            } else {
+                i += 1;
+                attempts += 1;
+                // no ears left, the shape is self intersecting or degenerate
+                if attempts > n {
+                    break;
+                }
+            }
+        }
+        if remaining.len() == 3 {
+            let (a, b, c) = (remaining[0], remaining[1], remaining[2]);
+            if cross(self.points[a], self.points[b], self.points[c]) > 0.0 {
+                indices.extend_from_slice(&[a as u16, b as u16, c as u16]);
+            }
+        }
+
+        (vertices, indices)
+    }
+
+    fn is_ear(&self, a: usize, b: usize, c: usize, remaining: &[usize]) -> bool {
+        let (pa, pb, pc) = (self.points[a], self.points[b], self.points[c]);
+        if cross(pa, pb, pc) <= 0.0 {
+            return false;
+        }
+        remaining
+            .iter()
+            .filter(|&&p| p != a && p != b && p != c)
+            .all(|&p| !in_triangle(self.points[p], pa, pb, pc))
+    }
+}
+
+fn signed_area(points: &[Vec2]) -> f32 {
+    let mut area = 0.0;
+    for (i, a) in points.iter().enumerate() {
+        let b = points[(i + 1) % points.len()];
+        area += a.x * b.y - b.x * a.y;
+    }
+    area * 0.5
+}
+
+fn cross(a: Vec2, b: Vec2, c: Vec2) -> f32 {
+    (b - a).perp_dot(c - a)
+}
+
+fn in_triangle(p: Vec2, a: Vec2, b: Vec2, c: Vec2) -> bool {
+    cross(a, b, p) >= 0.0 && cross(b, c, p) >= 0.0 && cross(c, a, p) >= 0.0
+}
+
+#[cfg(feature = "render")]
+impl Shape {
+    /// Build a line strip mesh that traces the outline of the shape, connecting the last point to the first
+    ///  for closed shapes. Pass the same scale as the one given to `TmxPlugin::scale` to get a mesh that matches
+    ///  the transform of the spawned object entity. Like `Object::to_mesh`, the mesh can be drawn by the sprite pipeline.
+    pub fn outline_mesh(&self, scale: Vec2) -> Mesh {
+        let vertices: Vec<[f32; 3]> = self
+            .points
+            .iter()
+            .map(|p| [p.x * scale.x, p.y * scale.y, 0.0])
+            .collect();
+        let mut indices: Vec<u16> = (0..vertices.len() as u16).collect();
+        if self.closed && vertices.len() > 2 {
+            indices.push(0);
+        }
+
+        let mut mesh = Mesh::new(PrimitiveTopology::LineStrip);
+        mesh.set_attribute(
+            Mesh::ATTRIBUTE_NORMAL,
+            vec![[0.0, 0.0, 1.0]; vertices.len()],
+        );
+        mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0.0, 0.0]; vertices.len()]);
+        mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, vertices);
+        mesh.set_indices(Some(Indices::U16(indices)));
+        mesh
+    }
+}
+
+#[cfg(feature = "render")]
+impl super::Object {
+    /// Build a mesh that fills the shape of this object, for example to visualize zones.
+    /// Pass the same scale as the one given to `TmxPlugin::scale` to get a mesh that matches the transform
+    ///  of the spawned object entity. The mesh has the attributes required by the sprite pipeline,
+    ///  so it can be spawned with a `ColorMaterial` and a `Sprite` with a size of one.
+    /// Objects without an area, like points and polylines, result in an empty mesh.
+    pub fn to_mesh(&self, scale: Vec2) -> Mesh {
+        let (vertices, indices) = self.shape.triangulate();
+        let vertices: Vec<[f32; 3]> = vertices
+            .into_iter()
+            .map(|[x, y, z]| [x * scale.x, y * scale.y, z])
+            .collect();
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_attribute(
+            Mesh::ATTRIBUTE_NORMAL,
+            vec![[0.0, 0.0, 1.0]; vertices.len()],
+        );
+        mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0.0, 0.0]; vertices.len()]);
+        mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, vertices);
+        mesh.set_indices(Some(Indices::U16(indices)));
+        mesh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn polygon(points: &[(f32, f32)]) -> Shape {
+        Shape {
+            points: points.iter().map(|&(x, y)| Vec2::new(x, y)).collect(),
+            closed: true,
+        }
+    }
+
+    /// The triangles of the triangulated shape, asserting that none of them are degenerate or wound clockwise.
+    fn triangles(shape: &Shape) -> Vec<[Vec2; 3]> {
+        let (vertices, indices) = shape.triangulate();
+        assert_eq!(vertices.len(), shape.points.len());
+        assert_eq!(indices.len() % 3, 0);
+        indices
+            .chunks(3)
+            .map(|triangle| {
+                let [a, b, c] = [0, 1, 2].map(|i| {
+                    let [x, y, _] = vertices[triangle[i] as usize];
+                    Vec2::new(x, y)
+                });
+                assert!(cross(a, b, c) > 0.0, "degenerate triangle {:?}", triangle);
+                [a, b, c]
+            })
+            .collect()
+    }
+
+    fn area(triangles: &[[Vec2; 3]]) -> f32 {
+        triangles
+            .iter()
+            .map(|&[a, b, c]| cross(a, b, c) * 0.5)
+            .sum()
+    }
+
+    #[test]
+    fn convex_quads_become_two_triangles() {
+        let quad = polygon(&[(0.0, 0.0), (4.0, 0.0), (4.0, 2.0), (0.0, 2.0)]);
+        assert_eq!(triangles(&quad).len(), 2);
+        assert_eq!(area(&triangles(&quad)), 8.0);
+
+        // the winding of the points doesn't matter
+        let reversed = polygon(&[(0.0, 2.0), (4.0, 2.0), (4.0, 0.0), (0.0, 0.0)]);
+        assert_eq!(triangles(&reversed).len(), 2);
+        assert_eq!(area(&triangles(&reversed)), 8.0);
+    }
+
+    #[test]
+    fn concave_polygons_are_covered_without_leaving_the_shape() {
+        // an L shape, the notch at the top right is outside of the polygon
+        let l = polygon(&[
+            (0.0, 0.0),
+            (4.0, 0.0),
+            (4.0, 2.0),
+            (2.0, 2.0),
+            (2.0, 4.0),
+            (0.0, 4.0),
+        ]);
+        let triangles = triangles(&l);
+        assert_eq!(triangles.len(), 4);
+        assert_eq!(area(&triangles), 12.0);
+        for &[a, b, c] in triangles.iter() {
+            let center = (a + b + c) / 3.0;
+            assert!(
+                !(center.x > 2.0 && center.y > 2.0),
+                "triangle {:?} covers the notch",
+                [a, b, c]
+            );
+        }
+    }
+
+    #[test]
+    fn collinear_points_dont_produce_degenerate_triangles() {
+        // a square with extra points halfway along two of its edges, and a duplicated corner
+        let square = polygon(&[
+            (0.0, 0.0),
+            (1.0, 0.0),
+            (2.0, 0.0),
+            (2.0, 2.0),
+            (2.0, 2.0),
+            (0.0, 2.0),
+            (0.0, 1.0),
+        ]);
+        let triangles = triangles(&square);
+        assert_eq!(area(&triangles), 4.0);
+
+        // a line has no area
+        let line = polygon(&[(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)]);
+        assert!(line.triangulate().1.is_empty());
+    }
+
+    #[test]
+    fn open_shapes_have_no_triangles() {
+        let mut polyline = polygon(&[(0.0, 0.0), (4.0, 0.0), (4.0, 2.0)]);
+        polyline.closed = false;
+        assert_eq!(polyline.triangulate(), (Vec::new(), Vec::new()));
+    }
+
+    #[cfg(feature = "render")]
+    #[test]
+    fn polygon_outlines_close_the_loop() {
+        use bevy_render::mesh::VertexAttributeValues;
+
+        let triangle = polygon(&[(0.0, 0.0), (4.0, 0.0), (4.0, 2.0)]);
+        let mesh = triangle.outline_mesh(Vec2::new(2.0, -2.0));
+        assert_eq!(mesh.primitive_topology(), PrimitiveTopology::LineStrip);
+        match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+            Some(VertexAttributeValues::Float3(positions)) => assert_eq!(
+                positions,
+                &vec![[0.0, 0.0, 0.0], [8.0, 0.0, 0.0], [8.0, -4.0, 0.0]]
+            ),
+            _ => panic!("mesh without positions"),
+        }
+        match mesh.indices() {
+            Some(Indices::U16(indices)) => assert_eq!(indices, &vec![0, 1, 2, 0]),
+            _ => panic!("mesh without 16 bit indices"),
+        }
+
+        // polylines stay open
+        let mut polyline = triangle;
+        polyline.closed = false;
+        match polyline.outline_mesh(Vec2::ONE).indices() {
+            Some(Indices::U16(indices)) => assert_eq!(indices, &vec![0, 1, 2]),
+            _ => panic!("mesh without 16 bit indices"),
+        }
+    }
+}
@@ -1,20 +1,26 @@
-use std::hash::Hasher;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::*;
 use async_mutex::Mutex;
-#[cfg(feature = "plugin")]
+#[cfg(feature = "render")]
 use bevy_asset::{Handle, LoadContext, LoadedAsset};
-#[cfg(feature = "plugin")]
+#[cfg(feature = "render")]
 use bevy_render::texture::{Extent3d, Texture as BevyTexture, TextureDimension, TextureFormat};
-use image::{load_from_memory, GenericImage, RgbaImage};
+#[cfg(feature = "render")]
+use bevy_tasks::TaskPool;
+#[cfg(feature = "image")]
+use image::codecs::png::PngEncoder;
+#[cfg(feature = "image")]
+use image::{load_from_memory, ColorType, RgbaImage};
 
 /// A shared image
 #[derive(Clone)]
 pub struct Texture {
     data: Arc<Mutex<Inner>>,
     label: Arc<str>,
+    path: Option<PathBuf>,
+    transparent_color: Option<[u8; 3]>,
     width: u32,
     height: u32,
 }
@@ -22,62 +28,144 @@ pub struct Texture {
 enum Inner {
     Defined {
         path: PathBuf,
+        /// The contents of the file, if they were already read to find the size of the image.
+        #[cfg_attr(not(feature = "render"), allow(dead_code))]
+        bytes: Option<Arc<[u8]>>,
     },
-    Decoded {
-        buffer: RgbaImage,
-    },
-    #[cfg(feature = "plugin")]
-    Loaded {
-        handle: Handle<BevyTexture>,
-    },
+    #[cfg(feature = "image")]
+    Decoded { buffer: RgbaImage },
+    /// An embedded image that can't be decoded without the `image` feature.
+    #[cfg(not(feature = "image"))]
+    Encoded { bytes: Arc<[u8]> },
+    #[cfg(feature = "render")]
+    Loaded { handle: Handle<BevyTexture> },
 }
 
+#[cfg(feature = "render")]
 pub(crate) struct TexturePtr(Arc<str>);
 
 impl Texture {
-    pub(crate) fn from_bytes(data: &[u8], label: impl Into<Arc<str>>) -> Result<Self> {
-        let buffer = load_from_memory(data)?.to_rgba8();
+    #[cfg(feature = "image")]
+    pub(crate) fn from_bytes(
+        data: &[u8],
+        label: impl Into<Arc<str>>,
+        transparent_color: Option<[u8; 3]>,
+    ) -> Result<Self> {
+        let mut buffer = load_from_memory(data)?.to_rgba8();
+        if let Some(color) = transparent_color {
+            apply_transparent_color(&mut buffer, color);
+        }
         let width = buffer.width();
         let height = buffer.height();
         Ok(Texture {
             data: Arc::new(Mutex::new(Inner::Decoded { buffer })),
             label: label.into(),
+            path: None,
+            transparent_color,
             width,
             height,
         })
     }
 
-    pub(crate) fn from_path(path: PathBuf) -> Self {
-        let label = format!("{}", path.display()).into();
+    #[cfg(not(feature = "image"))]
+    pub(crate) fn from_bytes(
+        data: &[u8],
+        label: impl Into<Arc<str>>,
+        transparent_color: Option<[u8; 3]>,
+    ) -> Result<Self> {
+        Ok(Texture {
+            data: Arc::new(Mutex::new(Inner::Encoded {
+                bytes: Arc::from(data),
+            })),
+            label: label.into(),
+            path: None,
+            transparent_color,
+            width: 0,
+            height: 0,
+        })
+    }
+
+    pub(crate) fn from_path(path: PathBuf, transparent_color: Option<[u8; 3]>) -> Self {
+        let label = match transparent_color {
+            // the same image without a transparent color is a different texture
+            Some([r, g, b]) => format!("{}#{:02x}{:02x}{:02x}", path.display(), r, g, b),
+            None => format!("{}", path.display()),
+        };
         Texture {
-            data: Arc::new(Mutex::new(Inner::Defined { path })),
-            label,
+            data: Arc::new(Mutex::new(Inner::Defined {
+                path: path.clone(),
+                bytes: None,
+            })),
+            label: label.into(),
+            path: Some(path),
+            transparent_color,
             width: 0,
             height: 0,
         }
     }
 
+    /// Find the size of an image file from the header of its contents `bytes`.
+    /// The contents are kept, so the file doesn't have to be read again to decode the image.
+    #[cfg(feature = "image")]
+    pub(crate) async fn with_contents(&self, bytes: Vec<u8>) -> Result<Self> {
+        let (width, height) = image::io::Reader::new(std::io::Cursor::new(&bytes))
+            .with_guessed_format()?
+            .into_dimensions()?;
+        let path = match &*self.data.lock().await {
+            Inner::Defined { path, .. } => path.clone(),
+            _ => return Ok(self.clone()),
+        };
+        Ok(Texture {
+            data: Arc::new(Mutex::new(Inner::Defined {
+                path,
+                bytes: Some(bytes.into()),
+            })),
+            width,
+            height,
+            ..self.clone()
+        })
+    }
+
     pub(crate) async fn resize(&self, width: u32, height: u32) -> Result<Self> {
-        if width != self.width && height != self.height {
+        if (width, height) != (self.width, self.height) {
             let data = self.data.lock().await;
             match &*data {
-                Inner::Defined { path } => Ok(Texture {
-                    data: Arc::new(Mutex::new(Inner::Defined { path: path.clone() })),
+                Inner::Defined { path, bytes } => Ok(Texture {
+                    data: Arc::new(Mutex::new(Inner::Defined {
+                        path: path.clone(),
+                        bytes: bytes.clone(),
+                    })),
                     label: format!("{}#{}x{}", self.label, width, height).into(),
+                    path: self.path.clone(),
+                    transparent_color: self.transparent_color,
                     width,
                     height,
                 }),
+                #[cfg(feature = "image")]
                 Inner::Decoded { buffer } => {
                     let mut new_image: RgbaImage = RgbaImage::new(width, height);
-                    new_image.copy_from(buffer, 0, 0)?;
+                    image::imageops::replace(&mut new_image, buffer, 0, 0);
                     Ok(Texture {
                         data: Arc::new(Mutex::new(Inner::Decoded { buffer: new_image })),
                         label: format!("{}#{}x{}", self.label, width, height).into(),
+                        path: None,
+                        transparent_color: self.transparent_color,
                         width,
                         height,
                     })
                 }
-                #[cfg(feature = "plugin")]
+                #[cfg(not(feature = "image"))]
+                Inner::Encoded { bytes } => Ok(Texture {
+                    data: Arc::new(Mutex::new(Inner::Encoded {
+                        bytes: bytes.clone(),
+                    })),
+                    label: format!("{}#{}x{}", self.label, width, height).into(),
+                    path: None,
+                    transparent_color: self.transparent_color,
+                    width,
+                    height,
+                }),
+                #[cfg(feature = "render")]
                 Inner::Loaded { .. } => unreachable!(),
             }
         } else {
@@ -85,7 +173,7 @@ impl Texture {
         }
     }
 
-    #[cfg(feature = "plugin")]
+    #[cfg(feature = "render")]
     pub(crate) async fn load(
         &self,
         load_context: &mut LoadContext<'_>,
@@ -93,15 +181,12 @@ impl Texture {
         let mut data = self.data.lock().await;
 
         let handle = match &mut *data {
-            Inner::Defined { path } => {
-                let mut buffer =
-                    load_from_memory(load_context.read_asset_bytes(path).await?.as_slice())?
-                        .to_rgba8();
-                if self.width > 0 && self.height > 0 {
-                    let mut new_image: RgbaImage = RgbaImage::new(self.width, self.height);
-                    new_image.copy_from(&buffer, 0, 0)?;
-                    buffer = new_image;
-                }
+            Inner::Defined { path, bytes } => {
+                let bytes = match bytes.take() {
+                    Some(bytes) => bytes,
+                    None => load_context.read_asset_bytes(path).await?.into(),
+                };
+                let buffer = decode(&bytes, self.transparent_color, self.width, self.height)?;
 
                 load_context.set_labeled_asset(
                     self.label.as_ref(),
@@ -121,8 +206,8 @@ impl Texture {
                 self.label.as_ref(),
                 LoadedAsset::new(BevyTexture::new(
                     Extent3d {
-                        width: self.width,
-                        height: self.height,
+                        width: buffer.width(),
+                        height: buffer.height(),
                         depth: 1,
                     },
                     TextureDimension::D2,
@@ -140,6 +225,145 @@ impl Texture {
         Ok(handle)
     }
 
+    /// Use a texture that was already loaded by another asset, instead of loading this image again.
+    #[cfg(feature = "render")]
+    pub(crate) async fn load_from(&self, handle: Handle<BevyTexture>) -> Handle<BevyTexture> {
+        *self.data.lock().await = Inner::Loaded {
+            handle: handle.clone(),
+        };
+        handle
+    }
+
+    /// Decode all images that are not decoded yet on the task pool, so that multiple images decode at once.
+    /// Images that are skipped here are still decoded when they are loaded.
+    #[cfg(feature = "render")]
+    pub(crate) async fn decode_all<'t>(
+        textures: impl IntoIterator<Item = &'t Texture>,
+        load_context: &LoadContext<'_>,
+        task_pool: &TaskPool,
+    ) -> Result<()> {
+        let mut files = Vec::new();
+        let mut labels = std::collections::HashSet::new();
+        for texture in textures {
+            // images with the same label are only loaded once by the scene
+            if !labels.insert(texture.label.clone()) {
+                continue;
+            }
+            let (path, bytes) = match &*texture.data.lock().await {
+                Inner::Defined { path, bytes } => (path.clone(), bytes.clone()),
+                _ => continue,
+            };
+            let bytes: Arc<[u8]> = match bytes {
+                Some(bytes) => bytes,
+                None => load_context.read_asset_bytes(&path).await?.into(),
+            };
+            files.push((texture, bytes));
+        }
+
+        Texture::decode_files(files, task_pool).await
+    }
+
+    /// Decode the contents of image files on the task pool, all at once.
+    #[cfg(feature = "render")]
+    async fn decode_files(files: Vec<(&Texture, Arc<[u8]>)>, task_pool: &TaskPool) -> Result<()> {
+        let mut pending = Vec::new();
+        for (texture, bytes) in files {
+            let (transparent_color, width, height) =
+                (texture.transparent_color, texture.width, texture.height);
+            let task =
+                task_pool.spawn(async move { decode(&bytes, transparent_color, width, height) });
+            pending.push((texture, task));
+        }
+
+        for (texture, task) in pending {
+            let buffer = task.await?;
+            let mut data = texture.data.lock().await;
+            if let Inner::Defined { .. } = &*data {
+                *data = Inner::Decoded { buffer };
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copy the image without sharing its loaded state with the original, so it can be loaded into another asset.
+    #[cfg(feature = "render")]
+    pub(crate) async fn detached(&self) -> Self {
+        let data = self.data.lock().await;
+        let inner = match &*data {
+            Inner::Defined { path, bytes } => Inner::Defined {
+                path: path.clone(),
+                bytes: bytes.clone(),
+            },
+            Inner::Decoded { buffer } => Inner::Decoded {
+                buffer: buffer.clone(),
+            },
+            #[cfg(feature = "render")]
+            Inner::Loaded { .. } => match self.path.as_ref() {
+                Some(path) => Inner::Defined {
+                    path: path.clone(),
+                    bytes: None,
+                },
+                None => unreachable!(),
+            },
+        };
+        Texture {
+            data: Arc::new(Mutex::new(inner)),
+            ..self.clone()
+        }
+    }
+
+    /// Encode an embedded image as png.
+    /// Fails if the image was loaded from a file, or if it was already handed over to the asset server.
+    /// Without the `image` feature, the embedded image is written as it was read.
+    pub(crate) fn encode_png(&self) -> Result<Vec<u8>> {
+        let data = self
+            .data
+            .try_lock()
+            .ok_or_else(|| anyhow!("image is in use"))?;
+        match &*data {
+            #[cfg(not(feature = "image"))]
+            Inner::Encoded { bytes } => Ok(bytes.to_vec()),
+            #[cfg(feature = "image")]
+            Inner::Decoded { buffer } => {
+                let mut bytes = Vec::new();
+                PngEncoder::new(&mut bytes).encode(
+                    buffer.as_raw(),
+                    buffer.width(),
+                    buffer.height(),
+                    ColorType::Rgba8,
+                )?;
+                Ok(bytes)
+            }
+            _ => bail!("image data is not available"),
+        }
+    }
+
+    /// The handle of the texture that this image was loaded into, as a labeled asset of the map or tileset that uses it.
+    /// `None` if the image wasn't used, or if it's still being loaded.
+    #[cfg(feature = "render")]
+    pub fn handle(&self) -> Option<Handle<BevyTexture>> {
+        match &*self.data.try_lock()? {
+            Inner::Loaded { handle } => Some(handle.clone()),
+            _ => None,
+        }
+    }
+
+    /// The path that the image was loaded from, relative to the asset directory.
+    /// Embedded images have no path.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// The color that is treated as transparent in this image, in the format `[r, g, b]`.
+    pub(crate) fn transparent_color(&self) -> Option<[u8; 3]> {
+        self.transparent_color
+    }
+
+    pub(crate) fn label(&self) -> &str {
+        self.label.as_ref()
+    }
+
     pub(crate) fn width(&self) -> u32 {
         self.width
     }
@@ -149,22 +373,162 @@ impl Texture {
     }
 }
 
+/// Decode an image file, optionally resizing it to `width` by `height`.
+/// The image is padded with transparent pixels when it's smaller than that size and cropped when it's larger.
+#[cfg(feature = "render")]
+fn decode(
+    data: &[u8],
+    transparent_color: Option<[u8; 3]>,
+    width: u32,
+    height: u32,
+) -> Result<RgbaImage> {
+    let mut buffer = load_from_memory(data)?.to_rgba8();
+    if let Some(color) = transparent_color {
+        apply_transparent_color(&mut buffer, color);
+    }
+    if width > 0 && height > 0 && buffer.dimensions() != (width, height) {
+        let mut new_image: RgbaImage = RgbaImage::new(width, height);
+        image::imageops::replace(&mut new_image, &buffer, 0, 0);
+        buffer = new_image;
+    }
+    Ok(buffer)
+}
+
+/// Make all pixels that match `color` fully transparent.
+#[cfg(feature = "image")]
+fn apply_transparent_color(buffer: &mut RgbaImage, [r, g, b]: [u8; 3]) {
+    for pixel in buffer.pixels_mut() {
+        if pixel.0[0] == r && pixel.0[1] == g && pixel.0[2] == b {
+            pixel.0[3] = 0;
+        }
+    }
+}
+
+#[cfg(feature = "render")]
 impl From<&Texture> for TexturePtr {
     fn from(image: &Texture) -> Self {
         Self(image.label.clone())
     }
 }
 
+#[cfg(feature = "render")]
 impl std::hash::Hash for TexturePtr {
-    fn hash<H: Hasher>(&self, state: &mut H) {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         self.0.hash(state)
     }
 }
 
+#[cfg(feature = "render")]
 impl std::cmp::PartialEq for TexturePtr {
     fn eq(&self, other: &Self) -> bool {
         self.0.eq(&other.0)
     }
 }
 
+#[cfg(feature = "render")]
 impl std::cmp::Eq for TexturePtr {}
+
+#[cfg(all(test, feature = "image"))]
+mod tests {
+    use super::*;
+    use crate::test_util::fixtures;
+
+    #[test]
+    fn pixels_of_the_transparent_color_become_transparent() {
+        let bytes = std::fs::read(fixtures().join("magenta.png")).unwrap();
+        let texture = Texture::from_bytes(&bytes, "magenta", Some([0xff, 0x00, 0xff])).unwrap();
+        let data = texture.data.try_lock().unwrap();
+        let buffer = match &*data {
+            Inner::Decoded { buffer } => buffer,
+            _ => panic!("expected a decoded image"),
+        };
+        for (x, y, pixel) in buffer.enumerate_pixels() {
+            if (x, y) == (1, 1) || (x, y) == (2, 2) {
+                assert_eq!(pixel.0, [0, 255, 0, 255]);
+            } else {
+                assert_eq!(pixel.0[3], 0, "pixel ({}, {}) is not transparent", x, y);
+            }
+        }
+    }
+
+    /// A png image of `size` by `size` pixels of noise, which takes a while to decode.
+    #[cfg(feature = "render")]
+    fn noise_png(size: u32, seed: u32) -> Vec<u8> {
+        let mut state = seed.wrapping_mul(2654435761).max(1);
+        let pixels: Vec<u8> = (0..size * size * 4)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        let mut bytes = Vec::new();
+        PngEncoder::new(&mut bytes)
+            .encode(&pixels, size, size, ColorType::Rgba8)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    #[cfg(feature = "render")]
+    fn images_are_decoded_in_parallel() {
+        use std::time::Instant;
+
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get().min(4));
+        let task_pool = bevy_tasks::TaskPoolBuilder::new()
+            .num_threads(threads)
+            .build();
+        let files: Vec<Arc<[u8]>> = (0..4).map(|i| noise_png(512, i).into()).collect();
+        let textures: Vec<Texture> = (0..files.len())
+            .map(|i| Texture::from_path(PathBuf::from(format!("{}.png", i)), None))
+            .collect();
+
+        let start = Instant::now();
+        let serial: Vec<RgbaImage> = files
+            .iter()
+            .map(|bytes| decode(bytes, None, 0, 0).unwrap())
+            .collect();
+        let serial_time = start.elapsed();
+
+        let start = Instant::now();
+        futures_lite::future::block_on(Texture::decode_files(
+            textures.iter().zip(files.iter().cloned()).collect(),
+            &task_pool,
+        ))
+        .unwrap();
+        let parallel_time = start.elapsed();
+
+        for (texture, serial) in textures.iter().zip(serial) {
+            match &*texture.data.try_lock().unwrap() {
+                Inner::Decoded { buffer } => assert!(*buffer == serial),
+                _ => panic!("expected a decoded image"),
+            }
+        }
+        // decoding at once only saves time if there are multiple cores to decode on
+        if threads > 1 {
+            assert!(
+                parallel_time < serial_time,
+                "decoding in parallel took {:?}, decoding one after another took {:?}",
+                parallel_time,
+                serial_time
+            );
+        }
+    }
+
+    #[cfg(feature = "webp")]
+    #[test]
+    fn webp_images_are_decoded() {
+        let bytes = std::fs::read(fixtures().join("lossy.webp")).unwrap();
+        let texture = Texture::from_bytes(&bytes, "lossy", None).unwrap();
+        assert_eq!((texture.width(), texture.height()), (16, 16));
+        let data = texture.data.try_lock().unwrap();
+        let buffer = match &*data {
+            Inner::Decoded { buffer } => buffer,
+            _ => panic!("expected a decoded image"),
+        };
+        // lossy webp images have no alpha channel, and this one isn't a single flat color
+        assert!(buffer.pixels().all(|pixel| pixel.0[3] == 255));
+        assert!(buffer.pixels().any(|pixel| pixel != buffer.get_pixel(0, 0)));
+    }
+}
@@ -1,3 +1,5 @@
+use smallvec::{smallvec, SmallVec};
+
 use super::*;
 
 /// Tiled has three different rendering types: orthographic, isometric and hexagonal. They are represented through this enum.
@@ -9,7 +11,7 @@ pub enum TileType {
         width: u32,
         /// Height in pixels of a single tile
         height: u32,
-        /// RenderOrder of tiles. Todo.
+        /// RenderOrder of tiles.
         render_order: RenderOrder,
     },
     /// Isometric rendering mode
@@ -24,7 +26,7 @@ pub enum TileType {
         stagger_odd: bool,
         /// When rendering staggered, whether to stagger the x or y axis.
         stagger_y: bool,
-        /// RenderOrder of tiles. Todo.
+        /// RenderOrder of tiles.
         render_order: RenderOrder,
     },
     /// Hexagonal rendering mode
@@ -39,7 +41,7 @@ pub enum TileType {
         stagger_y: bool,
         /// Width or height in pixels at the flat side of a hex tile, depending on `stagger_y`.  
         side_length: u32,
-        /// RenderOrder of tiles. Todo.
+        /// RenderOrder of tiles.
         render_order: RenderOrder,
     },
 }
@@ -64,7 +66,7 @@ impl TileType {
             } => {
                 if stagger {
                     if stagger_y {
-                        let rx = if (y % 2 == 1) == stagger_odd {
+                        let rx = if (mod2(y, 2) == 1) == stagger_odd {
                             x * width as i32 + width as i32 / 2
                         } else {
                             x * width as i32
@@ -73,7 +75,7 @@ impl TileType {
                         (rx, ry)
                     } else {
                         let rx = (width as i32 * x) / 2;
-                        let ry = if (x % 2 == 1) == stagger_odd {
+                        let ry = if (mod2(x, 2) == 1) == stagger_odd {
                             y * height as i32 + height as i32 / 2
                         } else {
                             y * height as i32
@@ -96,16 +98,17 @@ impl TileType {
                 ..
             } => {
                 if stagger_y {
-                    let rx = if (y % 2 == 1) == stagger_odd {
+                    let rx = if (mod2(y, 2) == 1) == stagger_odd {
                         x * width as i32 + width as i32 / 2
                     } else {
                         x * width as i32
                     };
-                    let ry = ((height + side_length) / 2 - 1) as i32 * y;
+                    let row_h = (height as i32 - side_length as i32) / 2 + side_length as i32;
+                    let ry = row_h * y;
                     (rx, ry)
                 } else {
                     let rx = ((width + side_length) / 2 - 1) as i32 * x;
-                    let ry = if (x % 2 == 1) == stagger_odd {
+                    let ry = if (mod2(x, 2) == 1) == stagger_odd {
                         y * height as i32 + height as i32 / 2
                     } else {
                         y * height as i32
@@ -123,7 +126,9 @@ impl TileType {
     /// * `y` - The vertical pixel coordinate
     pub fn pos_to_coord(&self, layer_height: i32, x: i32, y: i32) -> (i32, i32) {
         match *self {
-            TileType::Ortho { width, height, .. } => (x / width as i32, y / height as i32),
+            TileType::Ortho { width, height, .. } => {
+                (x.div_euclid(width as i32), y.div_euclid(height as i32))
+            }
 
             TileType::Isometric {
                 width,
@@ -175,10 +180,12 @@ impl TileType {
                     (x + off_x, y + off_y)
                 } else {
                     let origin = (width as i32 * layer_height) / 2;
-                    let x = x - origin;
-                    let rx = y / (height as i32) + x / (width as i32);
-                    let ry = y / (height as i32) - x / (width as i32);
-                    (rx, ry)
+                    let tile_x = (x - origin) as f32 / width as f32;
+                    let tile_y = y as f32 / height as f32;
+                    (
+                        (tile_y + tile_x).floor() as i32,
+                        (tile_y - tile_x).floor() as i32,
+                    )
                 }
             }
 
@@ -192,7 +199,7 @@ impl TileType {
             } => {
                 if stagger_y {
                     let col_w = width as i32;
-                    let row_h = (height as i32 - side_length as i32) / 2 + side_length as i32 - 1;
+                    let row_h = (height as i32 - side_length as i32) / 2 + side_length as i32;
                     let half_w = width as i32 / 2;
                     let half_h = height as i32 / 2;
 
@@ -261,6 +268,125 @@ impl TileType {
         }
     }
 
+    /// Get the tile coordinates of the tiles that share an edge with the tile at (x, y).
+    ///
+    /// Orthogonal and isometric tiles have 4 neighbors, hexagonal tiles have 6.
+    /// Use `neighbors8` to include the tiles that only share a corner.
+    /// Neighbors are returned regardless of the size of the layer, so they may lie outside of it.
+    pub fn neighbors(&self, x: i32, y: i32) -> SmallVec<[(i32, i32); 6]> {
+        match *self {
+            TileType::Ortho { .. } | TileType::Isometric { stagger: false, .. } => {
+                smallvec![(x, y - 1), (x + 1, y), (x, y + 1), (x - 1, y)]
+            }
+
+            TileType::Isometric {
+                stagger_odd,
+                stagger_y,
+                ..
+            } => {
+                if stagger_y {
+                    let dx = if (mod2(y, 2) == 1) == stagger_odd {
+                        0
+                    } else {
+                        -1
+                    };
+                    smallvec![
+                        (x + dx, y - 1),
+                        (x + dx + 1, y - 1),
+                        (x + dx + 1, y + 1),
+                        (x + dx, y + 1),
+                    ]
+                } else {
+                    let dy = if (mod2(x, 2) == 1) == stagger_odd {
+                        0
+                    } else {
+                        -1
+                    };
+                    smallvec![
+                        (x - 1, y + dy),
+                        (x + 1, y + dy),
+                        (x + 1, y + dy + 1),
+                        (x - 1, y + dy + 1),
+                    ]
+                }
+            }
+
+            TileType::Hexagonal {
+                stagger_odd,
+                stagger_y,
+                ..
+            } => {
+                if stagger_y {
+                    let dx = if (mod2(y, 2) == 1) == stagger_odd {
+                        0
+                    } else {
+                        -1
+                    };
+                    smallvec![
+                        (x + dx, y - 1),
+                        (x + dx + 1, y - 1),
+                        (x + 1, y),
+                        (x + dx + 1, y + 1),
+                        (x + dx, y + 1),
+                        (x - 1, y),
+                    ]
+                } else {
+                    let dy = if (mod2(x, 2) == 1) == stagger_odd {
+                        0
+                    } else {
+                        -1
+                    };
+                    smallvec![
+                        (x, y - 1),
+                        (x + 1, y + dy),
+                        (x + 1, y + dy + 1),
+                        (x, y + 1),
+                        (x - 1, y + dy + 1),
+                        (x - 1, y + dy),
+                    ]
+                }
+            }
+        }
+    }
+
+    /// Get the tile coordinates of the tiles that share an edge or a corner with the tile at (x, y),
+    ///  clockwise starting at the tile above it.
+    ///
+    /// Orthogonal and isometric tiles have 8 neighbors. Hexagonal tiles don't touch tiles at just a corner,
+    ///  so these are the same 6 neighbors as returned by `neighbors`.
+    /// Neighbors are returned regardless of the size of the layer, so they may lie outside of it.
+    pub fn neighbors8(&self, x: i32, y: i32) -> SmallVec<[(i32, i32); 8]> {
+        match *self {
+            TileType::Ortho { .. } | TileType::Isometric { stagger: false, .. } => smallvec![
+                (x, y - 1),
+                (x + 1, y - 1),
+                (x + 1, y),
+                (x + 1, y + 1),
+                (x, y + 1),
+                (x - 1, y + 1),
+                (x - 1, y),
+                (x - 1, y - 1),
+            ],
+
+            TileType::Isometric { stagger_y, .. } => {
+                // the edge neighbors are the top left, top right, bottom right and bottom left tiles,
+                //  the tiles that only share a corner are in between them
+                let edges = self.neighbors(x, y);
+                let corners = if stagger_y {
+                    [(x, y - 2), (x + 1, y), (x, y + 2), (x - 1, y)]
+                } else {
+                    [(x, y - 1), (x + 2, y), (x, y + 1), (x - 2, y)]
+                };
+                smallvec![
+                    corners[0], edges[1], corners[1], edges[2], corners[2], edges[3], corners[3],
+                    edges[0],
+                ]
+            }
+
+            TileType::Hexagonal { .. } => self.neighbors(x, y).into_iter().collect(),
+        }
+    }
+
     /// Get the tile width of this tile type.
     pub fn tile_width(&self) -> u32 {
         match *self {
@@ -270,6 +396,15 @@ impl TileType {
         }
     }
 
+    /// Get the order in which tiles are rendered.
+    pub fn render_order(&self) -> RenderOrder {
+        match *self {
+            TileType::Ortho { render_order, .. } => render_order,
+            TileType::Isometric { render_order, .. } => render_order,
+            TileType::Hexagonal { render_order, .. } => render_order,
+        }
+    }
+
     /// Get the tile height of this tile type.
     pub fn tile_height(&self) -> u32 {
         match *self {
@@ -290,9 +425,168 @@ fn mod2(x: i32, m: i32) -> i32 {
 }
 
 fn div2(x: i32, d: i32) -> i32 {
-    if x >= 0 {
-        x / d
-    } else {
-        x / d - 1
+    x.div_euclid(d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    #[test]
+    fn hexagonal_rows_are_spaced_by_the_tile_height() {
+        let map = parse(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="hexagonal" renderorder="right-down" width="2" height="3" tilewidth="32" tileheight="28" infinite="0" hexsidelength="14" staggeraxis="y" staggerindex="odd">
+</map>"#,
+        );
+        assert_eq!(map.tile_type.tile_width(), 32);
+        assert_eq!(map.tile_type.tile_height(), 28);
+
+        // rows overlap by half of the part of the tile that isn't the flat side
+        let row_height = (28 - 14) / 2 + 14;
+        let rows: Vec<(i32, i32)> = (0..3)
+            .map(|y| map.tile_type.coord_to_pos(3, 0, y))
+            .collect();
+        assert_eq!(rows, vec![(0, 0), (16, row_height), (0, 2 * row_height)]);
+    }
+
+    fn hexagonal(stagger_odd: bool, stagger_y: bool) -> TileType {
+        let (width, height) = if stagger_y { (32, 28) } else { (28, 32) };
+        TileType::Hexagonal {
+            width,
+            height,
+            stagger_odd,
+            stagger_y,
+            side_length: 14,
+            render_order: RenderOrder::RightDown,
+        }
+    }
+
+    fn sorted(mut coords: Vec<(i32, i32)>) -> Vec<(i32, i32)> {
+        coords.sort_unstable();
+        coords
+    }
+
+    #[test]
+    fn hexagonal_neighbors_on_odd_and_even_rows() {
+        // odd rows are shifted to the right
+        let tile_type = hexagonal(true, true);
+        assert_eq!(
+            sorted(tile_type.neighbors(2, 1).to_vec()),
+            sorted(vec![(2, 0), (3, 0), (1, 1), (3, 1), (2, 2), (3, 2)])
+        );
+        assert_eq!(
+            sorted(tile_type.neighbors(2, 2).to_vec()),
+            sorted(vec![(1, 1), (2, 1), (1, 2), (3, 2), (1, 3), (2, 3)])
+        );
+
+        // even rows are shifted to the right
+        let tile_type = hexagonal(false, true);
+        assert_eq!(
+            sorted(tile_type.neighbors(2, 1).to_vec()),
+            sorted(vec![(1, 0), (2, 0), (1, 1), (3, 1), (1, 2), (2, 2)])
+        );
+        assert_eq!(
+            sorted(tile_type.neighbors(2, 2).to_vec()),
+            sorted(vec![(2, 1), (3, 1), (1, 2), (3, 2), (2, 3), (3, 3)])
+        );
+    }
+
+    #[test]
+    fn hexagonal_neighbors_are_the_closest_tiles() {
+        for &(stagger_odd, stagger_y) in
+            &[(true, true), (false, true), (true, false), (false, false)]
+        {
+            let tile_type = hexagonal(stagger_odd, stagger_y);
+            let center = |(x, y)| {
+                let (px, py) = tile_type.coord_to_pos(0, x, y);
+                Vec2::new(px as f32, py as f32)
+            };
+            for y in -3..3 {
+                for x in -3..3 {
+                    // adjacent tiles are at most a tile width or height apart, all others are further away
+                    let expected: Vec<(i32, i32)> = (y - 2..=y + 2)
+                        .flat_map(|ny| (x - 2..=x + 2).map(move |nx| (nx, ny)))
+                        .filter(|&n| n != (x, y))
+                        .filter(|&n| center(n).distance(center((x, y))) <= 32.0)
+                        .collect();
+                    assert_eq!(
+                        sorted(tile_type.neighbors(x, y).to_vec()),
+                        sorted(expected),
+                        "neighbors of ({}, {}) in {:?}",
+                        x,
+                        y,
+                        tile_type
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn orthogonal_neighbors8_include_the_diagonals() {
+        let tile_type = TileType::Ortho {
+            width: 16,
+            height: 16,
+            render_order: RenderOrder::RightDown,
+        };
+        assert_eq!(
+            tile_type.neighbors8(2, 1).to_vec(),
+            vec![
+                (2, 0),
+                (3, 0),
+                (3, 1),
+                (3, 2),
+                (2, 2),
+                (1, 2),
+                (1, 1),
+                (1, 0)
+            ]
+        );
+        // the edge neighbors are every other diagonal neighbor
+        let edges: Vec<(i32, i32)> = tile_type.neighbors8(2, 1).into_iter().step_by(2).collect();
+        assert_eq!(edges, tile_type.neighbors(2, 1).to_vec());
+    }
+
+    #[test]
+    fn staggered_isometric_neighbors8_are_the_touching_tiles() {
+        for &(stagger_odd, stagger_y) in
+            &[(true, true), (false, true), (true, false), (false, false)]
+        {
+            let tile_type = TileType::Isometric {
+                width: 32,
+                height: 16,
+                stagger: true,
+                stagger_odd,
+                stagger_y,
+                render_order: RenderOrder::RightDown,
+            };
+            let center = |(x, y)| {
+                let (px, py) = tile_type.coord_to_pos(0, x, y);
+                Vec2::new(px as f32, py as f32)
+            };
+            for y in -3..3 {
+                for x in -3..3 {
+                    // two diamonds of the same size touch when their centers are within a diamond of twice the size
+                    let expected: Vec<(i32, i32)> = (y - 3..=y + 3)
+                        .flat_map(|ny| (x - 3..=x + 3).map(move |nx| (nx, ny)))
+                        .filter(|&n| n != (x, y))
+                        .filter(|&n| {
+                            let d = (center(n) - center((x, y))).abs();
+                            d.x / 32.0 + d.y / 16.0 <= 1.0
+                        })
+                        .collect();
+                    assert_eq!(
+                        sorted(tile_type.neighbors8(x, y).to_vec()),
+                        sorted(expected),
+                        "neighbors of ({}, {}) in {:?}",
+                        x,
+                        y,
+                        tile_type
+                    );
+                }
+            }
+        }
     }
 }
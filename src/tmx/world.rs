@@ -0,0 +1,34 @@
+use super::*;
+
+use anyhow::Result;
+use bevy_math::UVec2;
+
+/// A tiled world loaded from a .world file, which places multiple maps next to each other.
+#[cfg_attr(feature = "render", derive(TypeUuid))]
+#[cfg_attr(feature = "render", uuid = "5d2e8b71-c3f4-4a96-8e0d-7b1a6f3c9e52")]
+#[derive(Debug, Clone, Default)]
+pub struct World {
+    /// The maps in the world.
+    /// Maps that are matched by a pattern in the world file instead of being listed are not included.
+    pub maps: Vec<WorldMap>,
+    /// Whether the map editor only shows the maps next to the current map.
+    pub only_show_adjacent_maps: bool,
+}
+
+/// A map that is part of a `World`.
+#[derive(Debug, Clone)]
+pub struct WorldMap {
+    /// The file name of the map, relative to the directory of the world file.
+    pub file_name: String,
+    /// The position of the top left corner of the map within the world, measured in pixels.
+    pub position: IVec2,
+    /// The size of the map, measured in pixels.
+    pub size: UVec2,
+}
+
+impl World {
+    /// Parse a world from the contents of a .world file.
+    pub fn from_bytes(bytes: &[u8]) -> Result<World> {
+        World::load_from_json(bytes)
+    }
+}
@@ -0,0 +1,695 @@
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::*;
+use bevy_math::{IVec2, UVec2, Vec4};
+use xml::writer::{EmitterConfig, EventWriter, XmlEvent};
+
+use super::*;
+
+type Attributes = Vec<(&'static str, String)>;
+
+impl Map {
+    /// Write the map in the .tmx format.
+    ///
+    /// Not everything in a .tmx file is preserved when it's loaded, so the written map is not an exact copy of
+    ///  the original file:
+    /// - Tilesets are always embedded in the map, and objects created from templates are written as regular objects.
+    /// - Image sources are written as they were resolved during loading, relative to the asset directory.
+    ///   Embedded images can only be written if the map was not turned into a scene yet.
+    /// - Offsets, parallax factors and colors of group layers are written on the layers inside of the group.
+    /// - Anything that bevy_tmx doesn't parse, like wang sets, terrains and layer properties, is lost.
+    pub fn write_to_xml_writer<W: Write>(&self, w: W) -> Result<()> {
+        let mut w = EmitterConfig::new().perform_indent(true).create_writer(w);
+
+        let infinite = self.is_infinite();
+        let next_object_id = self.objects().map(|(_, o)| o.id).max().unwrap_or(0) + 1;
+
+        let mut attributes = vec![("version", "1.10".to_string())];
+        let (width, height, render_order) = match self.tile_type {
+            TileType::Ortho {
+                width,
+                height,
+                render_order,
+            } => {
+                attributes.push(("orientation", "orthogonal".to_string()));
+                (width, height, render_order)
+            }
+            TileType::Isometric {
+                width,
+                height,
+                stagger,
+                stagger_odd,
+                stagger_y,
+                render_order,
+            } => {
+                if stagger {
+                    attributes.push(("orientation", "staggered".to_string()));
+                    attributes.push(("staggeraxis", stagger_axis(stagger_y)));
+                    attributes.push(("staggerindex", stagger_index(stagger_odd)));
+                } else {
+                    attributes.push(("orientation", "isometric".to_string()));
+                }
+                (width, height, render_order)
+            }
+            TileType::Hexagonal {
+                width,
+                height,
+                stagger_odd,
+                stagger_y,
+                side_length,
+                render_order,
+            } => {
+                attributes.push(("orientation", "hexagonal".to_string()));
+                attributes.push(("hexsidelength", side_length.to_string()));
+                attributes.push(("staggeraxis", stagger_axis(stagger_y)));
+                attributes.push(("staggerindex", stagger_index(stagger_odd)));
+                (width, height, render_order)
+            }
+        };
+        attributes.push(("renderorder", self::render_order(render_order)));
+        attributes.push(("width", self.width.to_string()));
+        attributes.push(("height", self.height.to_string()));
+        attributes.push(("tilewidth", width.to_string()));
+        attributes.push(("tileheight", height.to_string()));
+        attributes.push(("infinite", if infinite { "1" } else { "0" }.to_string()));
+        if self.parallax_origin.x != 0.0 {
+            attributes.push(("parallaxoriginx", self.parallax_origin.x.to_string()));
+        }
+        if self.parallax_origin.y != 0.0 {
+            attributes.push(("parallaxoriginy", self.parallax_origin.y.to_string()));
+        }
+        if self.background[0] != 0 {
+            attributes.push(("backgroundcolor", color(self.background)));
+        }
+        attributes.push(("nextlayerid", (count_layers(&self.layers) + 1).to_string()));
+        attributes.push(("nextobjectid", next_object_id.to_string()));
+
+        start(&mut w, "map", attributes)?;
+        write_properties(&mut w, &self.properties)?;
+        for tileset in self.tilesets.iter() {
+            write_tileset(&mut w, tileset)?;
+        }
+        let mut next_layer_id = 1;
+        for layer in self.layers.iter() {
+            write_layer(&mut w, layer, &mut next_layer_id, infinite)?;
+        }
+        end(&mut w)
+    }
+
+    /// Maps are written as infinite maps if any of the tile layers doesn't match the size of the map.
+    fn is_infinite(&self) -> bool {
+        fn check(layers: &[Layer], size: UVec2) -> bool {
+            layers.iter().any(|layer| match layer {
+                Layer::TileLayer {
+                    position,
+                    size: layer_size,
+                    ..
+                } => *position != IVec2::ZERO || *layer_size != size,
+                Layer::Group { layers, .. } => check(layers.as_slice(), size),
+                _ => false,
+            })
+        }
+        self.infinite || check(self.layers.as_slice(), UVec2::new(self.width, self.height))
+    }
+}
+
+fn start<W: Write>(w: &mut EventWriter<W>, name: &str, attributes: Attributes) -> Result<()> {
+    let mut element = XmlEvent::start_element(name);
+    for (key, value) in attributes.iter() {
+        element = element.attr(*key, value.as_str());
+    }
+    w.write(element)?;
+    Ok(())
+}
+
+fn end<W: Write>(w: &mut EventWriter<W>) -> Result<()> {
+    w.write(XmlEvent::end_element())?;
+    Ok(())
+}
+
+fn empty<W: Write>(w: &mut EventWriter<W>, name: &str, attributes: Attributes) -> Result<()> {
+    start(w, name, attributes)?;
+    end(w)
+}
+
+fn text<W: Write>(w: &mut EventWriter<W>, text: &str) -> Result<()> {
+    w.write(XmlEvent::characters(text))?;
+    Ok(())
+}
+
+fn render_order(render_order: RenderOrder) -> String {
+    match render_order {
+        RenderOrder::RightDown => "right-down",
+        RenderOrder::RightUp => "right-up",
+        RenderOrder::LeftDown => "left-down",
+        RenderOrder::LeftUp => "left-up",
+    }
+    .to_string()
+}
+
+fn stagger_axis(stagger_y: bool) -> String {
+    if stagger_y { "y" } else { "x" }.to_string()
+}
+
+fn stagger_index(stagger_odd: bool) -> String {
+    if stagger_odd { "odd" } else { "even" }.to_string()
+}
+
+fn color([a, r, g, b]: [u8; 4]) -> String {
+    format!("#{:02x}{:02x}{:02x}{:02x}", a, r, g, b)
+}
+
+fn color_vec4(color: Vec4) -> String {
+    let channel = |c: f32| (c * 255.0).round() as u8;
+    self::color([
+        channel(color.w),
+        channel(color.x),
+        channel(color.y),
+        channel(color.z),
+    ])
+}
+
+fn count_layers(layers: &[Layer]) -> usize {
+    layers
+        .iter()
+        .map(|layer| match layer {
+            Layer::Group { layers, .. } => 1 + count_layers(layers.as_slice()),
+            _ => 1,
+        })
+        .sum()
+}
+
+fn write_properties<W: Write>(
+    w: &mut EventWriter<W>,
+    properties: &HashMap<String, Property>,
+) -> Result<()> {
+    // the tileset of template objects is tracked through a property, it's not a real property.
+    let properties = property::sorted_properties(properties);
+    if properties.is_empty() {
+        return Ok(());
+    }
+
+    start(w, "properties", vec![])?;
+    for (name, property) in properties {
+        let (ty, value) = match property {
+            Property::String(x) => ("string", x.clone()),
+            Property::Int(x) => ("int", x.to_string()),
+            Property::Float(x) => ("float", x.to_string()),
+            Property::Bool(x) => ("bool", x.to_string()),
+            Property::Color(x) => ("color", color(*x)),
+            Property::File(x) => ("file", x.clone()),
+            Property::Object(x) => ("object", x.to_string()),
+            Property::Class(members) => {
+                let attributes = vec![("name", name.to_string()), ("type", "class".to_string())];
+                start(w, "property", attributes)?;
+                write_properties(w, members)?;
+                end(w)?;
+                continue;
+            }
+        };
+
+        let mut attributes = vec![("name", name.to_string())];
+        if ty != "string" {
+            attributes.push(("type", ty.to_string()));
+        }
+        if value.contains('\n') {
+            // multiline strings are stored as text, like tiled does
+            start(w, "property", attributes)?;
+            text(w, value.as_str())?;
+            end(w)?;
+        } else {
+            attributes.push(("value", value));
+            empty(w, "property", attributes)?;
+        }
+    }
+    end(w)
+}
+
+fn write_image<W: Write>(w: &mut EventWriter<W>, image: &Texture) -> Result<()> {
+    let mut attributes = Vec::new();
+    if let Some(path) = image.path() {
+        attributes.push(("source", format!("{}", path.display())));
+    } else {
+        attributes.push(("format", "png".to_string()));
+    }
+    if let Some([r, g, b]) = image.transparent_color() {
+        attributes.push(("trans", format!("{:02x}{:02x}{:02x}", r, g, b)));
+    }
+    attributes.push(("width", image.width().to_string()));
+    attributes.push(("height", image.height().to_string()));
+
+    start(w, "image", attributes)?;
+    if image.path().is_none() {
+        start(w, "data", vec![("encoding", "base64".to_string())])?;
+        text(w, base64::encode(image.encode_png()?).as_str())?;
+        end(w)?;
+    }
+    end(w)
+}
+
+fn object_alignment(alignment: ObjectAlignment) -> String {
+    match alignment {
+        ObjectAlignment::Unspecified => "unspecified",
+        ObjectAlignment::TopLeft => "topleft",
+        ObjectAlignment::Top => "top",
+        ObjectAlignment::TopRight => "topright",
+        ObjectAlignment::Left => "left",
+        ObjectAlignment::Center => "center",
+        ObjectAlignment::Right => "right",
+        ObjectAlignment::BottomLeft => "bottomleft",
+        ObjectAlignment::Bottom => "bottom",
+        ObjectAlignment::BottomRight => "bottomright",
+    }
+    .to_string()
+}
+
+fn write_tile_offset_and_grid<W: Write>(w: &mut EventWriter<W>, tileset: &Tileset) -> Result<()> {
+    let offset = tileset.tile_offset;
+    if offset != IVec2::ZERO {
+        let attributes = vec![("x", offset.x.to_string()), ("y", offset.y.to_string())];
+        empty(w, "tileoffset", attributes)?;
+    }
+    if tileset.grid_orientation != GridOrientation::Orthogonal
+        || tileset.grid_size != tileset.tile_size
+    {
+        let orientation = match tileset.grid_orientation {
+            GridOrientation::Orthogonal => "orthogonal",
+            GridOrientation::Isometric => "isometric",
+        };
+        let attributes = vec![
+            ("orientation", orientation.to_string()),
+            ("width", tileset.grid_size.x.to_string()),
+            ("height", tileset.grid_size.y.to_string()),
+        ];
+        empty(w, "grid", attributes)?;
+    }
+    Ok(())
+}
+
+fn write_tileset<W: Write>(w: &mut EventWriter<W>, tileset: &Tileset) -> Result<()> {
+    let name = match tileset.source.strip_prefix("embedded#") {
+        Some(name) => name.to_string(),
+        None => Path::new(tileset.source.as_str())
+            .file_stem()
+            .map_or(String::new(), |stem| stem.to_string_lossy().to_string()),
+    };
+    let tile_width = tileset.tile_size.x as i32;
+    let tile_height = tileset.tile_size.y as i32;
+
+    let mut attributes = vec![
+        ("firstgid", tileset.first_gid.to_string()),
+        ("name", name),
+        ("tilewidth", tile_width.to_string()),
+        ("tileheight", tile_height.to_string()),
+    ];
+    if tileset.object_alignment != ObjectAlignment::Unspecified {
+        attributes.push((
+            "objectalignment",
+            object_alignment(tileset.object_alignment),
+        ));
+    }
+    if tileset.fill_mode == FillMode::PreserveAspectFit {
+        attributes.push(("fillmode", "preserve-aspect-fit".to_string()));
+    }
+
+    if let Some(image) = tileset.image.as_ref() {
+        // the layout of the tileset image is reconstructed from the pixel coordinates of the tiles.
+        let size = Vec2::new(image.width() as f32, image.height() as f32);
+        let corners: Vec<IVec2> = tileset
+            .tiles
+            .iter()
+            .map(|tile| {
+                let corner = tile
+                    .as_ref()
+                    .map_or(Vec2::ZERO, |tile| tile.top_left * size);
+                IVec2::new(corner.x.round() as i32, corner.y.round() as i32)
+            })
+            .collect();
+        let first = corners.first().copied().unwrap_or(IVec2::ZERO);
+        let columns = corners.iter().take_while(|c| c.y == first.y).count();
+        let spacing = if columns > 1 {
+            corners[1].x - first.x - tile_width
+        } else if corners.len() > columns {
+            corners[columns].y - first.y - tile_height
+        } else {
+            0
+        };
+
+        if spacing != 0 {
+            attributes.push(("spacing", spacing.to_string()));
+        }
+        if first.x != 0 {
+            attributes.push(("margin", first.x.to_string()));
+        }
+        attributes.push(("tilecount", tileset.tiles.len().to_string()));
+        attributes.push(("columns", columns.to_string()));
+        start(w, "tileset", attributes)?;
+        write_tile_offset_and_grid(w, tileset)?;
+        write_image(w, image)?;
+    } else {
+        let tile_count = tileset.tiles.iter().flatten().count();
+        attributes.push(("tilecount", tile_count.to_string()));
+        attributes.push(("columns", "0".to_string()));
+        start(w, "tileset", attributes)?;
+        write_tile_offset_and_grid(w, tileset)?;
+    }
+
+    for (id, tile) in tileset.tiles.iter().enumerate() {
+        if let Some(tile) = tile.as_ref() {
+            let own_image = match (tile.image.as_ref(), tileset.image.as_ref()) {
+                (Some(image), Some(tileset_image)) => image.label() != tileset_image.label(),
+                (image, _) => image.is_some(),
+            };
+            if !own_image
+                && tile.ty.is_empty()
+                && tile.probability == 1.0
+                && tile.properties.is_empty()
+                && tile.animation.is_empty()
+                && tile.object_group.is_empty()
+            {
+                continue;
+            }
+
+            let mut attributes = vec![("id", id.to_string())];
+            if !tile.ty.is_empty() {
+                attributes.push(("type", tile.ty.clone()));
+            }
+            if tile.probability != 1.0 {
+                attributes.push(("probability", tile.probability.to_string()));
+            }
+            if let (true, Some(image)) = (own_image, tile.image.as_ref()) {
+                let image_size = Vec2::new(image.width() as f32, image.height() as f32);
+                let position = (tile.top_left * image_size).round();
+                if position != Vec2::ZERO || tile.bottom_right != Vec2::ONE {
+                    attributes.push(("x", position.x.to_string()));
+                    attributes.push(("y", position.y.to_string()));
+                    attributes.push(("width", tile.width.to_string()));
+                    attributes.push(("height", tile.height.to_string()));
+                }
+            }
+            start(w, "tile", attributes)?;
+            write_properties(w, &tile.properties)?;
+            if let (true, Some(image)) = (own_image, tile.image.as_ref()) {
+                write_image(w, image)?;
+            }
+            if !tile.object_group.is_empty() {
+                start(w, "objectgroup", vec![("draworder", "index".to_string())])?;
+                for object in tile.object_group.iter() {
+                    write_object(w, object)?;
+                }
+                end(w)?;
+            }
+            if !tile.animation.is_empty() {
+                start(w, "animation", vec![])?;
+                for frame in tile.animation.iter() {
+                    let attributes = vec![
+                        ("tileid", frame.tile.to_string()),
+                        ("duration", frame.duration.to_string()),
+                    ];
+                    empty(w, "frame", attributes)?;
+                }
+                end(w)?;
+            }
+            end(w)?;
+        }
+    }
+
+    end(w)
+}
+
+fn layer_attributes(
+    id: u32,
+    name: &str,
+    offset: IVec2,
+    parallax: Vec2,
+    color: Vec4,
+    opacity: f32,
+    visible: bool,
+) -> Attributes {
+    let mut attributes = vec![("id", id.to_string()), ("name", name.to_string())];
+    if !visible {
+        attributes.push(("visible", "0".to_string()));
+    }
+    if opacity != 1.0 {
+        attributes.push(("opacity", opacity.to_string()));
+    }
+    if color != Vec4::ONE {
+        attributes.push(("tintcolor", color_vec4(color)));
+    }
+    if offset.x != 0 {
+        attributes.push(("offsetx", offset.x.to_string()));
+    }
+    if offset.y != 0 {
+        attributes.push(("offsety", offset.y.to_string()));
+    }
+    if parallax.x != 1.0 {
+        attributes.push(("parallaxx", parallax.x.to_string()));
+    }
+    if parallax.y != 1.0 {
+        attributes.push(("parallaxy", parallax.y.to_string()));
+    }
+    attributes
+}
+
+fn write_layer<W: Write>(
+    w: &mut EventWriter<W>,
+    layer: &Layer,
+    next_id: &mut u32,
+    infinite: bool,
+) -> Result<()> {
+    let id = *next_id;
+    *next_id += 1;
+
+    match layer {
+        Layer::TileLayer {
+            name,
+            size,
+            position,
+            offset,
+            parallax,
+            color,
+            opacity,
+            visible,
+            data,
+        } => {
+            let mut attributes =
+                layer_attributes(id, name, *offset, *parallax, *color, *opacity, *visible);
+            attributes.push(("width", size.x.to_string()));
+            attributes.push(("height", size.y.to_string()));
+            start(w, "layer", attributes)?;
+
+            let csv = data
+                .chunks(size.x.max(1) as usize)
+                .map(|row| {
+                    row.iter()
+                        .map(|gid| gid.to_string())
+                        .collect::<Vec<_>>()
+                        .join(",")
+                })
+                .collect::<Vec<_>>()
+                .join(",\n");
+            let csv = format!("\n{}\n", csv);
+
+            start(w, "data", vec![("encoding", "csv".to_string())])?;
+            if infinite {
+                let attributes = vec![
+                    ("x", position.x.to_string()),
+                    ("y", position.y.to_string()),
+                    ("width", size.x.to_string()),
+                    ("height", size.y.to_string()),
+                ];
+                start(w, "chunk", attributes)?;
+                text(w, csv.as_str())?;
+                end(w)?;
+            } else {
+                text(w, csv.as_str())?;
+            }
+            end(w)?;
+            end(w)
+        }
+
+        Layer::ObjectLayer {
+            name,
+            draworder_index,
+            objects,
+            offset,
+            parallax,
+            color,
+            opacity,
+            visible,
+            object_color,
+        } => {
+            let mut attributes =
+                layer_attributes(id, name, *offset, *parallax, *color, *opacity, *visible);
+            if *object_color != Layer::DEFAULT_OBJECT_COLOR {
+                attributes.push(("color", color_vec4(*object_color)));
+            }
+            if *draworder_index {
+                attributes.push(("draworder", "index".to_string()));
+            }
+            start(w, "objectgroup", attributes)?;
+            for object in objects.iter() {
+                write_object(w, object)?;
+            }
+            end(w)
+        }
+
+        Layer::ImageLayer {
+            name,
+            image,
+            offset,
+            parallax,
+            color,
+            opacity,
+            visible,
+            repeat_x,
+            repeat_y,
+        } => {
+            let mut attributes =
+                layer_attributes(id, name, *offset, *parallax, *color, *opacity, *visible);
+            if *repeat_x {
+                attributes.push(("repeatx", "1".to_string()));
+            }
+            if *repeat_y {
+                attributes.push(("repeaty", "1".to_string()));
+            }
+            start(w, "imagelayer", attributes)?;
+            write_image(w, image)?;
+            end(w)
+        }
+
+        Layer::Group {
+            name,
+            offset,
+            layers,
+        } => {
+            // parallax, color and opacity are written on the grouped layers, where they were applied
+            let attributes = layer_attributes(id, name, *offset, Vec2::ONE, Vec4::ONE, 1.0, true);
+            start(w, "group", attributes)?;
+            for layer in layers.iter() {
+                write_layer(w, layer, next_id, infinite)?;
+            }
+            end(w)
+        }
+    }
+}
+
+fn write_object<W: Write>(w: &mut EventWriter<W>, object: &Object) -> Result<()> {
+    let mut attributes = vec![("id", object.id.to_string())];
+    if !object.name.is_empty() {
+        attributes.push(("name", object.name.clone()));
+    }
+    if !object.ty.is_empty() {
+        attributes.push(("type", object.ty.clone()));
+    }
+    if let Some(gid) = object.tile() {
+        attributes.push(("gid", gid.to_string()));
+    }
+    attributes.push(("x", object.x.to_string()));
+    attributes.push(("y", object.y.to_string()));
+    if object.width != 0.0 {
+        attributes.push(("width", object.width.to_string()));
+    }
+    if object.height != 0.0 {
+        attributes.push(("height", object.height.to_string()));
+    }
+    if object.rotation != 0.0 {
+        attributes.push(("rotation", object.rotation.to_string()));
+    }
+    if !object.visible {
+        attributes.push(("visible", "0".to_string()));
+    }
+
+    start(w, "object", attributes)?;
+    write_properties(w, &object.properties)?;
+    match &object.kind {
+        ObjectKind::Rectangle | ObjectKind::Tile(_) => (),
+        ObjectKind::Ellipse => empty(w, "ellipse", vec![])?,
+        ObjectKind::Point => empty(w, "point", vec![])?,
+        ObjectKind::Polygon(points) | ObjectKind::Polyline(points) => {
+            let points = points
+                .iter()
+                .map(|p| format!("{},{}", p.x, p.y))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let name = if matches!(object.kind, ObjectKind::Polygon(_)) {
+                "polygon"
+            } else {
+                "polyline"
+            };
+            empty(w, name, vec![("points", points)])?;
+        }
+        ObjectKind::Text(data) => write_text(w, data)?,
+    }
+    end(w)
+}
+
+fn write_text<W: Write>(w: &mut EventWriter<W>, data: &TextData) -> Result<()> {
+    let mut attributes = Vec::new();
+    if data.font_family != "sans-serif" {
+        attributes.push(("fontfamily", data.font_family.clone()));
+    }
+    if data.pixel_size != 16 {
+        attributes.push(("pixelsize", data.pixel_size.to_string()));
+    }
+    if data.wrap {
+        attributes.push(("wrap", "1".to_string()));
+    }
+    if data.color != [255, 0, 0, 0] {
+        attributes.push(("color", color(data.color)));
+    }
+    match data.halign {
+        HorizontalAlignment::Left => (),
+        HorizontalAlignment::Center => attributes.push(("halign", "center".to_string())),
+        HorizontalAlignment::Right => attributes.push(("halign", "right".to_string())),
+        HorizontalAlignment::Justify => attributes.push(("halign", "justify".to_string())),
+    }
+    match data.valign {
+        VerticalAlignment::Top => (),
+        VerticalAlignment::Center => attributes.push(("valign", "center".to_string())),
+        VerticalAlignment::Bottom => attributes.push(("valign", "bottom".to_string())),
+    }
+
+    start(w, "text", attributes)?;
+    text(w, data.text.as_str())?;
+    end(w)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{describe_layer, fixtures, parse};
+
+    #[test]
+    fn written_maps_parse_to_the_same_map() {
+        let source = std::fs::read_to_string(fixtures().join("equivalent.tmx")).unwrap();
+        let map = parse(&source);
+        let mut written = Vec::new();
+        map.write_to_xml_writer(&mut written).unwrap();
+        let reparsed = parse(std::str::from_utf8(&written).unwrap());
+
+        assert_eq!((map.width, map.height), (reparsed.width, reparsed.height));
+        assert_eq!(map.infinite, reparsed.infinite);
+        assert_eq!(
+            format!("{:?}", map.tile_type),
+            format!("{:?}", reparsed.tile_type)
+        );
+        assert_eq!(map.background, reparsed.background);
+        assert_eq!(
+            format!("{:?}", map.properties_sorted()),
+            format!("{:?}", reparsed.properties_sorted())
+        );
+
+        assert_eq!(map.tilesets.len(), reparsed.tilesets.len());
+        for (tileset, reparsed) in map.tilesets.iter().zip(reparsed.tilesets.iter()) {
+            assert_eq!(tileset.first_gid, reparsed.first_gid);
+            assert_eq!(tileset.tiles.len(), reparsed.tiles.len());
+            assert_eq!(tileset.tile_size, reparsed.tile_size);
+            assert_eq!(tileset.columns, reparsed.columns);
+        }
+
+        let layers = |map: &Map| map.layers.iter().map(describe_layer).collect::<Vec<_>>();
+        assert_eq!(layers(&map), layers(&reparsed));
+    }
+}
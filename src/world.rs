@@ -0,0 +1,296 @@
+use std::ops::DerefMut;
+
+use bevy_app::EventWriter;
+use bevy_asset::{AssetServer, Handle};
+use bevy_ecs::{
+    entity::Entity,
+    query::{Added, Changed, Or, With},
+    reflect::ReflectComponent,
+    system::{Commands, Query, Res, ResMut},
+};
+use bevy_math::Vec2;
+use bevy_reflect::{Reflect, TypeUuid};
+use bevy_render::camera::Camera;
+use bevy_scene::{InstanceId, Scene, SceneSpawner};
+use bevy_transform::{
+    components::{Children, GlobalTransform, Transform},
+    hierarchy::DespawnRecursiveExt,
+};
+
+use crate::parallax::ParallaxCamera;
+
+/// Component for entities that spawn the scene of a map as their children.
+/// Loading a .world file results in a scene with an entity with this component for every map in the world,
+///  positioned at the offset of the map within the world. The map_scene_system spawns the scene once it's loaded.
+#[derive(Debug, Default, Clone, TypeUuid, Reflect)]
+#[reflect(Component)]
+#[uuid = "9b4f2c6e-1a7d-4e38-b5c0-f3d82e6a9174"]
+pub struct MapScene(pub Handle<Scene>);
+
+/// Settings for streaming the maps of worlds, see `TmxPlugin::world_streaming`.
+/// With streaming enabled, maps of a world are only loaded while the camera is near them.
+#[derive(Debug, Clone, Copy)]
+pub struct WorldStreaming {
+    /// The distance from the camera to the bounds of a map within which the map is loaded, in world units.
+    /// Maps that are further away are unloaded.
+    pub load_radius: f32,
+}
+
+/// Component for the entities of maps in a world that is loaded with streaming enabled.
+/// The world_streaming_system spawns the map as children of the entity when the camera comes near,
+///  and despawns them again when the camera moves away.
+#[derive(Debug, Default, Clone, TypeUuid, Reflect)]
+#[reflect(Component)]
+#[uuid = "e3a7c5d1-6f28-4b90-8d4e-2b7f1c9a0e63"]
+pub struct StreamedMap {
+    /// The asset path of the map.
+    pub path: String,
+    /// The size of the map in world units. The entity is at the top left corner of the map,
+    ///  so with a flipped y axis the height is negative.
+    pub size: Vec2,
+    scene: Handle<Scene>,
+    #[reflect(ignore)]
+    instance: Option<InstanceId>,
+    loaded: bool,
+}
+
+/// Event sent by the world_streaming_system when it loads or unloads the map of a `StreamedMap` entity.
+/// Loaded maps are spawned as children of the entity once the map asset has finished loading.
+#[derive(Debug, Clone)]
+pub struct WorldStreamingEvent {
+    /// The entity with the `StreamedMap` component.
+    pub entity: Entity,
+    /// The asset path of the map.
+    pub path: String,
+    /// Whether the map was loaded (true) or unloaded (false).
+    pub loaded: bool,
+}
+
+/// System that spawns the scenes of newly added `MapScene` components as children of their entity.
+pub fn map_scene_system(
+    mut spawner: ResMut<SceneSpawner>,
+    scenes: Query<(Entity, &MapScene), Added<MapScene>>,
+) {
+    for (entity, scene) in scenes.iter() {
+        spawner.spawn_as_child(scene.0.clone(), entity);
+    }
+}
+
+/// Filter for the entities of maps in a world that had a map attached to them.
+type AttachedMapFilter = (Changed<Children>, Or<(With<MapScene>, With<StreamedMap>)>);
+
+/// System that marks the `Transform` of `MapScene` and `StreamedMap` entities as changed when the map is attached to them.
+/// The map is spawned before it's attached, so otherwise its `GlobalTransform` would not include the offset of the map.
+pub fn map_scene_transform_system(mut transforms: Query<&mut Transform, AttachedMapFilter>) {
+    for mut transform in transforms.iter_mut() {
+        transform.deref_mut();
+    }
+}
+
+impl StreamedMap {
+    /// Construct a new `StreamedMap` for the map at the asset path `path`, which is not loaded yet.
+    pub fn new(path: impl Into<String>, size: Vec2) -> Self {
+        Self {
+            path: path.into(),
+            size,
+            scene: Handle::default(),
+            instance: None,
+            loaded: false,
+        }
+    }
+
+    /// Whether the map is currently spawned, or will be spawned once it's loaded.
+    pub fn is_loaded(&self) -> bool {
+        self.loaded
+    }
+}
+
+/// System that loads and unloads the maps of `StreamedMap` entities depending on their distance to the camera.
+#[allow(clippy::too_many_arguments)]
+pub fn world_streaming_system(
+    mut commands: Commands,
+    streaming: Res<WorldStreaming>,
+    server: Res<AssetServer>,
+    mut spawner: ResMut<SceneSpawner>,
+    mut events: EventWriter<WorldStreamingEvent>,
+    cameras: Query<(&GlobalTransform, &Camera, Option<&ParallaxCamera>)>,
+    mut maps: Query<(
+        Entity,
+        &GlobalTransform,
+        &mut StreamedMap,
+        Option<&Children>,
+    )>,
+) {
+    let camera = cameras
+        .iter()
+        .find(|(_, _, marker)| marker.is_some())
+        .or_else(|| cameras.iter().next());
+    let camera = match camera {
+        Some((transform, _, _)) => transform.translation.truncate(),
+        None => return,
+    };
+
+    for (entity, transform, mut map, children) in maps.iter_mut() {
+        let corner = transform.translation.truncate();
+        let min = corner.min(corner + map.size);
+        let max = corner.max(corner + map.size);
+        let distance = (camera.max(min).min(max) - camera).length();
+
+        let in_range = distance <= streaming.load_radius;
+        if in_range && !map.loaded {
+            map.scene = server.load(map.path.as_str());
+            map.loaded = true;
+            // a map that is still waiting to be spawned from an earlier visit is spawned only once
+            let pending =
+                matches!(map.instance, Some(instance) if !spawner.instance_is_ready(instance));
+            if !pending {
+                map.instance = Some(spawner.spawn_as_child(map.scene.clone(), entity));
+            }
+            events.send(WorldStreamingEvent {
+                entity,
+                path: map.path.clone(),
+                loaded: true,
+            });
+        } else if !in_range && map.loaded {
+            map.scene = Handle::default();
+            map.loaded = false;
+            events.send(WorldStreamingEvent {
+                entity,
+                path: map.path.clone(),
+                loaded: false,
+            });
+        }
+
+        // also catches maps that finish loading after they were unloaded
+        if !map.loaded {
+            for &child in children.iter().flat_map(|children| children.iter()) {
+                commands.entity(child).despawn_recursive();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::{App, Events, ManualEventReader};
+
+    use super::*;
+    use crate::scene::MapRoot;
+    use crate::test_util::{app, spawn};
+    use crate::TmxPlugin;
+
+    /// Update the app until `count` maps are spawned, returning the global translations of their roots.
+    fn map_roots(app: &mut App, count: usize) -> Vec<Vec2> {
+        let mut roots = app
+            .world
+            .query_filtered::<&GlobalTransform, With<MapRoot>>();
+        for _ in 0..1000 {
+            app.update();
+            if roots.iter(&app.world).count() == count {
+                // one more update, so the transforms are propagated to the spawned maps
+                app.update();
+                let mut translations: Vec<Vec2> = roots
+                    .iter(&app.world)
+                    .map(|transform| transform.translation.truncate())
+                    .collect();
+                translations.sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap());
+                return translations;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        panic!("timed out waiting for {} maps", count);
+    }
+
+    #[test]
+    fn the_maps_of_a_world_are_spawned_at_their_offsets() {
+        let mut app = app(TmxPlugin::default().scale(Vec2::new(2.0, -2.0)));
+        spawn(&mut app, "two.world");
+
+        let mut scenes = app.world.query::<(&MapScene, &Transform)>();
+        assert_eq!(scenes.iter(&app.world).count(), 2);
+        assert_eq!(
+            map_roots(&mut app, 2),
+            vec![Vec2::new(0.0, 0.0), Vec2::new(128.0, -64.0)]
+        );
+    }
+
+    fn move_camera(app: &mut App, camera: Entity, position: Vec2) {
+        app.world
+            .get_mut::<GlobalTransform>(camera)
+            .unwrap()
+            .translation = position.extend(0.0);
+        app.update();
+    }
+
+    /// The maps that were loaded (true) or unloaded (false) since the last call.
+    fn streaming_events(
+        app: &App,
+        reader: &mut ManualEventReader<WorldStreamingEvent>,
+    ) -> Vec<(String, bool)> {
+        let events = app
+            .world
+            .get_resource::<Events<WorldStreamingEvent>>()
+            .unwrap();
+        reader
+            .iter(events)
+            .map(|event| (event.path.clone(), event.loaded))
+            .collect()
+    }
+
+    #[test]
+    fn streamed_maps_follow_the_camera() {
+        let mut app = app(TmxPlugin::default()
+            .scale(Vec2::new(2.0, -2.0))
+            .world_streaming(WorldStreaming { load_radius: 10.0 }));
+        let camera = app
+            .world
+            .spawn()
+            .insert_bundle((
+                GlobalTransform::from_xyz(-500.0, 500.0, 0.0),
+                Camera::default(),
+            ))
+            .id();
+        let mut reader = app
+            .world
+            .get_resource::<Events<WorldStreamingEvent>>()
+            .unwrap()
+            .get_reader();
+        spawn(&mut app, "two.world");
+
+        // both maps are out of range
+        assert_eq!(streaming_events(&app, &mut reader), vec![]);
+        let mut maps = app.world.query::<&StreamedMap>();
+        assert_eq!(
+            maps.iter(&app.world).filter(|map| map.is_loaded()).count(),
+            0
+        );
+
+        // the first map covers (0, 0) to (128, -128), the second one (128, -64) to (256, -128)
+        move_camera(&mut app, camera, Vec2::new(32.0, -32.0));
+        assert_eq!(
+            streaming_events(&app, &mut reader),
+            vec![("objects.tmx".to_string(), true)]
+        );
+        assert_eq!(map_roots(&mut app, 1), vec![Vec2::new(0.0, 0.0)]);
+
+        move_camera(&mut app, camera, Vec2::new(200.0, -100.0));
+        let mut events = streaming_events(&app, &mut reader);
+        events.sort();
+        assert_eq!(
+            events,
+            vec![
+                ("objects.tmx".to_string(), false),
+                ("sparse.tmx".to_string(), true)
+            ]
+        );
+        assert_eq!(map_roots(&mut app, 1), vec![Vec2::new(128.0, -64.0)]);
+
+        // near the edge both maps are in range
+        move_camera(&mut app, camera, Vec2::new(132.0, -100.0));
+        assert_eq!(
+            streaming_events(&app, &mut reader),
+            vec![("objects.tmx".to_string(), true)]
+        );
+        assert_eq!(map_roots(&mut app, 2).len(), 2);
+    }
+}
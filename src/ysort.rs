@@ -0,0 +1,111 @@
+use bevy_ecs::{reflect::ReflectComponent, system::Query};
+use bevy_reflect::{Reflect, TypeUuid};
+use bevy_transform::components::Transform;
+
+/// Component for entities that should be drawn in front of or behind the objects in their layer depending on their y coordinate,
+///  like a character walking between trees.
+/// The ysort_system sets the z coordinate of the `Transform` every frame, within the depth range of the layer that the
+///  entity belongs to. Entities further down the map are drawn in front, just like objects with the topdown draw order.
+/// Tile objects in layers with the topdown draw order have this component unless disabled with `TmxPlugin::y_sort`.
+#[derive(Debug, Default, Clone, Copy, TypeUuid, Reflect)]
+#[reflect(Component)]
+#[uuid = "e86c1a4f-5b37-4d02-9f8e-13a7c6d0b295"]
+pub struct YSort {
+    /// Offset added to the y coordinate before sorting, for example to sort a sprite by its feet instead of its origin.
+    pub bias: f32,
+    /// The depth of the layer, which is the z coordinate of entities at the top of the map.
+    pub depth: f32,
+    /// The depth range of the layer. Entities at the bottom of the map are drawn at `depth + depth_range`.
+    pub depth_range: f32,
+    /// The y coordinate of the top of the map, relative to the parent of the entity.
+    pub top: f32,
+    /// The height of the map, measured in the same direction as the y axis of the map.
+    pub height: f32,
+}
+
+/// System that updates the depth of `YSort` entities from their y coordinate.
+pub fn ysort_system(mut entities: Query<(&mut Transform, &YSort)>) {
+    for (mut transform, sort) in entities.iter_mut() {
+        let z = sort.z(transform.translation.y);
+        if transform.translation.z != z {
+            transform.translation.z = z;
+        }
+    }
+}
+
+impl YSort {
+    /// Construct a new `YSort` with a bias of zero.
+    pub fn new(depth: f32, depth_range: f32, top: f32, height: f32) -> Self {
+        Self {
+            bias: 0.0,
+            depth,
+            depth_range,
+            top,
+            height,
+        }
+    }
+
+    /// Set the offset that is added to the y coordinate before sorting.
+    pub fn with_bias(mut self, bias: f32) -> Self {
+        self.bias = bias;
+        self
+    }
+
+    /// The z coordinate for an entity at the y coordinate `y`.
+    /// Entities outside of the map are clamped to the depth range of the layer.
+    pub fn z(&self, y: f32) -> f32 {
+        let fraction = if self.height != 0.0 {
+            ((y + self.bias - self.top) / self.height).clamp(0.0, 1.0 - f32::EPSILON)
+        } else {
+            0.0
+        };
+        self.depth + fraction * self.depth_range
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::{
+        schedule::{Stage, SystemStage},
+        system::IntoSystem,
+        world::World,
+    };
+
+    use super::*;
+
+    fn run(world: &mut World) {
+        SystemStage::parallel()
+            .with_system(ysort_system.system())
+            .run(world);
+    }
+
+    #[test]
+    fn moving_down_the_map_moves_in_front_of_a_neighbor() {
+        let mut world = World::new();
+        // a layer at depth 1 of a map that is 64 units tall, with the y axis pointing up
+        let sort = YSort::new(1.0, 0.5, 0.0, -64.0);
+        let tree = world
+            .spawn()
+            .insert_bundle((Transform::from_xyz(0.0, -40.0, 0.0), sort))
+            .id();
+        let player = world
+            .spawn()
+            .insert_bundle((Transform::from_xyz(0.0, -20.0, 0.0), sort))
+            .id();
+        let z = |world: &World, entity| world.get::<Transform>(entity).unwrap().translation.z;
+
+        run(&mut world);
+        assert!(z(&world, player) < z(&world, tree));
+        assert!((z(&world, tree) - 1.3125).abs() < 1e-6);
+
+        world.get_mut::<Transform>(player).unwrap().translation.y = -60.0;
+        run(&mut world);
+        assert!(z(&world, player) > z(&world, tree));
+
+        // the bias sorts the player by its feet, which are further down
+        world.get_mut::<Transform>(player).unwrap().translation.y = -30.0;
+        world.get_mut::<YSort>(player).unwrap().bias = -20.0;
+        run(&mut world);
+        assert!(z(&world, player) > z(&world, tree));
+    }
+}
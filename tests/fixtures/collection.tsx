@@ -0,0 +1,10 @@
+<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.10" name="collection" tilewidth="16" tileheight="24" tilecount="2" columns="0">
+ <grid orientation="orthogonal" width="1" height="1"/>
+ <tile id="0">
+  <image source="red.png" width="8" height="8"/>
+ </tile>
+ <tile id="3">
+  <image source="blue.png"/>
+ </tile>
+</tileset>
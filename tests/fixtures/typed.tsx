@@ -0,0 +1,11 @@
+<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.10" name="typed" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+ <image source="tiles.png" width="32" height="32"/>
+ <tile id="0" type="wall"/>
+ <tile id="1" class="water"/>
+ <tile id="3" type="door">
+  <properties>
+   <property name="locked" type="bool" value="true"/>
+  </properties>
+ </tile>
+</tileset>